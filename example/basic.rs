@@ -20,7 +20,7 @@ fn main() {
         ..Default::default()
     };
 
-    let mut db = VecBase::new(config);
+    let db = VecBase::new(config);
 
     // Insert some vectors
     let records = vec![
@@ -32,7 +32,7 @@ fn main() {
     ];
 
     for (id, v) in &records {
-        db.insert(id.to_string(), v.clone(), Some(format!("label:{}", id)))
+        db.insert(id.to_string(), v.clone(), Some(vcore::metadata! { "label" => *id }))
             .expect("insert failed");
     }
 
@@ -0,0 +1,85 @@
+// VecBase — batch_bench.rs
+// Criterion benchmark for parallel batch search.
+// Author: d65v <https://github.com/d65v>
+//
+// Run with:
+//   cargo bench --bench batch_bench
+//
+// Benchmarks (N=20000, D=128, 1000 queries, top-10):
+//   - batch_search/sequential   (db.search in a loop)
+//   - batch_search/threads/1
+//   - batch_search/threads/2
+//   - batch_search/threads/4
+//   - batch_search/threads/8
+//   - batch_search/global       (rayon global pool, one thread per core)
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use vcore::processing::{batch_search, batch_search_in, search_pool};
+use vcore::{VecBase, VecBaseConfig};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Same LCG as `search_bench.rs`, so the data sets match.
+fn gen_vec(seed: u64, dim: usize) -> Vec<f32> {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    (0..dim)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) as f32) / (u32::MAX as f32) * 2.0 - 1.0
+        })
+        .collect()
+}
+
+fn build_db(n: usize, dim: usize) -> VecBase {
+    let db = VecBase::new(VecBaseConfig {
+        dim,
+        max_elements: n + 64,
+        ..VecBaseConfig::default()
+    });
+    for i in 0..n {
+        db.insert(format!("v{}", i), gen_vec(i as u64, dim), None).unwrap();
+    }
+    db
+}
+
+// ── Batch Search ──────────────────────────────────────────────────────────────
+
+fn bench_batch_search(c: &mut Criterion) {
+    const DIM: usize = 128;
+    const N: usize = 20_000;
+    const QUERIES: usize = 1_000;
+    const TOP_K: usize = 10;
+
+    let db = build_db(N, DIM);
+    let queries: Vec<Vec<f32>> = (0..QUERIES)
+        .map(|i| gen_vec(1_000_000 + i as u64, DIM))
+        .collect();
+
+    let mut group = c.benchmark_group("batch_search");
+    group.throughput(Throughput::Elements(QUERIES as u64));
+    group.sample_size(10);
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            let out: Vec<_> = queries.iter().map(|q| db.search(q, TOP_K)).collect();
+            black_box(out)
+        });
+    });
+
+    for threads in [1usize, 2, 4, 8] {
+        let pool = search_pool(threads).unwrap();
+        group.bench_with_input(BenchmarkId::new("threads", threads), &threads, |b, _| {
+            b.iter(|| black_box(batch_search_in(&pool, &db, &queries, TOP_K)));
+        });
+    }
+
+    group.bench_function("global", |b| {
+        b.iter(|| black_box(batch_search(&db, &queries, TOP_K)));
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_batch_search);
+criterion_main!(benches);
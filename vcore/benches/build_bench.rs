@@ -0,0 +1,114 @@
+// VecBase — build_bench.rs
+// Criterion benchmark for parallel bulk index builds.
+// Author: d65v <https://github.com/d65v>
+//
+// Run with:
+//   cargo bench --bench build_bench
+//
+// Benchmarks (N=10000, D=128):
+//   - build/sequential   (db.insert in a loop)
+//   - build/bulk_load    (db.bulk_load, links planned on all cores)
+//
+// Before timing, recall@10 of both graphs against an exact scan is printed,
+// so a speedup that costs accuracy shows up next to the numbers.
+
+use std::collections::HashSet;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use vcore::embedding::{cosine_similarity, normalize};
+use vcore::processing::BatchInsert;
+use vcore::{VecBase, VecBaseConfig};
+
+const DIM: usize = 128;
+const N: usize = 10_000;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Same LCG as `search_bench.rs`, so the data sets match.
+fn gen_vec(seed: u64, dim: usize) -> Vec<f32> {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    (0..dim)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) as f32) / (u32::MAX as f32) * 2.0 - 1.0
+        })
+        .collect()
+}
+
+fn empty_db() -> VecBase {
+    VecBase::new(VecBaseConfig {
+        dim: DIM,
+        max_elements: N + 64,
+        ..VecBaseConfig::default()
+    })
+}
+
+fn items() -> Vec<BatchInsert> {
+    (0..N)
+        .map(|i| BatchInsert { id: format!("v{}", i), vector: gen_vec(i as u64, DIM), metadata: None })
+        .collect()
+}
+
+fn build_sequential(items: Vec<BatchInsert>) -> VecBase {
+    let db = empty_db();
+    for item in items {
+        db.insert(item.id, item.vector, item.metadata).unwrap();
+    }
+    db
+}
+
+fn build_bulk(items: Vec<BatchInsert>) -> VecBase {
+    let db = empty_db();
+    db.bulk_load(items).unwrap();
+    db
+}
+
+/// Recall@10 of `db` over 100 queries, against an exact scan of the data.
+fn recall(db: &VecBase) -> f32 {
+    let data: Vec<Vec<f32>> = (0..N).map(|i| normalize(&gen_vec(i as u64, DIM))).collect();
+    let mut hits = 0;
+    for q in 0..100 {
+        let query = normalize(&gen_vec(1_000_000 + q, DIM));
+        let mut exact: Vec<(usize, f32)> = data.iter().map(|v| cosine_similarity(&query, v)).enumerate().collect();
+        exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let exact: HashSet<String> = exact[..10].iter().map(|(i, _)| format!("v{}", i)).collect();
+        hits += db.search(&query, 10).iter().filter(|r| exact.contains(&r.id)).count();
+    }
+    hits as f32 / 1_000.0
+}
+
+fn report_recall() {
+    println!(
+        "recall@10 (N={}, D={}): sequential {:.3}, bulk_load {:.3}",
+        N,
+        DIM,
+        recall(&build_sequential(items())),
+        recall(&build_bulk(items()))
+    );
+}
+
+// ── Bulk Build ────────────────────────────────────────────────────────────────
+
+fn bench_build(c: &mut Criterion) {
+    // `cargo test --benches` runs this without `--bench`; skip the extra builds.
+    if std::env::args().any(|a| a == "--bench") {
+        report_recall();
+    }
+
+    let mut group = c.benchmark_group("build");
+    group.throughput(Throughput::Elements(N as u64));
+    group.sample_size(10);
+
+    group.bench_function("sequential", |b| {
+        b.iter_batched(items, |items| black_box(build_sequential(items)), criterion::BatchSize::LargeInput);
+    });
+    group.bench_function("bulk_load", |b| {
+        b.iter_batched(items, |items| black_box(build_bulk(items)), criterion::BatchSize::LargeInput);
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_build);
+criterion_main!(benches);
@@ -0,0 +1,132 @@
+// VecBase — ivf_bench.rs
+// IVF vs HNSW: recall, memory and search latency.
+// Author: d65v <https://github.com/d65v>
+//
+// Run with:
+//   cargo bench --bench ivf_bench
+//
+// Data: N=20000 vectors, D=128, Euclidean, mixed from 16 directions plus
+// noise (same generator as `pq_bench.rs`).
+//
+// Printed once before timing:
+//   recall@10 and heap bytes per vector for each index
+//
+// Benchmarks (top-10):
+//   - ivf_search/hnsw
+//   - ivf_search/ivf_nprobe_4    (nlist=128)
+//   - ivf_search/ivf_nprobe_16
+
+use std::collections::HashSet;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use vcore::embedding::{score, Metric};
+use vcore::ivf::{IvfIndex, IvfParams};
+use vcore::processing::HnswIndex;
+
+const DIM: usize = 128;
+const N: usize = 20_000;
+const NLIST: usize = 128;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Same LCG as `search_bench.rs`.
+fn gen_vec(seed: u64, dim: usize) -> Vec<f32> {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    (0..dim)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) as f32) / (u32::MAX as f32) * 2.0 - 1.0
+        })
+        .collect()
+}
+
+/// Same mix of 16 fixed directions plus noise as `pq_bench.rs`.
+fn embedding(i: u64) -> Vec<f32> {
+    let weights = gen_vec(i, 16);
+    let noise = gen_vec(1_000_000 + i, DIM);
+    let mut v: Vec<f32> = noise.iter().map(|n| 0.05 * n).collect();
+    for (k, w) in weights.iter().enumerate() {
+        for (x, b) in v.iter_mut().zip(gen_vec(u64::MAX - k as u64, DIM)) {
+            *x += w * b;
+        }
+    }
+    v
+}
+
+struct Indexes {
+    data: Vec<Vec<f32>>,
+    hnsw: HnswIndex,
+    ivf: IvfIndex,
+}
+
+fn build() -> Indexes {
+    let data: Vec<Vec<f32>> = (0..N as u64).map(embedding).collect();
+    let mut hnsw = HnswIndex::new(DIM, N, Metric::Euclidean);
+    let params = IvfParams { nlist: NLIST, ..IvfParams::default() };
+    let mut ivf = IvfIndex::with_params(DIM, N, Metric::Euclidean, params);
+    for (i, v) in data.iter().enumerate() {
+        hnsw.insert(format!("v{}", i), v.clone()).unwrap();
+        ivf.insert(format!("v{}", i), v.clone()).unwrap();
+    }
+    Indexes { data, hnsw, ivf }
+}
+
+fn queries() -> Vec<Vec<f32>> {
+    (0..100).map(|i| embedding(5_000_000 + i)).collect()
+}
+
+/// Recall@10 of `search` against an exact scan of `data`.
+fn recall(data: &[Vec<f32>], search: impl Fn(&[f32]) -> Vec<(String, f32)>) -> f32 {
+    let queries = queries();
+    let mut hits = 0;
+    for q in &queries {
+        let mut exact: Vec<(usize, f32)> = data.iter().map(|v| score(&Metric::Euclidean, q, v)).enumerate().collect();
+        exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let exact: HashSet<String> = exact[..10].iter().map(|(i, _)| format!("v{}", i)).collect();
+        hits += search(q).iter().filter(|(id, _)| exact.contains(id)).count();
+    }
+    hits as f32 / (queries.len() * 10) as f32
+}
+
+fn report(ix: &Indexes) {
+    let row = |name: &str, bytes: usize, recall: f32| {
+        println!("{:<14} {:>7.0} B/vec   recall@10 {:.3}", name, bytes as f64 / N as f64, recall);
+    };
+    println!("N={} D={} raw {} B/vec", N, DIM, DIM * 4);
+    row("hnsw", ix.hnsw.heap_bytes(), recall(&ix.data, |q| ix.hnsw.search(q, 10)));
+    for nprobe in [4, 16] {
+        let name = format!("ivf_nprobe_{}", nprobe);
+        row(&name, ix.ivf.heap_bytes(), recall(&ix.data, |q| ix.ivf.search_with_nprobe(q, 10, nprobe)));
+    }
+}
+
+// ── IVF vs HNSW ───────────────────────────────────────────────────────────────
+
+fn bench_ivf(c: &mut Criterion) {
+    let ix = build();
+    // `cargo test --benches` runs this without `--bench`; skip the report.
+    if std::env::args().any(|a| a == "--bench") {
+        report(&ix);
+    }
+    let queries = queries();
+
+    let mut group = c.benchmark_group("ivf_search");
+    group.bench_function("hnsw", |b| {
+        b.iter(|| queries.iter().map(|q| black_box(ix.hnsw.search(q, 10)).len()).sum::<usize>());
+    });
+    for nprobe in [4, 16] {
+        group.bench_function(format!("ivf_nprobe_{}", nprobe), |b| {
+            b.iter(|| {
+                queries
+                    .iter()
+                    .map(|q| black_box(ix.ivf.search_with_nprobe(q, 10, nprobe)).len())
+                    .sum::<usize>()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_ivf);
+criterion_main!(benches);
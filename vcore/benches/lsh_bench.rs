@@ -0,0 +1,145 @@
+// VecBase — lsh_bench.rs
+// LSH vs HNSW: churn cost, recall, memory and search latency.
+// Author: d65v <https://github.com/d65v>
+//
+// Run with:
+//   cargo bench --bench lsh_bench
+//
+// Data: N=20000 vectors, D=128, Cosine, mixed from 16 directions plus
+// noise.
+//
+// Printed once before timing:
+//   recall@10 and heap bytes per vector for each index
+//
+// Benchmarks:
+//   - lsh_churn/{hnsw,lsh}           remove one id and insert it again
+//   - lsh_search/hnsw                top-10
+//   - lsh_search/lsh_probes_{0,8}    top-10, default tables and hashes
+
+use std::collections::HashSet;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use vcore::embedding::{score, Metric};
+use vcore::lsh::LshIndex;
+use vcore::processing::HnswIndex;
+
+const DIM: usize = 128;
+const N: usize = 20_000;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// A mix of 16 fixed directions plus noise, like `pq_bench.rs`, but drawn
+/// from `StdRng`: the LCG's consecutive seeds give near-identical weights,
+/// which collapses every vector into a handful of hash buckets.
+fn embedding(i: u64) -> Vec<f32> {
+    let mut basis = StdRng::seed_from_u64(u64::MAX);
+    let mut rng = StdRng::seed_from_u64(i);
+    let mut v: Vec<f32> = (0..DIM).map(|_| 0.05 * rng.gen_range(-1.0..1.0)).collect();
+    for _ in 0..16 {
+        let w: f32 = rng.gen_range(-1.0..1.0);
+        for x in v.iter_mut() {
+            *x += w * basis.gen_range(-1.0..1.0);
+        }
+    }
+    v
+}
+
+struct Indexes {
+    data: Vec<Vec<f32>>,
+    hnsw: HnswIndex,
+    lsh: LshIndex,
+}
+
+fn build() -> Indexes {
+    let data: Vec<Vec<f32>> = (0..N as u64).map(embedding).collect();
+    let mut hnsw = HnswIndex::new(DIM, N, Metric::Cosine);
+    let mut lsh = LshIndex::new(DIM, N, Metric::Cosine);
+    for (i, v) in data.iter().enumerate() {
+        hnsw.insert(format!("v{}", i), v.clone()).unwrap();
+        lsh.insert(format!("v{}", i), v.clone()).unwrap();
+    }
+    Indexes { data, hnsw, lsh }
+}
+
+fn queries() -> Vec<Vec<f32>> {
+    (0..100).map(|i| embedding(5_000_000 + i)).collect()
+}
+
+/// Recall@10 of `search` against an exact scan of `data`.
+fn recall(data: &[Vec<f32>], search: impl Fn(&[f32]) -> Vec<(String, f32)>) -> f32 {
+    let queries = queries();
+    let mut hits = 0;
+    for q in &queries {
+        let mut exact: Vec<(usize, f32)> = data.iter().map(|v| score(&Metric::Cosine, q, v)).enumerate().collect();
+        exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let exact: HashSet<String> = exact[..10].iter().map(|(i, _)| format!("v{}", i)).collect();
+        hits += search(q).iter().filter(|(id, _)| exact.contains(id)).count();
+    }
+    hits as f32 / (queries.len() * 10) as f32
+}
+
+fn report(ix: &Indexes) {
+    let row = |name: &str, bytes: usize, recall: f32| {
+        println!("{:<14} {:>7.0} B/vec   recall@10 {:.3}", name, bytes as f64 / N as f64, recall);
+    };
+    println!("N={} D={} raw {} B/vec", N, DIM, DIM * 4);
+    row("hnsw", ix.hnsw.heap_bytes(), recall(&ix.data, |q| ix.hnsw.search(q, 10)));
+    for probes in [0, 8] {
+        let name = format!("lsh_probes_{}", probes);
+        row(&name, ix.lsh.heap_bytes(), recall(&ix.data, |q| ix.lsh.search_with_probes(q, 10, probes)));
+    }
+}
+
+// ── LSH vs HNSW ───────────────────────────────────────────────────────────────
+
+fn bench_lsh(c: &mut Criterion) {
+    let mut ix = build();
+    // `cargo test --benches` runs this without `--bench`; skip the report.
+    if std::env::args().any(|a| a == "--bench") {
+        report(&ix);
+    }
+    let queries = queries();
+
+    let mut churn = c.benchmark_group("lsh_churn");
+    churn.sample_size(10);
+    let mut next = 0;
+    churn.bench_function("hnsw", |b| {
+        b.iter(|| {
+            let id = format!("v{}", next % N);
+            ix.hnsw.remove(&id);
+            ix.hnsw.insert(id, ix.data[next % N].clone()).unwrap();
+            next += 1;
+        });
+    });
+    churn.bench_function("lsh", |b| {
+        b.iter(|| {
+            let id = format!("v{}", next % N);
+            ix.lsh.remove(&id);
+            ix.lsh.insert(id, ix.data[next % N].clone()).unwrap();
+            next += 1;
+        });
+    });
+    churn.finish();
+
+    let mut group = c.benchmark_group("lsh_search");
+    group.bench_function("hnsw", |b| {
+        b.iter(|| queries.iter().map(|q| black_box(ix.hnsw.search(q, 10)).len()).sum::<usize>());
+    });
+    for probes in [0, 8] {
+        group.bench_function(format!("lsh_probes_{}", probes), |b| {
+            b.iter(|| {
+                queries
+                    .iter()
+                    .map(|q| black_box(ix.lsh.search_with_probes(q, 10, probes)).len())
+                    .sum::<usize>()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lsh);
+criterion_main!(benches);
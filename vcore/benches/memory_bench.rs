@@ -0,0 +1,107 @@
+// VecBase — memory_bench.rs
+// Resident memory per stored vector.
+// Author: d65v <https://github.com/d65v>
+//
+// Run with:
+//   cargo bench --bench memory_bench
+//
+// Fills a VecBase with N random vectors and reports live heap bytes per
+// vector (via a counting global allocator) next to the raw payload size
+// (dim × 4 bytes), plus the process RSS delta where /proc is available.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use vcore::{VecBase, VecBaseConfig};
+
+// ── Counting Allocator ────────────────────────────────────────────────────────
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LIVE.fetch_add(new_size, Ordering::Relaxed);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Same LCG as search_bench.rs.
+fn gen_vec(seed: u64, dim: usize) -> Vec<f32> {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    (0..dim)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) as f32) / (u32::MAX as f32) * 2.0 - 1.0
+        })
+        .collect()
+}
+
+/// Resident set size in bytes, if the platform exposes it.
+fn rss_bytes() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+fn measure(n: usize, dim: usize) {
+    let heap_before = LIVE.load(Ordering::Relaxed);
+    let rss_before = rss_bytes();
+
+    let db = VecBase::new(VecBaseConfig {
+        dim,
+        max_elements: n,
+        ..VecBaseConfig::default()
+    });
+    for i in 0..n {
+        db.insert(format!("vec-{:08}", i), gen_vec(i as u64, dim), None).unwrap();
+    }
+
+    let heap = LIVE.load(Ordering::Relaxed) - heap_before;
+    let rss = rss_bytes()
+        .zip(rss_before)
+        .map(|(after, before)| format!("{:>8.0}", after.saturating_sub(before) as f64 / n as f64))
+        .unwrap_or_else(|| "     n/a".to_string());
+
+    println!(
+        "N={:<7} D={:<5} raw {:>6} B/vec   heap {:>8.0} B/vec ({:.2}x raw)   rss {} B/vec",
+        n,
+        dim,
+        dim * 4,
+        heap as f64 / n as f64,
+        heap as f64 / (n * dim * 4) as f64,
+        rss
+    );
+    drop(db);
+}
+
+fn main() {
+    // `cargo test --benches` runs this binary with `--bench` absent; keep it quick.
+    let full = std::env::args().any(|a| a == "--bench");
+    let sizes: &[(usize, usize)] = if full {
+        &[(20_000, 128), (20_000, 768), (5_000, 1536)]
+    } else {
+        &[(500, 32)]
+    };
+    for &(n, dim) in sizes {
+        measure(n, dim);
+    }
+}
@@ -0,0 +1,130 @@
+// VecBase — pq_bench.rs
+// Product Quantization vs HNSW: recall, memory and search latency.
+// Author: d65v <https://github.com/d65v>
+//
+// Run with:
+//   cargo bench --bench pq_bench
+//
+// Data: N=20000 vectors, D=128, Euclidean, mixed from 16 directions plus
+// noise (low intrinsic dimension, like real embeddings; PQ on uniform noise
+// is a worst case).
+//
+// Printed once before timing:
+//   recall@10 and heap bytes per vector for each index
+//
+// Benchmarks (top-10):
+//   - pq_search/hnsw
+//   - pq_search/pq             (m=16, 8 bits: 16 bytes per vector)
+//   - pq_search/pq_rerank_10   (same codes, exact re-rank of 100 candidates)
+
+use std::collections::HashSet;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use vcore::embedding::{score, Metric};
+use vcore::pq::{PqIndex, PqParams};
+use vcore::processing::HnswIndex;
+
+const DIM: usize = 128;
+const N: usize = 20_000;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Same LCG as `search_bench.rs`.
+fn gen_vec(seed: u64, dim: usize) -> Vec<f32> {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    (0..dim)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) as f32) / (u32::MAX as f32) * 2.0 - 1.0
+        })
+        .collect()
+}
+
+/// Point `i`: a random mix of 16 fixed directions plus a little noise, so
+/// the data has low intrinsic dimension like real embeddings.
+fn embedding(i: u64) -> Vec<f32> {
+    let weights = gen_vec(i, 16);
+    let noise = gen_vec(1_000_000 + i, DIM);
+    let mut v: Vec<f32> = noise.iter().map(|n| 0.05 * n).collect();
+    for (k, w) in weights.iter().enumerate() {
+        for (x, b) in v.iter_mut().zip(gen_vec(u64::MAX - k as u64, DIM)) {
+            *x += w * b;
+        }
+    }
+    v
+}
+
+struct Indexes {
+    data: Vec<Vec<f32>>,
+    hnsw: HnswIndex,
+    pq: PqIndex,
+    pq_rerank: PqIndex,
+}
+
+fn build() -> Indexes {
+    let data: Vec<Vec<f32>> = (0..N as u64).map(embedding).collect();
+    let mut hnsw = HnswIndex::new(DIM, N, Metric::Euclidean);
+    let params = PqParams { sub_vectors: 16, bits: 8, ..PqParams::default() };
+    let mut pq = PqIndex::train(DIM, N, Metric::Euclidean, params, &data[..5_000]).unwrap();
+    let mut pq_rerank = PqIndex::with_codec(pq.codec().unwrap().clone(), N, Metric::Euclidean, 10);
+    for (i, v) in data.iter().enumerate() {
+        hnsw.insert(format!("v{}", i), v.clone()).unwrap();
+        pq.insert(format!("v{}", i), v.clone()).unwrap();
+        pq_rerank.insert(format!("v{}", i), v.clone()).unwrap();
+    }
+    Indexes { data, hnsw, pq, pq_rerank }
+}
+
+fn queries() -> Vec<Vec<f32>> {
+    (0..100).map(|i| embedding(5_000_000 + i)).collect()
+}
+
+/// Recall@10 of `search` against an exact scan of `data`.
+fn recall(data: &[Vec<f32>], search: impl Fn(&[f32]) -> Vec<(String, f32)>) -> f32 {
+    let queries = queries();
+    let mut hits = 0;
+    for q in &queries {
+        let mut exact: Vec<(usize, f32)> = data.iter().map(|v| score(&Metric::Euclidean, q, v)).enumerate().collect();
+        exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let exact: HashSet<String> = exact[..10].iter().map(|(i, _)| format!("v{}", i)).collect();
+        hits += search(q).iter().filter(|(id, _)| exact.contains(id)).count();
+    }
+    hits as f32 / (queries.len() * 10) as f32
+}
+
+fn report(ix: &Indexes) {
+    let row = |name: &str, bytes: usize, recall: f32| {
+        println!("{:<14} {:>7.0} B/vec   recall@10 {:.3}", name, bytes as f64 / N as f64, recall);
+    };
+    println!("N={} D={} raw {} B/vec", N, DIM, DIM * 4);
+    row("hnsw", ix.hnsw.heap_bytes(), recall(&ix.data, |q| ix.hnsw.search(q, 10)));
+    row("pq", ix.pq.heap_bytes(), recall(&ix.data, |q| ix.pq.search(q, 10)));
+    row("pq_rerank_10", ix.pq_rerank.heap_bytes(), recall(&ix.data, |q| ix.pq_rerank.search(q, 10)));
+}
+
+// ── PQ vs HNSW ────────────────────────────────────────────────────────────────
+
+fn bench_pq(c: &mut Criterion) {
+    let ix = build();
+    // `cargo test --benches` runs this without `--bench`; skip the report.
+    if std::env::args().any(|a| a == "--bench") {
+        report(&ix);
+    }
+    let queries = queries();
+
+    let mut group = c.benchmark_group("pq_search");
+    group.bench_function("hnsw", |b| {
+        b.iter(|| queries.iter().map(|q| black_box(ix.hnsw.search(q, 10)).len()).sum::<usize>());
+    });
+    group.bench_function("pq", |b| {
+        b.iter(|| queries.iter().map(|q| black_box(ix.pq.search(q, 10)).len()).sum::<usize>());
+    });
+    group.bench_function("pq_rerank_10", |b| {
+        b.iter(|| queries.iter().map(|q| black_box(ix.pq_rerank.search(q, 10)).len()).sum::<usize>());
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_pq);
+criterion_main!(benches);
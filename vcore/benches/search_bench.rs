@@ -15,6 +15,12 @@
 //   - insert_batch_1k       (1000 inserts, D=128)
 //   - cosine_similarity_raw (raw math, no DB)
 //   - normalize_raw         (raw normalization, D=128)
+//   - kernels/dot/<set>/768 (each available SIMD set vs scalar)
+//   - kernels/l2_sq/<set>/768
+//   - euclidean_ranking/{score,rank_score}/<dim>  (500 candidates)
+//   - search_by_metric/<metric>        (N=5000, D=128, graph)
+//   - search_by_metric/brute/<metric>  (N=500,  D=128, exact scan)
+//   - search_by_quantization/<none|f16|int8|int8+rescore>  (N=5000, D=128)
 //   - search_by_dim/32      (fixed N=1000, varying D)
 //   - search_by_dim/128
 //   - search_by_dim/512
@@ -29,7 +35,7 @@ use criterion::{
 };
 
 use vcore::{VecBase, VecBaseConfig};
-use vcore::embedding::{cosine_similarity, normalize};
+use vcore::embedding::{cosine_similarity, normalize, rank_score, score, simd, Metric};
 
 // ── Helpers ───────────────────────────────────────────────────────────────────
 
@@ -49,7 +55,7 @@ fn gen_vec(seed: u64, dim: usize) -> Vec<f32> {
 
 /// Build a VecBase filled with `n` random vectors of dimension `dim`.
 fn build_db(n: usize, dim: usize, metric: &str) -> VecBase {
-    let mut db = VecBase::new(VecBaseConfig {
+    let db = VecBase::new(VecBaseConfig {
         dim,
         metric: metric.to_string(),
         max_elements: n + 64,
@@ -147,8 +153,9 @@ fn bench_insert_single(c: &mut Criterion) {
                 let v = gen_vec(1234, DIM);
                 (db, v)
             },
-            |(mut db, v)| {
-                black_box(db.insert("x".to_string(), v, None).unwrap())
+            |(db, v)| {
+                db.insert("x".to_string(), v, None).unwrap();
+                black_box(db)
             },
             criterion::BatchSize::SmallInput,
         );
@@ -172,9 +179,9 @@ fn bench_insert_batch_1k(c: &mut Criterion) {
                     .collect();
                 (db, vecs)
             },
-            |(mut db, vecs)| {
+            |(db, vecs)| {
                 for (id, v) in vecs {
-                    black_box(db.insert(id, v, None).unwrap());
+                    db.insert(id, v, None).unwrap();
                 }
             },
             criterion::BatchSize::LargeInput,
@@ -207,11 +214,62 @@ fn bench_normalize_raw(c: &mut Criterion) {
     });
 }
 
+fn bench_kernels(c: &mut Criterion) {
+    const DIM: usize = 768;
+    let a = gen_vec(4, DIM);
+    let b = gen_vec(5, DIM);
+
+    let mut group = c.benchmark_group("kernels");
+    group.throughput(Throughput::Elements(DIM as u64));
+
+    let scalar = simd::Kernels { name: "scalar", dot: simd::scalar_dot, l2_sq: simd::scalar_l2_sq };
+    for simd::Kernels { name, dot, l2_sq } in std::iter::once(scalar).chain(simd::available()) {
+        group.bench_with_input(BenchmarkId::new(format!("dot/{}", name), DIM), &DIM, |bench, _| {
+            bench.iter(|| black_box(dot(black_box(&a), black_box(&b))));
+        });
+        group.bench_with_input(BenchmarkId::new(format!("l2_sq/{}", name), DIM), &DIM, |bench, _| {
+            bench.iter(|| black_box(l2_sq(black_box(&a), black_box(&b))));
+        });
+    }
+
+    group.finish();
+}
+
+/// Ranking 500 candidates by Euclidean `score` (a sqrt each) against
+/// `rank_score` (squared distance), which is what the index compares.
+fn bench_euclidean_ranking(c: &mut Criterion) {
+    const CANDIDATES: u64 = 500;
+
+    let mut group = c.benchmark_group("euclidean_ranking");
+    group.throughput(Throughput::Elements(CANDIDATES));
+
+    for dim in [16usize, 128] {
+        let query = gen_vec(6, dim);
+        let candidates: Vec<Vec<f32>> = (0..CANDIDATES).map(|i| gen_vec(100 + i, dim)).collect();
+        let best = |f: fn(&Metric, &[f32], &[f32]) -> f32| {
+            candidates
+                .iter()
+                .map(|v| f(&Metric::Euclidean, black_box(&query), v))
+                .fold(f32::MIN, f32::max)
+        };
+
+        group.bench_with_input(BenchmarkId::new("score", dim), &dim, |b, _| {
+            b.iter(|| black_box(best(score)));
+        });
+        group.bench_with_input(BenchmarkId::new("rank_score", dim), &dim, |b, _| {
+            b.iter(|| black_box(best(rank_score)));
+        });
+    }
+
+    group.finish();
+}
+
 // ── Metric Comparison ─────────────────────────────────────────────────────────
 
 fn bench_search_by_metric(c: &mut Criterion) {
     const DIM: usize = 128;
     const N: usize = 5_000;
+    const BRUTE_N: usize = 500;
     const TOP_K: usize = 10;
 
     let mut group = c.benchmark_group("search_by_metric");
@@ -227,6 +285,48 @@ fn bench_search_by_metric(c: &mut Criterion) {
                 b.iter(|| black_box(db.search(black_box(&query), TOP_K)));
             },
         );
+
+        // Below brute_threshold every vector is scored, so per-candidate
+        // cost (e.g. a sqrt for euclidean) shows up undiluted.
+        let small = build_db(BRUTE_N, DIM, metric);
+        group.bench_with_input(BenchmarkId::new("brute", metric), metric, |b, _| {
+            b.iter(|| black_box(small.search(black_box(&query), TOP_K)));
+        });
+    }
+
+    group.finish();
+}
+
+// ── Quantized Search ──────────────────────────────────────────────────────────
+
+fn bench_search_by_quantization(c: &mut Criterion) {
+    const DIM: usize = 128;
+    const N: usize = 5_000;
+    const TOP_K: usize = 10;
+
+    let mut group = c.benchmark_group("search_by_quantization");
+
+    for (label, quantization, rescore) in [
+        ("none", "none", false),
+        ("f16", "f16", false),
+        ("int8", "int8", false),
+        ("int8+rescore", "int8", true),
+    ] {
+        let db = VecBase::new(VecBaseConfig {
+            dim: DIM,
+            max_elements: N + 64,
+            vector_quantization: quantization.to_string(),
+            rescore,
+            ..VecBaseConfig::default()
+        });
+        for i in 0..N {
+            db.insert(format!("v{}", i), gen_vec(i as u64, DIM), None).unwrap();
+        }
+        let query = gen_vec(55, DIM);
+
+        group.bench_function(BenchmarkId::from_parameter(label), |b| {
+            b.iter(|| black_box(db.search(black_box(&query), TOP_K)));
+        });
     }
 
     group.finish();
@@ -243,7 +343,10 @@ criterion_group!(
     bench_insert_batch_1k,
     bench_cosine_raw,
     bench_normalize_raw,
+    bench_kernels,
+    bench_euclidean_ranking,
     bench_search_by_metric,
+    bench_search_by_quantization,
 );
 
 criterion_main!(benches);
@@ -0,0 +1,15 @@
+// VecBase — build.rs
+// Generates the gRPC bindings in `vcore::grpc` from proto/vecbase.proto.
+// Author: d65v <https://github.com/d65v>
+//
+// Uses the protoc shipped by `protoc-bin-vendored` unless PROTOC is already
+// set, so building needs no system protobuf install.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if std::env::var_os("PROTOC").is_none() {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    }
+    println!("cargo:rerun-if-changed=proto/vecbase.proto");
+    tonic_prost_build::configure().compile_protos(&["proto/vecbase.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,387 @@
+// VecBase — database.rs
+// Many named collections in one process, each its own VecBase.
+// Author: d65v <https://github.com/d65v>
+//
+// A collection is a full `VecBase` with its own config (dim, metric, HNSW
+// parameters, storage backends), index and WAL. On disk:
+//
+//   <root>/collections/<name>/collection.json   the collection's VecBaseConfig
+//   <root>/collections/<name>/…                 its snapshot, WAL, record and
+//                                               vector files
+//
+// `collection.json` is written before the collection is first opened, so a
+// collection survives a restart even if it was never checkpointed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::storage::{storage_err, tmp_path};
+use crate::{Result, VecBase, VecBaseConfig, VecBaseError};
+
+/// Sub-directory of the database root holding one directory per collection.
+pub const COLLECTIONS_DIR: &str = "collections";
+
+/// Per-collection config file name.
+pub const COLLECTION_FILE: &str = "collection.json";
+
+/// Longest accepted collection name, in bytes.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// A set of named collections.
+pub struct Database {
+    /// `None` for purely in-memory databases.
+    root: Option<PathBuf>,
+    collections: BTreeMap<String, VecBase>,
+}
+
+impl Database {
+    /// Create an empty in-memory database. Collections are plain
+    /// `VecBase::new` instances and vanish when it is dropped.
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            collections: BTreeMap::new(),
+        }
+    }
+
+    /// Open (or create) a durable database rooted at `root`, reopening every
+    /// collection found under `<root>/collections`.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the directory or a collection
+    /// cannot be read, or `VecBaseError::ConfigError` if a `collection.json`
+    /// is invalid.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let dir = root.join(COLLECTIONS_DIR);
+        fs::create_dir_all(&dir).map_err(|e| storage_err("create collections dir", e))?;
+
+        let mut collections = BTreeMap::new();
+        let entries = fs::read_dir(&dir).map_err(|e| storage_err("list collections", e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| storage_err("list collections", e))?;
+            let path = entry.path();
+            let manifest = path.join(COLLECTION_FILE);
+            if !manifest.exists() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let mut config = read_config(&manifest)?;
+            config.storage_path = path.to_string_lossy().into_owned();
+            log::info!("Opening collection {:?}", name);
+            collections.insert(name, VecBase::open(config)?);
+        }
+
+        Ok(Self {
+            root: Some(root),
+            collections,
+        })
+    }
+
+    /// Create a collection named `name` with its own `config`. In a durable
+    /// database `config.storage_path` is replaced by the collection's
+    /// directory.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::ConfigError` for an invalid name or a
+    /// `vector_quantization` its index type does not support,
+    /// `VecBaseError::CollectionExists` if the name is taken, and
+    /// `VecBaseError::StorageError` if its files cannot be created.
+    pub fn create_collection(
+        &mut self,
+        name: &str,
+        mut config: VecBaseConfig,
+    ) -> Result<&mut VecBase> {
+        validate_name(name)?;
+        if self.collections.contains_key(name) {
+            return Err(VecBaseError::CollectionExists {
+                name: name.to_string(),
+            });
+        }
+
+        let db = match &self.root {
+            Some(root) => {
+                let dir = root.join(COLLECTIONS_DIR).join(name);
+                fs::create_dir_all(&dir).map_err(|e| storage_err("create collection dir", e))?;
+                config.storage_path = dir.to_string_lossy().into_owned();
+                let opened = write_config(&dir.join(COLLECTION_FILE), &config)
+                    .and_then(|_| VecBase::open(config));
+                if opened.is_err() {
+                    let _ = fs::remove_dir_all(&dir);
+                }
+                opened?
+            }
+            None => {
+                let db = VecBase::new(config);
+                db.check_quantization()?;
+                db
+            }
+        };
+        Ok(self.collections.entry(name.to_string()).or_insert(db))
+    }
+
+    /// Remove a collection and, in a durable database, delete its files.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::CollectionNotFound` if there is no such
+    /// collection, or `VecBaseError::StorageError` if its files cannot be
+    /// removed.
+    pub fn drop_collection(&mut self, name: &str) -> Result<()> {
+        let db = self
+            .collections
+            .remove(name)
+            .ok_or_else(|| VecBaseError::CollectionNotFound {
+                name: name.to_string(),
+            })?;
+        drop(db);
+        if let Some(root) = &self.root {
+            let dir = root.join(COLLECTIONS_DIR).join(name);
+            fs::remove_dir_all(&dir).map_err(|e| storage_err("remove collection dir", e))?;
+        }
+        Ok(())
+    }
+
+    /// Collection names, sorted.
+    pub fn list_collections(&self) -> Vec<&str> {
+        self.collections.keys().map(String::as_str).collect()
+    }
+
+    /// # Errors
+    /// Returns `VecBaseError::CollectionNotFound` if there is no such collection.
+    pub fn collection(&self, name: &str) -> Result<&VecBase> {
+        self.collections
+            .get(name)
+            .ok_or_else(|| VecBaseError::CollectionNotFound {
+                name: name.to_string(),
+            })
+    }
+
+    /// # Errors
+    /// Returns `VecBaseError::CollectionNotFound` if there is no such collection.
+    pub fn collection_mut(&mut self, name: &str) -> Result<&mut VecBase> {
+        self.collections
+            .get_mut(name)
+            .ok_or_else(|| VecBaseError::CollectionNotFound {
+                name: name.to_string(),
+            })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.collections.contains_key(name)
+    }
+
+    /// Checkpoint every collection (see [`VecBase::checkpoint`]). A no-op
+    /// for in-memory databases.
+    ///
+    /// # Errors
+    /// Returns the first `VecBaseError::StorageError` encountered.
+    pub fn checkpoint(&self) -> Result<()> {
+        if self.root.is_none() {
+            return Ok(());
+        }
+        for db in self.collections.values() {
+            db.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Root directory, if durable.
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Names become directory names, so keep them to `[A-Za-z0-9_-]`.
+fn validate_name(name: &str) -> Result<()> {
+    let ok = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    if ok {
+        Ok(())
+    } else {
+        Err(VecBaseError::ConfigError(format!(
+            "invalid collection name {:?}: use 1-{} of A-Z a-z 0-9 _ -",
+            name, MAX_NAME_LEN
+        )))
+    }
+}
+
+fn read_config(path: &Path) -> Result<VecBaseConfig> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| storage_err(&format!("read {}", path.display()), e))?;
+    serde_json::from_str(&text)
+        .map_err(|e| VecBaseError::ConfigError(format!("{}: {}", path.display(), e)))
+}
+
+fn write_config(path: &Path, config: &VecBaseConfig) -> Result<()> {
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| storage_err("encode collection config", e))?;
+    let tmp = tmp_path(path);
+    fs::write(&tmp, json).map_err(|e| storage_err("write collection config", e))?;
+    fs::rename(&tmp, path).map_err(|e| storage_err("rename collection config", e))
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::scratch_dir;
+
+    fn text() -> VecBaseConfig {
+        VecBaseConfig {
+            dim: 4,
+            ..VecBaseConfig::default()
+        }
+    }
+
+    fn image() -> VecBaseConfig {
+        VecBaseConfig {
+            dim: 3,
+            metric: "euclidean".into(),
+            ..VecBaseConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_collections_are_independent() {
+        let mut db = Database::new();
+        db.create_collection("text", text()).unwrap();
+        db.create_collection("image", image()).unwrap();
+        assert_eq!(db.list_collections(), vec!["image", "text"]);
+
+        db.collection_mut("text")
+            .unwrap()
+            .insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], None)
+            .unwrap();
+        db.collection_mut("image")
+            .unwrap()
+            .insert("a".into(), vec![0.0, 0.0, 2.0], None)
+            .unwrap();
+        let err = db
+            .collection_mut("image")
+            .unwrap()
+            .insert("b".into(), vec![1.0; 4], None);
+        assert!(matches!(
+            err,
+            Err(VecBaseError::DimensionMismatch { expected: 3, .. })
+        ));
+        assert_eq!(
+            db.collection("text")
+                .unwrap()
+                .search(&[1.0, 0.0, 0.0, 0.0], 1)[0]
+                .id,
+            "a"
+        );
+
+        assert!(matches!(
+            db.create_collection("text", text()),
+            Err(VecBaseError::CollectionExists { .. })
+        ));
+        for bad in ["", "../etc", "a b", &"x".repeat(MAX_NAME_LEN + 1)] {
+            assert!(matches!(
+                db.create_collection(bad, text()),
+                Err(VecBaseError::ConfigError(_))
+            ));
+        }
+        let unquantizable = VecBaseConfig {
+            index_type: "ivf".into(),
+            vector_quantization: "int8".into(),
+            ..text()
+        };
+        assert!(matches!(
+            db.create_collection("ivf", unquantizable),
+            Err(VecBaseError::ConfigError(_))
+        ));
+
+        db.drop_collection("text").unwrap();
+        assert!(matches!(
+            db.collection("text"),
+            Err(VecBaseError::CollectionNotFound { .. })
+        ));
+        assert!(matches!(
+            db.drop_collection("text"),
+            Err(VecBaseError::CollectionNotFound { .. })
+        ));
+        assert_eq!(db.list_collections(), vec!["image"]);
+    }
+
+    #[test]
+    fn test_durable_collections_reopen() {
+        let root = scratch_dir("database-reopen");
+        {
+            let mut db = Database::open(&root).unwrap();
+            let coll = db.create_collection("text", text()).unwrap();
+            coll.insert("t".into(), vec![0.0, 1.0, 0.0, 0.0], None)
+                .unwrap();
+            coll.checkpoint().unwrap();
+            // Never checkpointed: recovered from collection.json + WAL.
+            db.create_collection("image", image())
+                .unwrap()
+                .insert("i".into(), vec![1.0, 2.0, 3.0], None)
+                .unwrap();
+            db.create_collection("gone", text()).unwrap();
+            db.drop_collection("gone").unwrap();
+        }
+        assert!(!root.join(COLLECTIONS_DIR).join("gone").exists());
+
+        let db = Database::open(&root).unwrap();
+        assert_eq!(db.list_collections(), vec!["image", "text"]);
+        let image = db.collection("image").unwrap();
+        assert_eq!(
+            (image.config.dim, image.config.metric.as_str()),
+            (3, "euclidean")
+        );
+        assert_eq!(image.vector("i"), Some(vec![1.0, 2.0, 3.0]));
+        let text = db.collection("text").unwrap();
+        assert_eq!(
+            text.config.storage_path,
+            root.join(COLLECTIONS_DIR).join("text").to_string_lossy()
+        );
+        assert_eq!(text.len(), 1);
+
+        // Checkpointing needs only a shared handle.
+        let shared = std::sync::Arc::new(db);
+        shared.checkpoint().unwrap();
+        assert!(root.join(COLLECTIONS_DIR).join("image").join(crate::storage::SNAPSHOT_FILE).exists());
+    }
+
+    #[test]
+    fn test_open_reads_older_collection_configs() {
+        let root = scratch_dir("database-legacy");
+        let dir = root.join(COLLECTIONS_DIR).join("legacy");
+        fs::create_dir_all(&dir).unwrap();
+        // A collection.json from before index types and quantization.
+        let legacy = r#"{
+            "dim": 3,
+            "metric": "euclidean",
+            "max_elements": 1000,
+            "storage_path": "/elsewhere",
+            "hnsw_m": 8,
+            "ef_construction": 50,
+            "ef_search": 32,
+            "brute_threshold": 500,
+            "vector_storage": "memory",
+            "record_storage": "memory",
+            "payload_indexes": { "lang": "Keyword" }
+        }"#;
+        fs::write(dir.join(COLLECTION_FILE), legacy).unwrap();
+
+        let mut db = Database::open(&root).unwrap();
+        let coll = db.collection_mut("legacy").unwrap();
+        assert_eq!((coll.config.dim, coll.config.hnsw_m), (3, 8));
+        assert_eq!(coll.config.index_type, VecBaseConfig::default().index_type);
+        assert_eq!(coll.config.vector_quantization, "none");
+        coll.insert("a".into(), vec![1.0, 2.0, 3.0], None).unwrap();
+        assert_eq!(coll.len(), 1);
+    }
+}
@@ -2,8 +2,12 @@
 // Vector normalization, format parsing, and similarity metrics.
 // Author: d65v <https://github.com/d65v>
 
+use serde::{Deserialize, Serialize};
+
+pub mod simd;
+
 /// Supported similarity metrics.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Metric {
     /// Cosine similarity (assumes pre-normalized vectors → dot product)
     Cosine,
@@ -28,18 +32,19 @@ pub fn normalize(v: &[f32]) -> Vec<f32> {
 /// Compute the L2 (Euclidean) magnitude (norm) of a vector.
 #[inline]
 pub fn magnitude(v: &[f32]) -> f32 {
-    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+    dot(v, v).sqrt()
 }
 
 // ── Similarity / Distance ─────────────────────────────────────────────────────
 
-/// Dot product of two equal-length vectors.
+/// Dot product of two equal-length vectors, using the fastest kernel this
+/// CPU supports (see [`simd`]).
 ///
 /// # Panics
-/// Does not panic; if lengths differ the shorter one is the limit (zip).
+/// Does not panic; if lengths differ the shorter one is the limit.
 #[inline]
 pub fn dot(a: &[f32], b: &[f32]) -> f32 {
-    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+    (simd::active().dot)(a, b)
 }
 
 /// Cosine similarity between two vectors.
@@ -51,14 +56,10 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
 }
 
 /// Squared Euclidean distance (cheaper — avoids sqrt when only ranking).
+/// Vectorized like [`dot`].
+#[inline]
 pub fn euclidean_distance_sq(a: &[f32], b: &[f32]) -> f32 {
-    a.iter()
-        .zip(b.iter())
-        .map(|(x, y)| {
-            let d = x - y;
-            d * d
-        })
-        .sum()
+    (simd::active().l2_sq)(a, b)
 }
 
 /// Euclidean distance.
@@ -75,6 +76,27 @@ pub fn score(metric: &Metric, query: &[f32], candidate: &[f32]) -> f32 {
     }
 }
 
+/// Score used to rank candidates inside an index. Orders candidates exactly
+/// like [`score`], but Euclidean uses the negated *squared* distance, saving
+/// a `sqrt` per candidate. Convert with [`score_from_rank`] before results
+/// leave the index.
+#[inline]
+pub fn rank_score(metric: &Metric, query: &[f32], candidate: &[f32]) -> f32 {
+    match metric {
+        Metric::Euclidean => -euclidean_distance_sq(query, candidate),
+        _ => score(metric, query, candidate),
+    }
+}
+
+/// Turn a [`rank_score`] into the public [`score`] for the same pair.
+#[inline]
+pub fn score_from_rank(metric: &Metric, rank: f32) -> f32 {
+    match metric {
+        Metric::Euclidean => -(-rank).sqrt(),
+        _ => rank,
+    }
+}
+
 // ── Embedding Parsing ─────────────────────────────────────────────────────────
 
 /// Parse a JSON array of floats into a Vec<f32>.
@@ -153,6 +175,18 @@ mod tests {
         assert!((euclidean_distance(&a, &b) - 5.0).abs() < 1e-5);
     }
 
+    #[test]
+    fn test_rank_score_converts_to_score() {
+        let a = [0.3, -1.7, 2.25, 9.0];
+        let b = [-4.0, 0.5, 1.0, 3.5];
+        for metric in [Metric::Cosine, Metric::Euclidean, Metric::DotProduct] {
+            let rank = rank_score(&metric, &a, &b);
+            assert_eq!(score_from_rank(&metric, rank), score(&metric, &a, &b));
+        }
+        // Closer still ranks higher.
+        assert!(rank_score(&Metric::Euclidean, &a, &a) > rank_score(&Metric::Euclidean, &a, &b));
+    }
+
     #[test]
     fn test_parse_json_embedding() {
         let json = "[0.1, 0.2, 0.3]";
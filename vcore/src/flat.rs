@@ -0,0 +1,210 @@
+// VecBase — flat.rs
+// Exact flat index: every search scores every stored vector.
+// Author: d65v <https://github.com/d65v>
+//
+// NOTE: No structure beyond the vectors and the id table, so inserts and
+// removes are O(1) and results are always exact, at O(N · D) per query.
+// The baseline other indexes are measured against, and a fine choice for
+// collections small enough that a scan is fast.
+
+use std::any::Any;
+
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::{rank_score, Metric};
+use crate::index::{self, to_scores, AnnIndex, IndexType, TopK};
+use crate::processing::IdTable;
+use crate::vectors::{FreeRows, VectorStore};
+use crate::{Result, VecBaseConfig, VecBaseError};
+
+// ── Flat Index ────────────────────────────────────────────────────────────────
+
+/// An exact nearest neighbor index that scans every vector. Same row, id
+/// and score conventions as [`HnswIndex`](crate::processing::HnswIndex).
+#[derive(Serialize, Deserialize)]
+pub struct FlatIndex {
+    dim: usize,
+    max_elements: usize,
+    metric: Metric,
+    vectors: VectorStore,
+    /// External id ↔ row
+    ids: IdTable,
+    /// Free rows, reused before the store grows
+    free: FreeRows,
+}
+
+impl FlatIndex {
+    pub fn new(dim: usize, max_elements: usize, metric: Metric) -> Self {
+        Self::with_store(max_elements, metric, VectorStore::memory(dim))
+    }
+
+    /// Create an empty index over an explicit vector store, e.g. a
+    /// memory-mapped one from [`VectorStore::mmap`]. Any rows already in
+    /// the store are treated as free and will be overwritten.
+    pub fn with_store(max_elements: usize, metric: Metric, vectors: VectorStore) -> Self {
+        let allocated = vectors.len() as u32;
+        Self {
+            dim: vectors.dim(),
+            max_elements,
+            metric,
+            ids: IdTable::with_free_rows(allocated as usize),
+            free: FreeRows::all(&vectors),
+            vectors,
+        }
+    }
+
+    /// Top-k rows for which `accept` returns true, exactly.
+    pub fn search_rows_filtered<F: Fn(u32) -> bool>(&self, query: &[f32], top_k: usize, accept: F) -> Vec<(u32, f32)> {
+        let mut best = TopK::new(top_k);
+        for r in self.ids.rows().filter(|&r| accept(r)) {
+            best.push(r, rank_score(&self.metric, query, self.vectors.get(r)));
+        }
+        to_scores(&self.metric, best.into_sorted())
+    }
+}
+
+impl AnnIndex for FlatIndex {
+    fn kind(&self) -> &'static str {
+        "flat"
+    }
+
+    /// # Errors
+    /// Returns `VecBaseError::CapacityExceeded` if the index already holds
+    /// `max_elements` vectors, or `VecBaseError::StorageError` if the vector
+    /// store cannot grow.
+    fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        // The new vector goes through a free row like any other, so a mapped
+        // store keeps the old one for the last checkpoint (see `FreeRows`).
+        AnnIndex::remove(self, &id);
+        if self.ids.len() >= self.max_elements {
+            return Err(VecBaseError::CapacityExceeded {
+                max_elements: self.max_elements,
+            });
+        }
+        let row = match self.free.pop() {
+            Some(r) => {
+                self.vectors.set(r, &vector);
+                r
+            }
+            None => self.vectors.push(&vector)?,
+        };
+        self.ids.insert(row, &id);
+        Ok(row)
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some(row) = self.ids.row(id) {
+            self.ids.remove_row(row);
+            self.free.push(row, &self.vectors);
+        }
+    }
+
+    /// Exact; `breadth` is ignored.
+    fn search_rows(&self, query: &[f32], top_k: usize, _breadth: Option<usize>) -> Vec<(u32, f32)> {
+        FlatIndex::search_rows_filtered(self, query, top_k, |_| true)
+    }
+
+    fn search_rows_filtered(&self, query: &[f32], top_k: usize, accept: &dyn Fn(u32) -> bool) -> Vec<(u32, f32)> {
+        FlatIndex::search_rows_filtered(self, query, top_k, accept)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        index::encode(self)
+    }
+
+    fn row(&self, id: &str) -> Option<u32> {
+        self.ids.row(id)
+    }
+
+    fn id_at(&self, row: u32) -> Option<&str> {
+        self.ids.id(row)
+    }
+
+    fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        self.ids.id(row).map(|_| self.vectors.get(row))
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.vectors.heap_bytes() + self.ids.heap_bytes() + self.free.heap_bytes()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.vectors.flush()
+    }
+
+    fn reserve(&mut self, additional: usize) -> Result<()> {
+        self.vectors.reserve(additional)
+    }
+
+    fn release_free_rows(&mut self) {
+        self.free.release();
+    }
+}
+
+/// Registry entry for `index_type = "flat"`, which has no parameters.
+pub const INDEX_TYPE: IndexType = IndexType {
+    name: "flat",
+    build: build_index,
+    load: index::decode::<FlatIndex>,
+};
+
+fn build_index(config: &VecBaseConfig, metric: Metric, vectors: VectorStore) -> Box<dyn AnnIndex> {
+    Box::new(FlatIndex::with_store(config.max_elements, metric, vectors))
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::score;
+
+    #[test]
+    fn test_exact_search_and_reuse() {
+        let mut idx = FlatIndex::new(2, 3, Metric::Euclidean);
+        idx.insert("a".into(), vec![0.0, 0.0]).unwrap();
+        idx.insert("b".into(), vec![3.0, 4.0]).unwrap();
+        idx.insert("c".into(), vec![1.0, 0.0]).unwrap();
+        assert!(matches!(
+            idx.insert("d".into(), vec![9.0, 9.0]),
+            Err(VecBaseError::CapacityExceeded { max_elements: 3 })
+        ));
+
+        let found = AnnIndex::search_rows(&idx, &[0.0, 0.0], 3, None);
+        let ids: Vec<&str> = found.iter().map(|&(r, _)| idx.id_at(r).unwrap()).collect();
+        assert_eq!(ids, ["a", "c", "b"]);
+        assert_eq!(found[2].1, score(&Metric::Euclidean, &[0.0, 0.0], &[3.0, 4.0]));
+
+        let b = idx.row("b").unwrap();
+        idx.remove("b");
+        assert_eq!(idx.vector("b"), None);
+        assert_eq!(idx.insert("d".into(), vec![9.0, 9.0]).unwrap(), b);
+        assert_eq!(idx.len(), 3);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut idx = FlatIndex::new(2, 10, Metric::DotProduct);
+        idx.insert("a".into(), vec![1.0, 2.0]).unwrap();
+        let loaded = index::decode::<FlatIndex>(&idx.to_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.kind(), "flat");
+        assert_eq!(loaded.vector("a"), Some(&[1.0, 2.0][..]));
+        assert!(loaded.downcast_ref::<FlatIndex>().is_some());
+    }
+}
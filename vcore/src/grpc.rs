@@ -0,0 +1,485 @@
+// VecBase — grpc.rs
+// gRPC service served by `vecbase grpc`, generated from proto/vecbase.proto.
+// Author: d65v <https://github.com/d65v>
+//
+// Same operations as the HTTP API in `server.rs`, but vectors travel as
+// packed binary floats instead of JSON text. Status codes follow
+// `code_for`; the handlers share one `VecBase` (see `SharedDb`) and run on
+// the blocking pool, exactly like the HTTP handlers.
+
+use std::collections::HashMap;
+use std::future::Future;
+
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+use crate::metadata::{Metadata, Value};
+use crate::processing::{batch_insert, BatchInsert};
+use crate::server::{SharedDb, MAX_BODY_BYTES};
+use crate::{VecBase, VecBaseError};
+
+/// Generated protobuf messages, client and server stubs.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("vecbase.v1");
+}
+
+use proto::metadata_value::Kind;
+use proto::vec_base_server::{VecBase as VecBaseRpc, VecBaseServer};
+
+/// Streamed upserts are applied in chunks of this many items, so a bulk
+/// load is never buffered in memory all at once.
+pub const BULK_CHUNK: usize = 1024;
+
+/// Results returned by `Search` when the request leaves `top_k` at 0.
+pub const DEFAULT_TOP_K: usize = 10;
+
+// ── Errors ────────────────────────────────────────────────────────────────────
+
+/// gRPC status code for a `VecBaseError`.
+pub fn code_for(err: &VecBaseError) -> Code {
+    match err {
+        VecBaseError::DimensionMismatch { .. } | VecBaseError::ConfigError(_) => {
+            Code::InvalidArgument
+        }
+        VecBaseError::NotFound { .. } | VecBaseError::CollectionNotFound { .. } => Code::NotFound,
+        VecBaseError::CollectionExists { .. } => Code::AlreadyExists,
+        VecBaseError::CapacityExceeded { .. } => Code::ResourceExhausted,
+        VecBaseError::StorageError(_) | VecBaseError::PluginLoadError(_) => Code::Internal,
+    }
+}
+
+fn status(err: VecBaseError) -> Status {
+    let code = code_for(&err);
+    if code == Code::Internal {
+        log::error!("{}", err);
+    }
+    Status::new(code, err.to_string())
+}
+
+// ── Metadata Conversion ───────────────────────────────────────────────────────
+
+fn value_from_proto(value: proto::MetadataValue) -> Option<Value> {
+    Some(match value.kind? {
+        Kind::StringValue(s) => Value::String(s),
+        Kind::NumberValue(n) => Value::Number(n),
+        Kind::BoolValue(b) => Value::Bool(b),
+        Kind::ListValue(list) => Value::List(
+            list.values
+                .into_iter()
+                .map(value_from_proto)
+                .collect::<Option<_>>()?,
+        ),
+    })
+}
+
+fn value_to_proto(value: &Value) -> proto::MetadataValue {
+    let kind = match value {
+        Value::String(s) => Kind::StringValue(s.clone()),
+        Value::Number(n) => Kind::NumberValue(*n),
+        Value::Bool(b) => Kind::BoolValue(*b),
+        Value::List(items) => Kind::ListValue(proto::ValueList {
+            values: items.iter().map(value_to_proto).collect(),
+        }),
+    };
+    proto::MetadataValue { kind: Some(kind) }
+}
+
+/// Metadata from a request; an empty map means none.
+fn metadata_from_proto(
+    fields: HashMap<String, proto::MetadataValue>,
+) -> Result<Option<Metadata>, String> {
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    fields
+        .into_iter()
+        .map(|(key, value)| match value_from_proto(value) {
+            Some(value) => Ok((key, value)),
+            None => Err(format!("metadata field {:?} has no value", key)),
+        })
+        .collect::<Result<Metadata, _>>()
+        .map(Some)
+}
+
+fn metadata_to_proto(meta: Option<&Metadata>) -> HashMap<String, proto::MetadataValue> {
+    meta.into_iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value_to_proto(value)))
+        .collect()
+}
+
+// ── Service ───────────────────────────────────────────────────────────────────
+
+/// The `vecbase.v1.VecBase` service over a shared database.
+pub struct GrpcService {
+    db: SharedDb,
+}
+
+impl GrpcService {
+    pub fn new(db: SharedDb) -> Self {
+        Self { db }
+    }
+
+    /// Wrap in the generated tonic server, accepting messages up to
+    /// [`MAX_BODY_BYTES`] like the HTTP API.
+    pub fn into_server(self) -> VecBaseServer<Self> {
+        VecBaseServer::new(self)
+            .max_decoding_message_size(MAX_BODY_BYTES)
+            .max_encoding_message_size(MAX_BODY_BYTES)
+    }
+
+    /// Run `f` against the database on the blocking pool.
+    async fn with_db<T, F>(&self, f: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&VecBase) -> Result<T, Status> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|e| Status::internal(format!("request handler failed: {}", e)))?
+    }
+
+    /// Insert one chunk of a bulk upsert, recording failures.
+    async fn upsert_chunk(
+        &self,
+        items: Vec<BatchInsert>,
+        response: &mut proto::BulkUpsertResponse,
+    ) -> Result<(), Status> {
+        let result = self
+            .with_db(move |db| Ok(batch_insert(db, items)))
+            .await?;
+        response.upserted += result.inserted as u64;
+        response.failed.extend(
+            result
+                .failed
+                .into_iter()
+                .map(|(id, error)| proto::BulkFailure { id, error }),
+        );
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl VecBaseRpc for GrpcService {
+    async fn insert(
+        &self,
+        request: Request<proto::UpsertRequest>,
+    ) -> Result<Response<proto::InsertResponse>, Status> {
+        let req = request.into_inner();
+        let metadata = metadata_from_proto(req.metadata).map_err(Status::invalid_argument)?;
+        let id = req.id;
+        self.with_db(move |db| {
+            db.insert(id.clone(), req.vector, metadata).map_err(status)?;
+            Ok(Response::new(proto::InsertResponse { id }))
+        })
+        .await
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchResponse>, Status> {
+        let req = request.into_inner();
+        let breadth = match (req.ef, req.nprobe) {
+            (Some(_), Some(_)) => {
+                return Err(Status::invalid_argument("set at most one of ef and nprobe"));
+            }
+            (ef, nprobe) => ef.or(nprobe),
+        };
+        self.with_db(move |db| {
+            if req.vector.len() != db.config.dim {
+                return Err(status(VecBaseError::DimensionMismatch {
+                    expected: db.config.dim,
+                    got: req.vector.len(),
+                }));
+            }
+            // Clamped like HTTP: no collection holds more than max_elements.
+            let top_k = match req.top_k {
+                0 => DEFAULT_TOP_K,
+                k => k as usize,
+            }
+            .min(db.config.max_elements);
+            let results = match breadth {
+                Some(breadth) => db.search_with_breadth(&req.vector, top_k, breadth as usize),
+                None => db.search(&req.vector, top_k),
+            };
+            let hits = results
+                .into_iter()
+                .map(|r| proto::Hit {
+                    metadata: metadata_to_proto(r.metadata.as_ref()),
+                    id: r.id,
+                    score: r.score,
+                })
+                .collect();
+            Ok(Response::new(proto::SearchResponse { hits }))
+        })
+        .await
+    }
+
+    async fn get(
+        &self,
+        request: Request<proto::GetRequest>,
+    ) -> Result<Response<proto::Record>, Status> {
+        let id = request.into_inner().id;
+        self.with_db(move |db| {
+            let (record, vector) = db
+                .get_with_vector(&id)
+                .map_err(status)?
+                .ok_or_else(|| status(VecBaseError::NotFound { id: id.clone() }))?;
+            Ok(Response::new(proto::Record {
+                vector,
+                metadata: metadata_to_proto(record.metadata.as_ref()),
+                id: record.id,
+            }))
+        })
+        .await
+    }
+
+    async fn delete(
+        &self,
+        request: Request<proto::DeleteRequest>,
+    ) -> Result<Response<proto::DeleteResponse>, Status> {
+        let id = request.into_inner().id;
+        self.with_db(move |db| {
+            db.delete(&id).map_err(status)?;
+            Ok(Response::new(proto::DeleteResponse {}))
+        })
+        .await
+    }
+
+    async fn bulk_upsert(
+        &self,
+        request: Request<Streaming<proto::UpsertRequest>>,
+    ) -> Result<Response<proto::BulkUpsertResponse>, Status> {
+        let mut stream = request.into_inner();
+        let mut response = proto::BulkUpsertResponse::default();
+        let mut chunk = Vec::with_capacity(BULK_CHUNK);
+        while let Some(item) = stream.message().await? {
+            match metadata_from_proto(item.metadata) {
+                Ok(metadata) => chunk.push(BatchInsert {
+                    id: item.id,
+                    vector: item.vector,
+                    metadata,
+                }),
+                Err(error) => response.failed.push(proto::BulkFailure { id: item.id, error }),
+            }
+            if chunk.len() >= BULK_CHUNK {
+                let full = std::mem::replace(&mut chunk, Vec::with_capacity(BULK_CHUNK));
+                self.upsert_chunk(full, &mut response).await?;
+            }
+        }
+        if !chunk.is_empty() {
+            self.upsert_chunk(chunk, &mut response).await?;
+        }
+        Ok(Response::new(response))
+    }
+}
+
+/// Serve the gRPC service on `listener` until `shutdown` resolves.
+///
+/// # Errors
+/// Returns the transport error if the server fails.
+pub async fn serve(
+    listener: TcpListener,
+    db: SharedDb,
+    shutdown: impl Future<Output = ()> + Send,
+) -> Result<(), tonic::transport::Error> {
+    if let Ok(addr) = listener.local_addr() {
+        log::info!("gRPC API listening on {}", addr);
+    }
+    tonic::transport::Server::builder()
+        .add_service(GrpcService::new(db).into_server())
+        .serve_with_incoming_shutdown(TcpIncoming::from(listener), shutdown)
+        .await
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VecBaseConfig;
+    use proto::vec_base_client::VecBaseClient;
+    use std::sync::Arc;
+    use tonic::transport::Channel;
+
+    /// Start a server on an ephemeral local port and connect a client to it.
+    async fn spawn_server(config: VecBaseConfig) -> VecBaseClient<Channel> {
+        let db: SharedDb = Arc::new(VecBase::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, db, std::future::pending()));
+        VecBaseClient::connect(format!("http://{}", addr)).await.unwrap()
+    }
+
+    fn upsert(id: &str, vector: Vec<f32>, meta: Option<Metadata>) -> proto::UpsertRequest {
+        proto::UpsertRequest {
+            id: id.into(),
+            vector,
+            metadata: metadata_to_proto(meta.as_ref()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grpc_api() {
+        let mut client = spawn_server(VecBaseConfig {
+            dim: 3,
+            metric: "euclidean".into(),
+            max_elements: 4,
+            ..VecBaseConfig::default()
+        })
+        .await;
+
+        let meta = crate::metadata! { "lang" => "en", "tags" => vec!["a", "b"] };
+        let res = client
+            .insert(upsert("a", vec![1.0, 0.0, 0.0], Some(meta.clone())))
+            .await
+            .unwrap();
+        assert_eq!(res.into_inner().id, "a");
+
+        let mut bad_meta = upsert("bad-meta", vec![1.0, 1.0, 1.0], None);
+        bad_meta.metadata.insert("x".into(), proto::MetadataValue { kind: None });
+        let items = vec![
+            upsert("b", vec![0.0, 1.0, 0.0], None),
+            upsert("c", vec![0.0, 0.0, 1.0], None),
+            upsert("short", vec![1.0], None),
+            bad_meta,
+        ];
+        let bulk = client
+            .bulk_upsert(tokio_stream::iter(items))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(bulk.upserted, 2);
+        let mut failed: Vec<&str> = bulk.failed.iter().map(|f| f.id.as_str()).collect();
+        failed.sort();
+        assert_eq!(failed, vec!["bad-meta", "short"]);
+
+        let hits = client
+            .search(proto::SearchRequest {
+                vector: vec![0.9, 0.1, 0.0],
+                top_k: 2,
+                ef: None,
+                nprobe: None,
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .hits;
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].id, "a");
+        assert_eq!(metadata_from_proto(hits[0].metadata.clone()), Ok(Some(meta.clone())));
+
+        let record = client
+            .get(proto::GetRequest { id: "a".into() })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(record.vector, vec![1.0, 0.0, 0.0]);
+        assert_eq!(metadata_from_proto(record.metadata), Ok(Some(meta)));
+
+        client.delete(proto::DeleteRequest { id: "a".into() }).await.unwrap();
+        let err = client.get(proto::GetRequest { id: "a".into() }).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+        let err = client
+            .delete(proto::DeleteRequest { id: "a".into() })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_grpc_error_codes() {
+        let mut client = spawn_server(VecBaseConfig {
+            dim: 2,
+            max_elements: 1,
+            ..VecBaseConfig::default()
+        })
+        .await;
+
+        let err = client.insert(upsert("x", vec![1.0], None)).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        client.insert(upsert("x", vec![1.0, 0.0], None)).await.unwrap();
+        let err = client.insert(upsert("y", vec![0.0, 1.0], None)).await.unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        assert!(err.message().contains("Capacity"));
+
+        let err = client
+            .search(proto::SearchRequest {
+                vector: vec![1.0, 2.0, 3.0],
+                top_k: 0,
+                ef: Some(8),
+                nprobe: None,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.message().contains("Dimension"));
+
+        // top_k = 0 falls back to the default.
+        let hits = client
+            .search(proto::SearchRequest {
+                vector: vec![1.0, 0.0],
+                top_k: 0,
+                ef: None,
+                nprobe: None,
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .hits;
+        assert_eq!(hits.len(), 1);
+
+        // An oversized top_k is clamped rather than sized into buffers.
+        let hits = client
+            .search(proto::SearchRequest {
+                vector: vec![1.0, 0.0],
+                top_k: u32::MAX,
+                ef: None,
+                nprobe: None,
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .hits;
+        assert_eq!(hits.len(), 1);
+
+        // nprobe aliases ef; setting both is ambiguous.
+        let hits = client
+            .search(proto::SearchRequest {
+                vector: vec![1.0, 0.0],
+                top_k: 1,
+                ef: None,
+                nprobe: Some(4),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .hits;
+        assert_eq!(hits.len(), 1);
+        let err = client
+            .search(proto::SearchRequest {
+                vector: vec![1.0, 0.0],
+                top_k: 1,
+                ef: Some(4),
+                nprobe: Some(4),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.message().contains("nprobe"));
+    }
+
+    #[test]
+    fn test_code_mapping() {
+        assert_eq!(code_for(&VecBaseError::NotFound { id: "x".into() }), Code::NotFound);
+        assert_eq!(
+            code_for(&VecBaseError::CollectionExists { name: "x".into() }),
+            Code::AlreadyExists
+        );
+        assert_eq!(code_for(&VecBaseError::StorageError("disk".into())), Code::Internal);
+    }
+}
@@ -0,0 +1,612 @@
+// VecBase — index.rs
+// Pluggable ANN indexes behind the `AnnIndex` trait, and their registry.
+// Author: d65v <https://github.com/d65v>
+//
+// `VecBase` talks to its index only through `AnnIndex`, and builds it from
+// `VecBaseConfig::index_type` through a registry of named index types, so a
+// new algorithm needs an `AnnIndex` impl and a `register` call, not edits to
+// core. Each type reads its own settings from `VecBaseConfig::index_params`
+// (see `parse_params`). Five ship with the crate, each exporting its
+// `INDEX_TYPE`:
+//
+//   - hnsw: HnswIndex, the graph index (default)
+//   - ivf:  IvfIndex, k-means posting lists
+//   - lsh:  LshIndex, locality-sensitive hash tables
+//   - flat: FlatIndex, an exact scan
+//   - pq:   PqIndex, product-quantized codes
+//
+// Snapshots store an index as its registered name plus the bytes from
+// `AnnIndex::to_bytes`; the name picks the loader on restore.
+
+use std::any::Any;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::{OnceLock, RwLock};
+
+use ordered_float::OrderedFloat;
+use serde::de::{DeserializeOwned, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::embedding::{rank_score, score_from_rank, Metric};
+use crate::quantize::Quantization;
+use crate::{flat, ivf, lsh, pq, processing};
+use crate::storage::storage_err;
+use crate::vectors::VectorStore;
+use crate::{Result, VecBaseConfig, VecBaseError};
+
+/// Work done ahead of an insert or remove by [`AnnIndex::plan_insert`] /
+/// [`AnnIndex::plan_remove`], handed back to the matching commit. Each
+/// index type decides what it holds.
+pub type IndexPlan = Box<dyn Any + Send + Sync>;
+
+// ── Trait ─────────────────────────────────────────────────────────────────────
+
+/// An approximate (or exact) nearest neighbor index over dense `u32` rows.
+///
+/// Every vector lives at a row, which `VecBase` uses to key its records and
+/// payload indexes. Rows of removed vectors may be reused. Vectors of cosine
+/// collections arrive normalized. Searches return (row, score) best first,
+/// with scores following [`score`](crate::embedding::score).
+///
+/// Implementations must be `Send + Sync` so a `VecBase` can be shared across
+/// threads.
+pub trait AnnIndex: Send + Sync {
+    /// Name this index type is registered under (see [`register`]).
+    fn kind(&self) -> &'static str;
+
+    /// Insert or replace `id`'s vector and return its row.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::CapacityExceeded` if a new id would not fit.
+    fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32>;
+
+    /// Remove `id`; a no-op if it is not indexed.
+    fn remove(&mut self, id: &str);
+
+    /// Top-k rows nearest to `query`. `breadth` is how hard to look (HNSW
+    /// beam width, IVF lists probed); `None` uses the index default.
+    fn search_rows(&self, query: &[f32], top_k: usize, breadth: Option<usize>) -> Vec<(u32, f32)>;
+
+    /// Number of indexed vectors.
+    fn len(&self) -> usize;
+
+    /// Serialize the index for a snapshot, to be read back by the `load`
+    /// function of its [`IndexType`].
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if encoding fails.
+    fn to_bytes(&self) -> Result<Vec<u8>>;
+
+    /// Row `id` is stored at.
+    fn row(&self, id: &str) -> Option<u32>;
+
+    /// Id stored at `row`, or `None` for free or out-of-range rows.
+    fn id_at(&self, row: u32) -> Option<&str>;
+
+    /// Vector stored at `row`, or `None` for free or out-of-range rows.
+    fn vector_at(&self, row: u32) -> Option<&[f32]>;
+
+    /// Dimensionality of indexed vectors.
+    fn dim(&self) -> usize;
+
+    /// Metric the index ranks by.
+    fn metric(&self) -> &Metric;
+
+    /// Approximate heap bytes held by the index.
+    fn heap_bytes(&self) -> usize;
+
+    /// `self`, for downcasting to the concrete type with
+    /// `<dyn AnnIndex>::downcast_ref`.
+    fn as_any(&self) -> &dyn Any;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `id` is indexed.
+    fn contains(&self, id: &str) -> bool {
+        self.row(id).is_some()
+    }
+
+    /// Stored vector for `id`.
+    fn vector(&self, id: &str) -> Option<&[f32]> {
+        self.row(id).and_then(|r| self.vector_at(r))
+    }
+
+    /// Top-k rows for which `accept` returns true. Fewer than `top_k`
+    /// results should mean fewer than `top_k` rows are accepted.
+    ///
+    /// The default over-fetches from [`search_rows`](Self::search_rows),
+    /// doubling until `top_k` rows pass or the whole index was returned.
+    /// Indexes that can apply the filter while searching should override it.
+    fn search_rows_filtered(&self, query: &[f32], top_k: usize, accept: &dyn Fn(u32) -> bool) -> Vec<(u32, f32)> {
+        let mut fetch = top_k.max(1);
+        loop {
+            let found = self.search_rows(query, fetch, None);
+            let exhausted = found.len() < fetch || fetch >= self.len();
+            let mut kept: Vec<(u32, f32)> = found.into_iter().filter(|&(r, _)| accept(r)).collect();
+            if kept.len() >= top_k || exhausted {
+                kept.truncate(top_k);
+                return kept;
+            }
+            fetch = fetch.saturating_mul(2);
+        }
+    }
+
+    /// Exact top-k over just `rows`, e.g. a candidate set from a payload
+    /// index. Free rows are skipped.
+    fn search_rows_among(&self, query: &[f32], top_k: usize, rows: &mut dyn Iterator<Item = u32>) -> Vec<(u32, f32)> {
+        let mut best = TopK::new(top_k);
+        for r in rows {
+            if let Some(v) = self.vector_at(r) {
+                best.push(r, rank_score(self.metric(), query, v));
+            }
+        }
+        to_scores(self.metric(), best.into_sorted())
+    }
+
+    /// Insert many vectors, returning the row of each item in input order.
+    /// The default inserts one by one.
+    ///
+    /// # Errors
+    /// As for [`insert`](Self::insert). Items before the failing one stay
+    /// inserted.
+    fn insert_bulk(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<Vec<u32>> {
+        items.into_iter().map(|(id, vector)| self.insert(id, vector)).collect()
+    }
+
+    /// Make room for `additional` more vectors, so the next that many
+    /// inserts cannot fail for lack of space. `VecBase` calls this before
+    /// it logs a write. The default does nothing.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the vector store cannot grow.
+    fn reserve(&mut self, _additional: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// First half of an insert: expensive work that only needs a shared
+    /// borrow, so `VecBase` can run it while searches continue. The default
+    /// plans nothing.
+    fn plan_insert(&self, _id: &str, _vector: &[f32]) -> Option<IndexPlan> {
+        None
+    }
+
+    /// Second half of an insert, given what [`plan_insert`](Self::plan_insert)
+    /// returned. Implementations must cope with a plan made before some
+    /// other change to the index.
+    ///
+    /// # Errors
+    /// As for [`insert`](Self::insert).
+    fn commit_insert(&mut self, id: String, vector: Vec<f32>, _plan: Option<IndexPlan>) -> Result<u32> {
+        self.insert(id, vector)
+    }
+
+    /// First half of a remove, like [`plan_insert`](Self::plan_insert).
+    fn plan_remove(&self, _id: &str) -> Option<IndexPlan> {
+        None
+    }
+
+    /// Second half of a remove, given what [`plan_remove`](Self::plan_remove)
+    /// returned.
+    fn commit_remove(&mut self, id: &str, _plan: Option<IndexPlan>) {
+        self.remove(id)
+    }
+
+    /// Flush any disk-backed storage (no-op by default).
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the flush fails.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once a checkpoint snapshot of the index is on disk. Rows
+    /// freed before it may be overwritten from now on (no-op by default;
+    /// see [`FreeRows`](crate::vectors::FreeRows)).
+    fn release_free_rows(&mut self) {}
+
+    /// Whether [`set_quantization`](Self::set_quantization) has any effect.
+    /// `VecBase` rejects `vector_quantization` for indexes that return
+    /// false (the default).
+    fn supports_quantization(&self) -> bool {
+        false
+    }
+
+    /// Keep a quantized copy of the vectors for searches to score (see
+    /// [`quantize`](crate::quantize)). Indexes that always score full
+    /// vectors ignore it.
+    fn set_quantization(&mut self, _quantization: Quantization, _rescore: bool) {}
+}
+
+impl dyn AnnIndex {
+    /// The concrete index, if it is a `T`.
+    pub fn downcast_ref<T: AnnIndex + 'static>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+}
+
+// ── Registry ──────────────────────────────────────────────────────────────────
+
+/// A named index type `VecBaseConfig::index_type` can select.
+#[derive(Clone, Copy)]
+pub struct IndexType {
+    /// Value of `index_type` that selects it, and [`AnnIndex::kind`] of the
+    /// indexes it builds
+    pub name: &'static str,
+    /// An empty index for `config`, over `vectors`
+    pub build: fn(&VecBaseConfig, Metric, VectorStore) -> Box<dyn AnnIndex>,
+    /// An index from the bytes of [`AnnIndex::to_bytes`]
+    pub load: fn(&[u8]) -> Result<Box<dyn AnnIndex>>,
+}
+
+/// Index types registered before any [`register`] call.
+const BUILT_IN: [IndexType; 5] = [
+    processing::INDEX_TYPE,
+    ivf::INDEX_TYPE,
+    lsh::INDEX_TYPE,
+    flat::INDEX_TYPE,
+    pq::INDEX_TYPE,
+];
+
+fn registry() -> &'static RwLock<Vec<IndexType>> {
+    static REGISTRY: OnceLock<RwLock<Vec<IndexType>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(BUILT_IN.to_vec()))
+}
+
+/// Make `index_type` selectable by name, replacing any type already
+/// registered under it. Register before opening collections that use it.
+pub fn register(index_type: IndexType) {
+    let mut types = registry().write().unwrap_or_else(|e| e.into_inner());
+    types.retain(|t| t.name != index_type.name);
+    types.push(index_type);
+}
+
+/// An empty index of the type `config.index_type` names, over `vectors`.
+pub fn build(config: &VecBaseConfig, metric: Metric, vectors: VectorStore) -> Box<dyn AnnIndex> {
+    let index_type = lookup(config.index_kind()).unwrap_or(processing::INDEX_TYPE);
+    (index_type.build)(config, metric, vectors)
+}
+
+/// The index type registered as `name`.
+pub fn lookup(name: &str) -> Option<IndexType> {
+    let types = registry().read().unwrap_or_else(|e| e.into_inner());
+    types.iter().find(|t| t.name == name).copied()
+}
+
+/// Names of every registered index type.
+pub fn registered() -> Vec<&'static str> {
+    let types = registry().read().unwrap_or_else(|e| e.into_inner());
+    types.iter().map(|t| t.name).collect()
+}
+
+// ── Parameters ────────────────────────────────────────────────────────────────
+
+/// Settings for the configured index type, keyed by name. Each type reads
+/// the keys it knows with [`parse_params`].
+pub type IndexParams = BTreeMap<String, serde_json::Value>;
+
+/// `config.index_params` as `T`, for an [`IndexType::build`]. `T` should
+/// derive `Deserialize` with `#[serde(default)]`, so missing keys take its
+/// defaults. Unknown keys are ignored; params that do not fit `T` are
+/// logged and replaced by `T::default()`.
+pub fn parse_params<T: DeserializeOwned + Default>(config: &VecBaseConfig) -> T {
+    let object = serde_json::Value::Object(config.index_params.clone().into_iter().collect());
+    serde_json::from_value(object).unwrap_or_else(|e| {
+        log::warn!("{} index_params: {}, using defaults", config.index_type, e);
+        T::default()
+    })
+}
+
+/// Serde for [`IndexParams`]: a map in human-readable formats such as
+/// `collection.json`, a JSON string in snapshots, since bincode cannot
+/// decode a `serde_json::Value`.
+pub(crate) mod params_format {
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::IndexParams;
+
+    pub fn serialize<S: Serializer>(params: &IndexParams, s: S) -> Result<S::Ok, S::Error> {
+        if s.is_human_readable() {
+            params.serialize(s)
+        } else {
+            serde_json::to_string(params).map_err(S::Error::custom)?.serialize(s)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<IndexParams, D::Error> {
+        if d.is_human_readable() {
+            IndexParams::deserialize(d)
+        } else {
+            serde_json::from_str(&String::deserialize(d)?).map_err(D::Error::custom)
+        }
+    }
+}
+
+// ── Encoding ──────────────────────────────────────────────────────────────────
+
+/// [`IndexType::load`] for indexes that derive `Deserialize` and encode
+/// themselves with [`encode`].
+///
+/// # Errors
+/// Returns `VecBaseError::StorageError` if the bytes do not decode.
+pub fn decode<T: AnnIndex + DeserializeOwned + 'static>(bytes: &[u8]) -> Result<Box<dyn AnnIndex>> {
+    let index: T = bincode::deserialize(bytes).map_err(|e| storage_err("decode index", e))?;
+    Ok(Box::new(index))
+}
+
+/// [`AnnIndex::to_bytes`] for indexes that derive `Serialize`.
+///
+/// # Errors
+/// Returns `VecBaseError::StorageError` if encoding fails.
+pub fn encode<T: Serialize>(index: &T) -> Result<Vec<u8>> {
+    bincode::serialize(index).map_err(|e| storage_err("encode index", e))
+}
+
+// ── Snapshot Form ─────────────────────────────────────────────────────────────
+
+/// Writes an index into a snapshot as (kind, bytes).
+pub(crate) struct IndexRef<'a>(pub &'a dyn AnnIndex);
+
+impl Serialize for IndexRef<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        let bytes = self.0.to_bytes().map_err(serde::ser::Error::custom)?;
+        let mut tuple = s.serialize_tuple(2)?;
+        tuple.serialize_element(self.0.kind())?;
+        tuple.serialize_element(&Bytes(bytes))?;
+        tuple.end()
+    }
+}
+
+/// An index read from a snapshot, not yet decoded.
+#[derive(Deserialize)]
+pub(crate) struct StoredIndex {
+    pub kind: String,
+    bytes: Bytes,
+}
+
+impl StoredIndex {
+    /// Decode with the loader registered for `kind`.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if no index type is registered
+    /// as `kind` or the bytes do not decode.
+    pub fn load(self) -> Result<Box<dyn AnnIndex>> {
+        let index_type = lookup(&self.kind).ok_or_else(|| {
+            VecBaseError::StorageError(format!("snapshot holds an unregistered {:?} index", self.kind))
+        })?;
+        (index_type.load)(&self.bytes.0)
+    }
+}
+
+/// A byte buffer encoded as one length-prefixed blob rather than a
+/// sequence of `u8`s.
+struct Bytes(Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl Visitor<'_> for BytesVisitor {
+            type Value = Bytes;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a byte buffer")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> std::result::Result<Bytes, E> {
+                Ok(Bytes(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> std::result::Result<Bytes, E> {
+                Ok(Bytes(v))
+            }
+        }
+
+        d.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Bounded min-heap keeping the `k` best (rank score, row) pairs.
+pub(crate) struct TopK {
+    k: usize,
+    heap: BinaryHeap<Reverse<(OrderedFloat<f32>, u32)>>,
+}
+
+impl TopK {
+    /// Grows as rows are pushed: `k` comes from callers and may be far
+    /// larger than the number of rows.
+    pub(crate) fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, row: u32, score: f32) {
+        let s = OrderedFloat(score);
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse((s, row)));
+        } else if self.heap.peek().is_some_and(|w| s > w.0 .0) {
+            self.heap.pop();
+            self.heap.push(Reverse((s, row)));
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.heap.len() >= self.k
+    }
+
+    /// Best first.
+    pub(crate) fn into_sorted(self) -> Vec<(u32, f32)> {
+        self.heap.into_sorted_vec().into_iter().map(|Reverse((s, r))| (r, s.into_inner())).collect()
+    }
+}
+
+/// Convert rank scores (see [`rank_score`]) to public scores.
+pub(crate) fn to_scores(metric: &Metric, mut found: Vec<(u32, f32)>) -> Vec<(u32, f32)> {
+    for (_, s) in found.iter_mut() {
+        *s = score_from_rank(metric, *s);
+    }
+    found
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat::FlatIndex;
+    use crate::VecBase;
+
+    /// An index defined outside the crate's built-ins, relying on every
+    /// provided method.
+    #[derive(Serialize, Deserialize)]
+    struct Plain(FlatIndex);
+
+    impl AnnIndex for Plain {
+        fn kind(&self) -> &'static str {
+            "plain"
+        }
+        fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+            self.0.insert(id, vector)
+        }
+        fn remove(&mut self, id: &str) {
+            self.0.remove(id)
+        }
+        fn search_rows(&self, query: &[f32], top_k: usize, breadth: Option<usize>) -> Vec<(u32, f32)> {
+            self.0.search_rows(query, top_k, breadth)
+        }
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+        fn to_bytes(&self) -> Result<Vec<u8>> {
+            encode(self)
+        }
+        fn row(&self, id: &str) -> Option<u32> {
+            self.0.row(id)
+        }
+        fn id_at(&self, row: u32) -> Option<&str> {
+            self.0.id_at(row)
+        }
+        fn vector_at(&self, row: u32) -> Option<&[f32]> {
+            self.0.vector_at(row)
+        }
+        fn dim(&self) -> usize {
+            self.0.dim()
+        }
+        fn metric(&self) -> &Metric {
+            self.0.metric()
+        }
+        fn heap_bytes(&self) -> usize {
+            self.0.heap_bytes()
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_registered_index_type_backs_a_collection() {
+        register(IndexType {
+            name: "plain",
+            build: |config, metric, vectors| {
+                Box::new(Plain(FlatIndex::with_store(config.max_elements, metric, vectors)))
+            },
+            load: decode::<Plain>,
+        });
+        assert!(registered().contains(&"plain"));
+        assert!(lookup("missing").is_none());
+
+        let config = VecBaseConfig {
+            dim: 2,
+            metric: "euclidean".into(),
+            index_type: "plain".into(),
+            ..VecBaseConfig::default()
+        };
+        let db = VecBase::new(config);
+        for i in 0..20 {
+            db.insert(format!("v{}", i), vec![i as f32, 0.0], None).unwrap();
+        }
+        db.delete("v3").unwrap();
+
+        let path = crate::storage::tests::scratch_dir("index-plain").join("db.snap");
+        db.save(&path).unwrap();
+        let db = VecBase::load(&path).unwrap();
+        let state = db.read();
+        assert!(state.index.downcast_ref::<Plain>().is_some());
+
+        // Default filtered search: over-fetches until enough rows pass.
+        let odd = state.index.search_rows_filtered(&[0.0, 0.0], 3, &|r| r % 2 == 1);
+        let ids: Vec<&str> = odd.iter().map(|&(r, _)| state.index.id_at(r).unwrap()).collect();
+        assert_eq!(ids, ["v1", "v5", "v7"]);
+
+        // Default exact scan over given rows skips freed ones.
+        let among = state.index.search_rows_among(&[0.0, 0.0], 5, &mut (0..5));
+        let ids: Vec<&str> = among.iter().map(|&(r, _)| state.index.id_at(r).unwrap()).collect();
+        assert_eq!(ids, ["v0", "v1", "v2", "v4"]);
+    }
+
+    #[test]
+    fn test_parse_params_fills_defaults_and_survives_snapshots() {
+        let config = VecBaseConfig {
+            index_type: "ivf".into(),
+            index_params: [("nprobe".into(), 3.into())].into(),
+            ..VecBaseConfig::default()
+        };
+        let params: crate::ivf::IvfParams = parse_params(&config);
+        assert_eq!(params.nprobe, 3);
+        assert_eq!(params.nlist, crate::ivf::IvfParams::default().nlist);
+
+        // bincode cannot encode JSON values directly, see `params_format`.
+        let bytes = bincode::serialize(&config).unwrap();
+        let back: VecBaseConfig = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(back.index_params, config.index_params);
+
+        // A malformed value falls back to the defaults instead of failing.
+        let config = VecBaseConfig {
+            index_params: [("nprobe".into(), "many".into())].into(),
+            ..config
+        };
+        let params: crate::ivf::IvfParams = parse_params(&config);
+        assert_eq!(params, crate::ivf::IvfParams::default());
+    }
+
+    #[test]
+    fn test_huge_top_k_returns_every_row() {
+        for index_type in ["flat", "ivf", "lsh", "pq"] {
+            let db = VecBase::new(VecBaseConfig {
+                dim: 2,
+                index_type: index_type.into(),
+                ..VecBaseConfig::default()
+            });
+            for i in 0..10 {
+                db.insert(format!("v{}", i), vec![1.0, i as f32], None).unwrap();
+            }
+            assert_eq!(db.search(&[1.0, 0.0], 4_000_000_000).len(), 10, "{}", index_type);
+            let state = db.read();
+            let among = state.index.search_rows_among(&[1.0, 0.0], usize::MAX, &mut (0..10));
+            assert_eq!(among.len(), 10, "{}", index_type);
+        }
+    }
+
+    #[test]
+    fn test_unregistered_snapshot_kind_is_an_error() {
+        let stored = StoredIndex {
+            kind: "missing".into(),
+            bytes: Bytes(Vec::new()),
+        };
+        assert!(matches!(stored.load(), Err(VecBaseError::StorageError(_))));
+    }
+}
@@ -0,0 +1,758 @@
+// VecBase — ivf.rs
+// Inverted-file (IVF) index: k-means coarse centroids over posting lists.
+// Author: d65v <https://github.com/d65v>
+//
+// NOTE: IVF-Flat (Sivic & Zisserman, 2003; Jégou et al., 2011). The vector
+// space is cut into `nlist` Voronoi cells around k-means centroids, and each
+// vector is filed in the posting list of its closest centroid. A query ranks
+// the centroids, then scans only the `nprobe` closest lists exactly. Beyond
+// the vectors themselves the index holds one centroid per list and 12 bytes
+// per vector, against up to 2·M 4-byte neighbor rows per vector in HNSW's
+// layer 0 alone.
+//
+// Centroids need data to train on, so a fresh index is searched by brute
+// force until it holds `nlist · TRAIN_MIN_PER_LIST` vectors, then trains
+// itself. It retrains (and refiles every vector) each time it grows 4×, until
+// the training sample is capped at `nlist · TRAIN_MAX_PER_LIST` vectors.
+// Inserts and removes never touch the other vectors: a remove is a
+// swap-remove in one posting list.
+//
+// Training is O(N · nlist · iters). Through `AnnIndex`, the insert that
+// crosses a threshold runs k-means and refiles every vector in
+// `plan_insert` (shared borrow), so `VecBase` searches keep running; only
+// swapping in the new lists happens in `commit_insert`.
+
+use std::any::Any;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::{normalize, rank_score, Metric};
+use crate::index::{self, to_scores, AnnIndex, IndexPlan, IndexType, TopK};
+use crate::pq::kmeans;
+use crate::processing::IdTable;
+use crate::vectors::{FreeRows, VectorStore};
+use crate::{Result, VecBaseConfig, VecBaseError};
+
+// ── IVF Index ─────────────────────────────────────────────────────────────────
+
+/// Training and search parameters for [`IvfIndex`], read from
+/// `index_params` (e.g. `{"nlist": 1024, "nprobe": 16}`).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct IvfParams {
+    /// Number of posting lists (k-means centroids)
+    pub nlist: usize,
+    /// Default number of closest lists scanned per query
+    pub nprobe: usize,
+    /// Lloyd iterations per training run
+    pub train_iters: usize,
+}
+
+impl Default for IvfParams {
+    fn default() -> Self {
+        Self {
+            nlist: 256,
+            nprobe: 8,
+            train_iters: 10,
+        }
+    }
+}
+
+/// An untrained index trains itself once it holds this many vectors per list.
+pub const TRAIN_MIN_PER_LIST: usize = 39;
+
+/// Training samples at most this many vectors per list.
+pub const TRAIN_MAX_PER_LIST: usize = 256;
+
+/// The index retrains whenever it has grown this many times past the size
+/// it was last trained at, until the training sample is capped.
+pub const RETRAIN_GROWTH: usize = 4;
+
+/// `slots` entry of a row that is in no posting list.
+const UNLISTED: (u32, u32) = (u32::MAX, u32::MAX);
+
+/// An inverted-file approximate nearest neighbor index. Same row, id and
+/// score conventions as [`HnswIndex`](crate::processing::HnswIndex):
+/// vectors live at dense `u32` rows of a [`VectorStore`], cosine assumes
+/// pre-normalized vectors, and freed rows are reused.
+#[derive(Serialize, Deserialize)]
+pub struct IvfIndex {
+    dim: usize,
+    max_elements: usize,
+    metric: Metric,
+    nlist: usize,
+    nprobe: usize,
+    train_iters: usize,
+    vectors: VectorStore,
+    /// External id ↔ row
+    ids: IdTable,
+    /// Free rows, reused before the store grows
+    free: FreeRows,
+    /// `nlist · dim` coarse centroids; empty until trained
+    centroids: Vec<f32>,
+    /// Rows filed under each centroid
+    lists: Vec<Vec<u32>>,
+    /// Row → (list, position in that list), [`UNLISTED`] for free rows and
+    /// for every row while untrained
+    slots: Vec<(u32, u32)>,
+    /// Number of vectors the centroids were last trained on (0 = untrained)
+    trained_on: usize,
+    /// Bumped by every mutation, so stale training plans are detected
+    #[serde(skip)]
+    version: u64,
+}
+
+/// New centroids and posting lists for a pending (re)train, from
+/// [`IvfIndex::plan_training`].
+#[derive(Debug)]
+pub struct TrainPlan {
+    /// Index version the plan was made against
+    version: u64,
+    centroids: Vec<f32>,
+    /// Closest list of every stored row
+    assigned: Vec<(u32, usize)>,
+}
+
+impl IvfIndex {
+    pub fn new(dim: usize, max_elements: usize, metric: Metric) -> Self {
+        Self::with_params(dim, max_elements, metric, IvfParams::default())
+    }
+
+    /// Create an index with explicit parameters. `nlist` and `nprobe` are
+    /// clamped to at least 1.
+    pub fn with_params(dim: usize, max_elements: usize, metric: Metric, params: IvfParams) -> Self {
+        Self::with_store(max_elements, metric, params, VectorStore::memory(dim))
+    }
+
+    /// Create an empty index over an explicit vector store, e.g. a
+    /// memory-mapped one from [`VectorStore::mmap`]. Any rows already in
+    /// the store are treated as free and will be overwritten.
+    pub fn with_store(max_elements: usize, metric: Metric, params: IvfParams, vectors: VectorStore) -> Self {
+        let allocated = vectors.len() as u32;
+        Self {
+            dim: vectors.dim(),
+            max_elements,
+            metric,
+            nlist: params.nlist.max(1),
+            nprobe: params.nprobe.max(1),
+            train_iters: params.train_iters,
+            ids: IdTable::with_free_rows(allocated as usize),
+            free: FreeRows::all(&vectors),
+            slots: vec![UNLISTED; allocated as usize],
+            vectors,
+            centroids: Vec::new(),
+            lists: Vec::new(),
+            trained_on: 0,
+            version: 0,
+        }
+    }
+
+    /// Insert a new vector and return the row it was stored at.
+    /// Re-inserting an existing id replaces its vector. May train or
+    /// retrain the centroids (see the module notes).
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::CapacityExceeded` if the index already holds
+    /// `max_elements` vectors, or `VecBaseError::StorageError` if the vector
+    /// store cannot grow.
+    pub fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        debug_assert_eq!(
+            vector.len(),
+            self.dim,
+            "insert: vector dim {} ≠ index dim {}",
+            vector.len(),
+            self.dim
+        );
+
+        // The new vector goes through a free row like any other, so a mapped
+        // store keeps the old one for the last checkpoint (see `FreeRows`).
+        self.remove(&id);
+        if self.ids.len() >= self.max_elements {
+            return Err(VecBaseError::CapacityExceeded {
+                max_elements: self.max_elements,
+            });
+        }
+
+        let row = match self.free.pop() {
+            Some(r) => {
+                self.vectors.set(r, &vector);
+                r
+            }
+            None => {
+                let r = self.vectors.push(&vector)?;
+                self.slots.push(UNLISTED);
+                r
+            }
+        };
+        self.ids.insert(row, &id);
+        self.file(row);
+        self.version += 1;
+        if self.needs_training(self.ids.len()) {
+            self.train();
+        }
+        Ok(row)
+    }
+
+    /// Like [`insert`](Self::insert), but first swaps in the centroids of
+    /// `plan` (see [`plan_insert`](Self::plan_insert)) if the index has not
+    /// changed since it was made.
+    ///
+    /// # Errors
+    /// As for [`insert`](Self::insert).
+    pub fn commit_insert(&mut self, id: String, vector: Vec<f32>, plan: Option<TrainPlan>) -> Result<u32> {
+        if let Some(plan) = plan {
+            self.commit_training(plan);
+        }
+        self.insert(id, vector)
+    }
+
+    /// First half of an insert of `id`: if it would make the index
+    /// (re)train, do the training now, under a shared borrow.
+    pub fn plan_insert(&self, id: &str) -> Option<TrainPlan> {
+        let len = self.ids.len() + usize::from(self.ids.row(id).is_none());
+        if self.needs_training(len) {
+            self.plan_training()
+        } else {
+            None
+        }
+    }
+
+    /// Insert many vectors, returning the row of each item in input order.
+    /// Inserts are already cheap, so this just inserts one by one.
+    ///
+    /// # Errors
+    /// As for [`insert`](Self::insert). Items before the failing one stay
+    /// inserted.
+    pub fn insert_bulk(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<Vec<u32>> {
+        items.into_iter().map(|(id, vector)| self.insert(id, vector)).collect()
+    }
+
+    /// Remove `id`; its row is reused by a later insert. O(1): only its own
+    /// posting list changes.
+    pub fn remove(&mut self, id: &str) {
+        if let Some(row) = self.ids.row(id) {
+            self.unlist(row);
+            self.ids.remove_row(row);
+            self.free.push(row, &self.vectors);
+            self.version += 1;
+        }
+    }
+
+    /// (Re)train the centroids on a sample of the stored vectors and refile
+    /// every vector. Happens automatically as the index grows; call it after
+    /// heavy churn has shifted the data away from the current centroids.
+    /// Does nothing on an empty index.
+    pub fn train(&mut self) {
+        if let Some(plan) = self.plan_training() {
+            self.commit_training(plan);
+        }
+    }
+
+    /// The expensive part of [`train`](Self::train): k-means over a sample
+    /// and the closest list of every vector. `None` on an empty index.
+    pub fn plan_training(&self) -> Option<TrainPlan> {
+        let mut rows: Vec<u32> = self.ids.rows().collect();
+        if rows.is_empty() {
+            return None;
+        }
+        // Sorted first so the sample does not depend on hash order.
+        rows.sort_unstable();
+        let mut rng = StdRng::seed_from_u64(0x5eed_1f00 + rows.len() as u64);
+        let take = rows.len().min(self.nlist * TRAIN_MAX_PER_LIST);
+        for i in 0..take {
+            let j = rng.gen_range(i..rows.len());
+            rows.swap(i, j);
+        }
+        let sample: Vec<&[f32]> = rows[..take].iter().map(|&r| self.vectors.get(r)).collect();
+        let mut centroids = kmeans(&sample, self.dim, self.nlist, self.train_iters, 0x5eed_1f00);
+        if matches!(self.metric, Metric::Cosine) {
+            for c in centroids.chunks_exact_mut(self.dim) {
+                let unit = normalize(c);
+                c.copy_from_slice(&unit);
+            }
+        }
+        let assigned = rows
+            .par_iter()
+            .map(|&r| (r, closest_list(&self.metric, &centroids, self.vectors.get(r))))
+            .collect();
+        Some(TrainPlan {
+            version: self.version,
+            centroids,
+            assigned,
+        })
+    }
+
+    /// Swap in the centroids and lists of `plan`. Does nothing if the index
+    /// changed since the plan was made.
+    pub fn commit_training(&mut self, plan: TrainPlan) {
+        if plan.version != self.version {
+            return;
+        }
+        self.centroids = plan.centroids;
+        self.trained_on = self.ids.len();
+        self.lists = vec![Vec::new(); self.nlist];
+        self.slots.fill(UNLISTED);
+        for (row, list) in plan.assigned {
+            self.slots[row as usize] = (list as u32, self.lists[list].len() as u32);
+            self.lists[list].push(row);
+        }
+        self.version += 1;
+    }
+
+    /// Whether the centroids should be (re)trained once the index holds
+    /// `len` vectors.
+    fn needs_training(&self, len: usize) -> bool {
+        if self.trained_on == 0 {
+            return len >= self.nlist * TRAIN_MIN_PER_LIST;
+        }
+        self.trained_on < self.nlist * TRAIN_MAX_PER_LIST && len >= self.trained_on * RETRAIN_GROWTH
+    }
+
+    /// File `row` under its closest centroid (no-op while untrained).
+    fn file(&mut self, row: u32) {
+        if self.centroids.is_empty() {
+            return;
+        }
+        let list = self.closest_list(self.vectors.get(row));
+        self.slots[row as usize] = (list as u32, self.lists[list].len() as u32);
+        self.lists[list].push(row);
+    }
+
+    /// Take `row` out of its posting list, moving the list's last row into
+    /// its place.
+    fn unlist(&mut self, row: u32) {
+        let (list, pos) = std::mem::replace(&mut self.slots[row as usize], UNLISTED);
+        if (list, pos) == UNLISTED {
+            return;
+        }
+        let members = &mut self.lists[list as usize];
+        members.swap_remove(pos as usize);
+        if let Some(&moved) = members.get(pos as usize) {
+            self.slots[moved as usize].1 = pos;
+        }
+    }
+
+    /// Posting list whose centroid ranks best against `v`.
+    fn closest_list(&self, v: &[f32]) -> usize {
+        closest_list(&self.metric, &self.centroids, v)
+    }
+
+    /// All posting lists, closest centroid to `query` first.
+    fn probe_order(&self, query: &[f32]) -> Vec<usize> {
+        let mut order: Vec<(usize, f32)> = self
+            .centroids
+            .chunks_exact(self.dim)
+            .map(|c| rank_score(&self.metric, query, c))
+            .enumerate()
+            .collect();
+        order.sort_by(|a, b| b.1.total_cmp(&a.1));
+        order.into_iter().map(|(i, _)| i).collect()
+    }
+
+    /// Top-k nearest neighbors of `query`, scanning the default `nprobe`
+    /// lists.
+    pub fn search(&self, query: &[f32], top_k: usize) -> Vec<(String, f32)> {
+        self.search_with_nprobe(query, top_k, self.nprobe)
+    }
+
+    /// Like [`search`](Self::search), but scanning `nprobe` lists. Larger
+    /// `nprobe` trades latency for recall; `nprobe ≥ nlist` is exact.
+    pub fn search_with_nprobe(&self, query: &[f32], top_k: usize, nprobe: usize) -> Vec<(String, f32)> {
+        self.search_rows(query, top_k, nprobe)
+            .into_iter()
+            .filter_map(|(r, s)| self.ids.id(r).map(|id| (id.to_string(), s)))
+            .collect()
+    }
+
+    /// Like [`search_with_nprobe`](Self::search_with_nprobe), but returns
+    /// rows so callers can resolve ids and vectors without copying them.
+    pub fn search_rows(&self, query: &[f32], top_k: usize, nprobe: usize) -> Vec<(u32, f32)> {
+        self.search_rows_filtered_with(query, top_k, nprobe, |_| true)
+    }
+
+    /// Search restricted to rows for which `accept` returns true, scanning
+    /// the default `nprobe` lists.
+    ///
+    /// Rejected rows never enter the result set, and when the probed lists
+    /// hold fewer than `top_k` accepted rows the next closest lists are
+    /// scanned too, so fewer than `top_k` results means fewer than `top_k`
+    /// rows match.
+    pub fn search_rows_filtered<F: Fn(u32) -> bool>(&self, query: &[f32], top_k: usize, accept: F) -> Vec<(u32, f32)> {
+        self.search_rows_filtered_with(query, top_k, self.nprobe, accept)
+    }
+
+    fn search_rows_filtered_with<F: Fn(u32) -> bool>(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        nprobe: usize,
+        accept: F,
+    ) -> Vec<(u32, f32)> {
+        if top_k == 0 {
+            return vec![];
+        }
+        let mut best = TopK::new(top_k);
+        if self.centroids.is_empty() {
+            for r in self.ids.rows().filter(|&r| accept(r)) {
+                best.push(r, rank_score(&self.metric, query, self.vectors.get(r)));
+            }
+            return to_scores(&self.metric, best.into_sorted());
+        }
+
+        for (probed, list) in self.probe_order(query).into_iter().enumerate() {
+            if probed >= nprobe.max(1) && best.is_full() {
+                break;
+            }
+            for &r in self.lists[list].iter().filter(|&&r| accept(r)) {
+                best.push(r, rank_score(&self.metric, query, self.vectors.get(r)));
+            }
+        }
+        to_scores(&self.metric, best.into_sorted())
+    }
+
+    /// Exact top-k over just `rows`, e.g. a candidate set from a payload
+    /// index. Free rows are skipped.
+    pub fn search_rows_among(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        rows: impl IntoIterator<Item = u32>,
+    ) -> Vec<(u32, f32)> {
+        let mut best = TopK::new(top_k);
+        for r in rows.into_iter().filter(|&r| self.ids.id(r).is_some()) {
+            best.push(r, rank_score(&self.metric, query, self.vectors.get(r)));
+        }
+        to_scores(&self.metric, best.into_sorted())
+    }
+
+    /// Number of indexed vectors.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.len() == 0
+    }
+
+    /// Whether `id` is indexed.
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Stored vector for `id`, read straight from the vector store.
+    pub fn vector(&self, id: &str) -> Option<&[f32]> {
+        self.ids.row(id).map(|r| self.vectors.get(r))
+    }
+
+    /// Row `id` is stored at.
+    pub fn row(&self, id: &str) -> Option<u32> {
+        self.ids.row(id)
+    }
+
+    /// Id stored at `row`, or `None` for free or out-of-range rows.
+    pub fn id_at(&self, row: u32) -> Option<&str> {
+        self.ids.id(row)
+    }
+
+    /// Vector stored at `row`, or `None` for free or out-of-range rows.
+    pub fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        self.ids.id(row).map(|_| self.vectors.get(row))
+    }
+
+    /// Approximate heap bytes held by vectors, ids, centroids and lists.
+    pub fn heap_bytes(&self) -> usize {
+        let lists: usize = self.lists.iter().map(|l| l.capacity() * 4).sum();
+        self.vectors.heap_bytes()
+            + self.ids.heap_bytes()
+            + self.centroids.capacity() * 4
+            + self.lists.capacity() * std::mem::size_of::<Vec<u32>>()
+            + lists
+            + self.slots.capacity() * 8
+            + self.free.heap_bytes()
+    }
+
+    /// Flush the vector store to disk (no-op when held in memory).
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the flush fails.
+    pub fn flush(&self) -> Result<()> {
+        self.vectors.flush()
+    }
+
+    /// Dimensionality of indexed vectors.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Metric vectors are filed and ranked by.
+    pub fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    /// Parameters this index was created with.
+    pub fn params(&self) -> IvfParams {
+        IvfParams {
+            nlist: self.nlist,
+            nprobe: self.nprobe,
+            train_iters: self.train_iters,
+        }
+    }
+
+    /// Whether centroids have been trained (before that, searches are exact).
+    pub fn is_trained(&self) -> bool {
+        !self.centroids.is_empty()
+    }
+}
+
+impl AnnIndex for IvfIndex {
+    fn kind(&self) -> &'static str {
+        "ivf"
+    }
+
+    fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        IvfIndex::insert(self, id, vector)
+    }
+
+    /// Trains ahead of the insert that would trigger it, see
+    /// [`IvfIndex::plan_insert`].
+    fn plan_insert(&self, id: &str, _vector: &[f32]) -> Option<IndexPlan> {
+        IvfIndex::plan_insert(self, id).map(|p| Box::new(p) as IndexPlan)
+    }
+
+    fn commit_insert(&mut self, id: String, vector: Vec<f32>, plan: Option<IndexPlan>) -> Result<u32> {
+        let plan = plan.and_then(|p| p.downcast::<TrainPlan>().ok()).map(|p| *p);
+        IvfIndex::commit_insert(self, id, vector, plan)
+    }
+
+    fn remove(&mut self, id: &str) {
+        IvfIndex::remove(self, id)
+    }
+
+    fn search_rows(&self, query: &[f32], top_k: usize, breadth: Option<usize>) -> Vec<(u32, f32)> {
+        IvfIndex::search_rows(self, query, top_k, breadth.unwrap_or(self.nprobe))
+    }
+
+    fn search_rows_filtered(&self, query: &[f32], top_k: usize, accept: &dyn Fn(u32) -> bool) -> Vec<(u32, f32)> {
+        IvfIndex::search_rows_filtered(self, query, top_k, accept)
+    }
+
+    fn search_rows_among(&self, query: &[f32], top_k: usize, rows: &mut dyn Iterator<Item = u32>) -> Vec<(u32, f32)> {
+        IvfIndex::search_rows_among(self, query, top_k, rows)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        index::encode(self)
+    }
+
+    fn row(&self, id: &str) -> Option<u32> {
+        self.ids.row(id)
+    }
+
+    fn id_at(&self, row: u32) -> Option<&str> {
+        self.ids.id(row)
+    }
+
+    fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        IvfIndex::vector_at(self, row)
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    fn heap_bytes(&self) -> usize {
+        IvfIndex::heap_bytes(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.vectors.flush()
+    }
+
+    fn reserve(&mut self, additional: usize) -> Result<()> {
+        self.vectors.reserve(additional)
+    }
+
+    fn release_free_rows(&mut self) {
+        self.free.release();
+    }
+}
+
+/// Index of the centroid in `centroids` that ranks best against `v`.
+fn closest_list(metric: &Metric, centroids: &[f32], v: &[f32]) -> usize {
+    let mut best = (0, f32::NEG_INFINITY);
+    for (i, c) in centroids.chunks_exact(v.len()).enumerate() {
+        let s = rank_score(metric, v, c);
+        if s > best.1 {
+            best = (i, s);
+        }
+    }
+    best.0
+}
+
+/// Registry entry for `index_type = "ivf"`, with [`IvfParams`] from
+/// `index_params`.
+pub const INDEX_TYPE: IndexType = IndexType {
+    name: "ivf",
+    build: build_index,
+    load: index::decode::<IvfIndex>,
+};
+
+fn build_index(config: &VecBaseConfig, metric: Metric, vectors: VectorStore) -> Box<dyn AnnIndex> {
+    Box::new(IvfIndex::with_store(config.max_elements, metric, index::parse_params(config), vectors))
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn random(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect()
+    }
+
+    fn small_params() -> IvfParams {
+        IvfParams {
+            nlist: 16,
+            nprobe: 4,
+            ..IvfParams::default()
+        }
+    }
+
+    fn build(n: usize, dim: usize, metric: Metric) -> IvfIndex {
+        let mut idx = IvfIndex::with_params(dim, 100_000, metric, small_params());
+        for (i, v) in random(n, dim, 7).into_iter().enumerate() {
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+        idx
+    }
+
+    #[test]
+    fn test_trains_once_big_enough() {
+        let mut idx = IvfIndex::with_params(4, 10_000, Metric::Euclidean, small_params());
+        let data = random(16 * TRAIN_MIN_PER_LIST, 4, 1);
+        for (i, v) in data.iter().enumerate() {
+            assert!(!idx.is_trained());
+            idx.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+        assert!(idx.is_trained());
+        let filed: usize = idx.lists.iter().map(Vec::len).sum();
+        assert_eq!(filed, idx.len());
+    }
+
+    #[test]
+    fn test_training_is_planned_ahead_of_the_insert() {
+        let mut idx = IvfIndex::with_params(4, 10_000, Metric::Euclidean, small_params());
+        let data = random(16 * TRAIN_MIN_PER_LIST, 4, 1);
+        let (last, rest) = data.split_last().unwrap();
+        for (i, v) in rest.iter().enumerate() {
+            idx.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+        assert!(idx.plan_insert("v0").is_none(), "a replace does not grow the index");
+
+        // A plan made before another change is dropped.
+        let stale = idx.plan_insert("last");
+        assert!(stale.is_some());
+        idx.remove("v1");
+        idx.commit_insert("last".into(), last.clone(), stale).unwrap();
+        assert!(!idx.is_trained());
+
+        let plan = idx.plan_insert("v1");
+        assert!(plan.is_some() && !idx.is_trained());
+        idx.commit_insert("v1".into(), rest[1].clone(), plan).unwrap();
+        assert!(idx.is_trained());
+        let filed: usize = idx.lists.iter().map(Vec::len).sum();
+        assert_eq!(filed, idx.len());
+    }
+
+    #[test]
+    fn test_recall_against_brute_force() {
+        let dim = 16;
+        let idx = build(5_000, dim, Metric::Euclidean);
+        assert!(idx.is_trained());
+
+        let mut hits = 0;
+        let queries = random(50, dim, 99);
+        for q in &queries {
+            let approx: HashSet<u32> = idx.search_rows(q, 10, 4).into_iter().map(|(r, _)| r).collect();
+            let exact = idx.search_rows_among(q, 10, 0..idx.len() as u32);
+            hits += exact.iter().filter(|(r, _)| approx.contains(r)).count();
+        }
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        assert!(recall > 0.7, "recall@10 with nprobe=4/16: {}", recall);
+    }
+
+    #[test]
+    fn test_probing_every_list_is_exact() {
+        let idx = build(2_000, 8, Metric::DotProduct);
+        for q in random(10, 8, 3) {
+            let all = idx.search_rows(&q, 5, 16);
+            let exact = idx.search_rows_among(&q, 5, 0..idx.len() as u32);
+            assert_eq!(all, exact);
+        }
+    }
+
+    #[test]
+    fn test_remove_and_replace() {
+        let mut idx = build(1_000, 4, Metric::Euclidean);
+        let target = idx.vector("v10").unwrap().to_vec();
+        assert_eq!(idx.search(&target, 1)[0].0, "v10");
+
+        idx.remove("v10");
+        assert!(!idx.contains("v10"));
+        assert_eq!(idx.len(), 999);
+        assert!(idx.search(&target, 5).iter().all(|(id, _)| id != "v10"));
+
+        idx.insert("v11".into(), target.clone()).unwrap();
+        assert_eq!(idx.search(&target, 1)[0].0, "v11");
+        assert_eq!(idx.len(), 999);
+
+        // Every live row is filed exactly once, at the position it records.
+        let filed: usize = idx.lists.iter().map(Vec::len).sum();
+        assert_eq!(filed, idx.len());
+        for (l, list) in idx.lists.iter().enumerate() {
+            for (p, &r) in list.iter().enumerate() {
+                assert_eq!(idx.slots[r as usize], (l as u32, p as u32));
+            }
+        }
+    }
+
+    #[test]
+    fn test_filtered_search_probes_until_full() {
+        let idx = build(2_000, 8, Metric::Euclidean);
+        let q = random(1, 8, 5).remove(0);
+        // Only every 50th row matches: four probed lists rarely hold ten.
+        let found = idx.search_rows_filtered(&q, 10, |r| r % 50 == 0);
+        assert_eq!(found.len(), 10);
+        assert!(found.iter().all(|(r, _)| r % 50 == 0));
+        assert!(found.windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut idx = IvfIndex::new(2, 1, Metric::Euclidean);
+        idx.insert("a".into(), vec![0.0, 1.0]).unwrap();
+        idx.insert("a".into(), vec![1.0, 0.0]).unwrap();
+        assert!(matches!(
+            idx.insert("b".into(), vec![1.0, 1.0]),
+            Err(VecBaseError::CapacityExceeded { max_elements: 1 })
+        ));
+    }
+}
@@ -3,16 +3,42 @@
 // Compiled as both `cdylib` (for plugins / FFI) and `rlib` (for the binary).
 // Author: d65v <https://github.com/d65v>
 
+pub mod database;
 pub mod embedding;
+pub mod flat;
+pub mod grpc;
+pub mod index;
+pub mod ivf;
+pub mod lsh;
+pub mod metadata;
+pub mod payload;
+pub mod pq;
 pub mod processing;
+pub mod quantize;
+pub mod records;
+pub mod server;
+pub mod storage;
+pub mod vectors;
+pub mod wal;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use serde::{Deserialize, Serialize};
+use rayon::prelude::*;
+use serde::ser::{Error as _, SerializeSeq};
+use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
 use crate::embedding::{normalize, Metric};
-use crate::processing::HnswIndex;
+use crate::index::{AnnIndex, IndexParams, IndexRef, StoredIndex};
+use crate::metadata::{Filter, Metadata};
+use crate::payload::{PayloadIndexes, PayloadKind};
+use crate::processing::{BatchInsert, HnswIndex, HnswParams};
+use crate::quantize::Quantization;
+use crate::records::{FileRecordStore, MemoryRecordStore, RecordStore};
+use crate::vectors::VectorStore;
+use crate::wal::{Wal, WalOp};
 
 // ── Errors ────────────────────────────────────────────────────────────────────
 
@@ -32,13 +58,25 @@ pub enum VecBaseError {
 
     #[error("Configuration error: {0}")]
     ConfigError(String),
+
+    #[error("Capacity exceeded: max_elements is {max_elements}")]
+    CapacityExceeded { max_elements: usize },
+
+    #[error("Collection not found: {name}")]
+    CollectionNotFound { name: String },
+
+    #[error("Collection already exists: {name}")]
+    CollectionExists { name: String },
 }
 
 pub type Result<T> = std::result::Result<T, VecBaseError>;
 
 // ── Config ────────────────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone)]
+/// Fields missing from a serialized config take their [`Default`] values,
+/// so configs written before a field existed still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct VecBaseConfig {
     /// Dimensionality of all stored vectors
     pub dim: usize,
@@ -48,6 +86,42 @@ pub struct VecBaseConfig {
     pub max_elements: usize,
     /// Path for optional persistence
     pub storage_path: String,
+    /// ANN index: "hnsw" (graph), "ivf" (k-means posting lists, see
+    /// [`ivf`]), "lsh" (hash tables, see [`lsh`]), "flat" (exact scan),
+    /// "pq" (product-quantized codes, see [`pq`]) or any type added with
+    /// [`index::register`]
+    pub index_type: String,
+    /// Settings for `index_type`, read by that type, e.g. `nlist` and
+    /// `nprobe` for IVF (see [`ivf::IvfParams`], [`lsh::LshParams`],
+    /// [`pq::PqParams`]).
+    /// Missing keys take the type's defaults.
+    #[serde(with = "index::params_format")]
+    pub index_params: IndexParams,
+    /// HNSW: max neighbors per node per layer (M)
+    pub hnsw_m: usize,
+    /// HNSW: beam width while building the graph
+    pub ef_construction: usize,
+    /// HNSW: default beam width while searching
+    pub ef_search: usize,
+    /// Collections at or below this size are searched by brute force
+    pub brute_threshold: usize,
+    /// Where index vectors live: "memory" or "mmap" (a fixed-stride file
+    /// under `storage_path`, only used by `VecBase::open`). See also
+    /// [`maps_vectors`](Self::maps_vectors).
+    pub vector_storage: String,
+    /// Quantized copy of index vectors that searches score: "none", "f16"
+    /// or "int8" (see [`quantize`]). Only HNSW supports it.
+    pub vector_quantization: String,
+    /// Re-score the final candidates of a quantized search against the
+    /// full vectors. Without it searches never read them, so `VecBase::open`
+    /// keeps them memory-mapped.
+    pub rescore: bool,
+    /// Where records live: "memory" or "file" (an append-only record file
+    /// under `storage_path`, only used by `VecBase::open`)
+    pub record_storage: String,
+    /// Metadata fields to keep secondary indexes for, used to plan
+    /// filtered searches (see [`payload`])
+    pub payload_indexes: BTreeMap<String, PayloadKind>,
 }
 
 impl Default for VecBaseConfig {
@@ -57,6 +131,17 @@ impl Default for VecBaseConfig {
             metric: "cosine".to_string(),
             max_elements: 1_000_000,
             storage_path: "./data".to_string(),
+            index_type: "hnsw".to_string(),
+            index_params: IndexParams::new(),
+            hnsw_m: 16,
+            ef_construction: 100,
+            ef_search: 64,
+            brute_threshold: 500,
+            vector_storage: "memory".to_string(),
+            vector_quantization: "none".to_string(),
+            rescore: true,
+            record_storage: "memory".to_string(),
+            payload_indexes: BTreeMap::new(),
         }
     }
 }
@@ -79,23 +164,104 @@ impl VecBaseConfig {
         let storage_path =
             std::env::var("VECBASE_STORAGE_PATH").unwrap_or_else(|_| "./data".to_string());
 
+        let defaults = Self::default();
+        let env_usize = |key: &str, default: usize| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+
         Self {
             dim,
             metric,
             max_elements,
             storage_path,
+            index_type: std::env::var("VECBASE_INDEX_TYPE").unwrap_or(defaults.index_type),
+            index_params: std::env::var("VECBASE_INDEX_PARAMS")
+                .ok()
+                .and_then(|json| {
+                    serde_json::from_str(&json)
+                        .map_err(|e| log::warn!("VECBASE_INDEX_PARAMS is not a JSON object: {}", e))
+                        .ok()
+                })
+                .unwrap_or(defaults.index_params),
+            hnsw_m: env_usize("VECBASE_HNSW_M", defaults.hnsw_m),
+            ef_construction: env_usize("VECBASE_EF_CONSTRUCTION", defaults.ef_construction),
+            ef_search: env_usize("VECBASE_EF_SEARCH", defaults.ef_search),
+            brute_threshold: env_usize("VECBASE_BRUTE_THRESHOLD", defaults.brute_threshold),
+            vector_storage: std::env::var("VECBASE_VECTOR_STORAGE")
+                .unwrap_or(defaults.vector_storage),
+            vector_quantization: std::env::var("VECBASE_VECTOR_QUANTIZATION")
+                .unwrap_or(defaults.vector_quantization),
+            rescore: std::env::var("VECBASE_RESCORE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.rescore),
+            record_storage: std::env::var("VECBASE_RECORD_STORAGE")
+                .unwrap_or(defaults.record_storage),
+            payload_indexes: std::env::var("VECBASE_PAYLOAD_INDEXES")
+                .map(|spec| payload::parse_fields(&spec))
+                .unwrap_or(defaults.payload_indexes),
         }
     }
+
+    /// HNSW parameters derived from this config.
+    pub fn hnsw_params(&self) -> HnswParams {
+        HnswParams {
+            m: self.hnsw_m,
+            ef_construction: self.ef_construction,
+            ef_search: self.ef_search,
+            brute_threshold: self.brute_threshold,
+            quantization: self.quantization(),
+            rescore: self.rescore,
+        }
+    }
+
+    /// Registered index type named by `index_type`; unknown names mean HNSW.
+    pub fn index_kind(&self) -> &'static str {
+        match index::lookup(&self.index_type) {
+            Some(index_type) => index_type.name,
+            None => {
+                log::warn!("unknown index_type {:?}, using hnsw", self.index_type);
+                "hnsw"
+            }
+        }
+    }
+
+    /// Whether `VecBase::open` keeps index vectors in a memory-mapped file
+    /// rather than RAM: with `vector_storage = "mmap"`, or with quantization
+    /// on and `rescore` off, where only graph wiring and `get` read the full
+    /// vectors. Decided when a collection is created; its snapshot keeps
+    /// the choice.
+    pub fn maps_vectors(&self) -> bool {
+        self.vector_storage == "mmap" || (self.quantization() != Quantization::None && !self.rescore)
+    }
+
+    /// Parsed `vector_quantization`; unknown values mean no quantization.
+    pub fn quantization(&self) -> Quantization {
+        Quantization::parse(&self.vector_quantization).unwrap_or_else(|| {
+            log::warn!(
+                "unknown vector_quantization {:?}, storing full vectors only",
+                self.vector_quantization
+            );
+            Quantization::None
+        })
+    }
 }
 
 // ── Core Data Types ───────────────────────────────────────────────────────────
 
 /// A single stored vector record.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// The vector itself is not copied here: it lives once in the index's
+/// vector arena at `row`. Read it with [`VecBase::vector`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VecRecord {
     pub id: String,
-    pub vector: Vec<f32>,
-    pub metadata: Option<String>,
+    /// Dense internal id of the vector in the index arena
+    pub row: u32,
+    pub metadata: Option<Metadata>,
 }
 
 /// A single search result.
@@ -103,130 +269,813 @@ pub struct VecRecord {
 pub struct SearchResult {
     pub id: String,
     pub score: f32,
-    pub metadata: Option<String>,
+    pub metadata: Option<Metadata>,
+}
+
+// ── Snapshot Body ─────────────────────────────────────────────────────────────
+
+/// Borrowed view of a `VecBase` written by [`VecBase::save`].
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    config: &'a VecBaseConfig,
+    records: RecordsRef<'a>,
+    index: IndexRef<'a>,
+}
+
+/// Owned form of [`SnapshotRef`] read back by [`VecBase::load`].
+#[derive(Deserialize)]
+struct Snapshot {
+    config: VecBaseConfig,
+    records: Vec<VecRecord>,
+    index: StoredIndex,
+}
+
+/// Streams a record store into a snapshot without cloning it first.
+/// Persistent stores keep their own file, so nothing is written for them.
+struct RecordsRef<'a>(&'a dyn RecordStore);
+
+impl Serialize for RecordsRef<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.0.is_persistent() {
+            return s.serialize_seq(Some(0))?.end();
+        }
+        let mut seq = s.serialize_seq(Some(self.0.len()))?;
+        for record in self.0.iter() {
+            seq.serialize_element(&record.map_err(S::Error::custom)?)?;
+        }
+        seq.end()
+    }
 }
 
 // ── Main Database Struct ──────────────────────────────────────────────────────
 
+/// A vector collection that can be shared between threads (e.g. in an
+/// `Arc`): every method that reads or writes vectors takes `&self`.
+///
+/// Concurrency model: one writer at a time, any number of readers.
+/// Searches and lookups share a read lock on [`State`]. A mutation first
+/// takes the writer lock (which also owns the WAL), appends to the WAL,
+/// then plans its graph change under the read lock — the expensive beam
+/// search for an insert, the scan for back-links on a delete — and only
+/// takes the write lock for the short commit. So searches keep running
+/// while an insert is logged and planned, and wait only for the commit.
 pub struct VecBase {
     pub config: VecBaseConfig,
-    records: HashMap<String, VecRecord>,
-    index: HnswIndex,
+    state: RwLock<State>,
     metric: Metric,
+    /// Write-ahead log (`None` for purely in-memory instances). Held for
+    /// the whole of every mutation, so it doubles as the writer lock and
+    /// WAL order always matches apply order.
+    wal: Mutex<Option<Wal>>,
+}
+
+/// Everything a search reads, guarded as one unit by `VecBase::state`.
+struct State {
+    records: Box<dyn RecordStore>,
+    index: Box<dyn AnnIndex>,
+    /// Secondary indexes over `config.payload_indexes`, keyed by index row
+    payload: PayloadIndexes,
 }
 
 impl VecBase {
-    /// Create a new VecBase instance with the given config.
+    /// Create a new in-memory VecBase instance with the given config.
     pub fn new(config: VecBaseConfig) -> Self {
+        if config.maps_vectors() {
+            log::warn!("VecBase::new keeps vectors in memory; use VecBase::open for mmap storage");
+        }
+        if config.record_storage == "file" {
+            log::warn!("VecBase::new keeps records in memory; use VecBase::open for file storage");
+        }
+        let db = Self::with_store(config, Box::new(MemoryRecordStore::new()));
+        if let Err(e) = db.check_quantization() {
+            log::warn!("{}", e);
+        }
+        db
+    }
+
+    /// Create a VecBase that keeps its records in `records` instead of the
+    /// built-in stores. Index vectors stay in memory.
+    pub fn with_store(config: VecBaseConfig, records: Box<dyn RecordStore>) -> Self {
+        let vectors = VectorStore::memory(config.dim);
+        Self::with_parts(config, vectors, records)
+    }
+
+    fn with_parts(
+        config: VecBaseConfig,
+        vectors: VectorStore,
+        records: Box<dyn RecordStore>,
+    ) -> Self {
         let metric = match config.metric.as_str() {
             "euclidean" => Metric::Euclidean,
             "dot" => Metric::DotProduct,
             _ => Metric::Cosine,
         };
 
-        let index = HnswIndex::new(config.dim, config.max_elements);
+        let index = index::build(&config, metric.clone(), vectors);
+
+        let mut payload = PayloadIndexes::new();
+        for (field, &kind) in &config.payload_indexes {
+            payload.create(field, kind);
+        }
 
         Self {
             config,
-            records: HashMap::new(),
-            index,
+            state: RwLock::new(State {
+                records,
+                index,
+                payload,
+            }),
             metric,
+            wal: Mutex::new(None),
         }
     }
 
+    /// Open a durable VecBase rooted at `config.storage_path`.
+    ///
+    /// Loads the last checkpoint snapshot (if any), replays the write-ahead
+    /// log on top of it, and logs every subsequent insert/delete before it
+    /// is applied. With `vector_storage = "mmap"` index vectors live in
+    /// `<storage_path>/vectors.vbv` instead of RAM; with
+    /// `record_storage = "file"` records live in `<storage_path>/records.vbr`.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on I/O or snapshot failure, and
+    /// `VecBaseError::ConfigError` if the stored dim/metric differ from `config`
+    /// or `vector_quantization` is set for an index type without support
+    /// for it.
+    pub fn open(config: VecBaseConfig) -> Result<Self> {
+        let records: Box<dyn RecordStore> = if config.record_storage == "file" {
+            let path = Path::new(&config.storage_path).join(records::RECORDS_FILE);
+            Box::new(FileRecordStore::open(path)?)
+        } else {
+            Box::new(MemoryRecordStore::new())
+        };
+        Self::open_with_store(config, records)
+    }
+
+    /// Like [`open`](Self::open), but with a caller-supplied record store.
+    ///
+    /// # Errors
+    /// As for [`open`](Self::open), plus `VecBaseError::StorageError` if
+    /// `records` does not hold exactly the ids in the recovered index.
+    pub fn open_with_store(config: VecBaseConfig, records: Box<dyn RecordStore>) -> Result<Self> {
+        let dir = std::path::PathBuf::from(&config.storage_path);
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| storage::storage_err("create storage dir", e))?;
+
+        let snapshot = dir.join(storage::SNAPSHOT_FILE);
+        let mut db = if snapshot.exists() {
+            let snap: Snapshot = storage::read_snapshot(&snapshot)?;
+            if snap.config.dim != config.dim
+                || snap.config.metric != config.metric
+                || snap.index.kind != config.index_kind()
+            {
+                return Err(VecBaseError::ConfigError(format!(
+                    "storage at {} holds a dim={} metric={} {} index, but config asks for dim={} metric={} {}",
+                    config.storage_path,
+                    snap.config.dim,
+                    snap.config.metric,
+                    snap.index.kind,
+                    config.dim,
+                    config.metric,
+                    config.index_kind()
+                )));
+            }
+            let mut db = VecBase::restore(&snapshot, snap, records)?;
+            db.config.storage_path = config.storage_path.clone();
+            // Quantization is derived from the vectors, so `config` may change it.
+            if db.config.vector_quantization != config.vector_quantization
+                || db.config.rescore != config.rescore
+            {
+                db.config.vector_quantization = config.vector_quantization.clone();
+                db.config.rescore = config.rescore;
+                let quantization = db.config.quantization();
+                db.state_mut().index.set_quantization(quantization, config.rescore);
+            }
+            // Fields declared in `config` but not in the snapshot are added.
+            for (field, &kind) in &config.payload_indexes {
+                if db.config.payload_indexes.get(field) != Some(&kind) {
+                    db.create_payload_index(field, kind)?;
+                }
+            }
+            db
+        } else if config.maps_vectors() {
+            let vectors = VectorStore::mmap(dir.join(vectors::VECTORS_FILE), config.dim)?;
+            VecBase::with_parts(config, vectors, records)
+        } else {
+            VecBase::with_store(config, records)
+        };
+        db.check_quantization()?;
+
+        let (wal, ops) = Wal::open(dir.join(wal::WAL_FILE))?;
+        if !ops.is_empty() {
+            log::info!("Replaying {} WAL operations", ops.len());
+        }
+        for op in ops {
+            db.apply(op)?;
+        }
+        let state = db.state_mut();
+        if state.index.len() != state.records.len() {
+            return Err(VecBaseError::StorageError(format!(
+                "record store holds {} records but the index has {} nodes",
+                state.records.len(),
+                state.index.len()
+            )));
+        }
+        *db.wal.get_mut().unwrap_or_else(|e| e.into_inner()) = Some(wal);
+        Ok(db)
+    }
+
+    /// Write a snapshot to `storage_path` and truncate the write-ahead log.
+    /// Searches keep running meanwhile; mutations wait until it is done.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if either step fails. The WAL is
+    /// only truncated once the snapshot and record store are safely on disk.
+    pub fn checkpoint(&self) -> Result<()> {
+        let mut wal = self.writer();
+        let snapshot = Path::new(&self.config.storage_path).join(storage::SNAPSHOT_FILE);
+        self.write().records.flush()?;
+        self.save(snapshot)?;
+        if let Some(wal) = wal.as_mut() {
+            wal.truncate()?;
+        }
+        self.write().index.release_free_rows();
+        Ok(())
+    }
+
     /// Insert a vector record.
     ///
     /// # Errors
-    /// Returns `VecBaseError::DimensionMismatch` if vector length ≠ config.dim.
-    pub fn insert(
-        &mut self,
-        id: String,
-        vector: Vec<f32>,
-        metadata: Option<String>,
-    ) -> Result<()> {
+    /// Returns `VecBaseError::DimensionMismatch` if vector length ≠ config.dim,
+    /// or `VecBaseError::CapacityExceeded` if a new id would exceed
+    /// `config.max_elements`.
+    pub fn insert(&self, id: String, vector: Vec<f32>, metadata: Option<Metadata>) -> Result<()> {
         if vector.len() != self.config.dim {
             return Err(VecBaseError::DimensionMismatch {
                 expected: self.config.dim,
                 got: vector.len(),
             });
         }
+        let mut wal = self.writer();
+        // Checked before logging so a full index never leaves an op in the
+        // WAL that would fail again on replay.
+        {
+            let state = self.read();
+            if state.records.len() >= self.config.max_elements && !state.records.contains(&id) {
+                return Err(VecBaseError::CapacityExceeded {
+                    max_elements: self.config.max_elements,
+                });
+            }
+        }
 
-        // Normalize for cosine similarity
-        let stored_vec = if matches!(self.metric, Metric::Cosine) {
-            normalize(&vector)
-        } else {
-            vector.clone()
-        };
+        // Grow first, so running out of space fails before anything is logged.
+        self.write().index.reserve(1)?;
+        let op = WalOp::Insert { id: id.clone(), vector, metadata };
+        if let Some(wal) = wal.as_mut() {
+            wal.append(&op)?;
+        }
+        self.apply(op).inspect_err(|_| log_discarded(wal.as_mut(), &[id]))
+    }
 
-        let record = VecRecord {
-            id: id.clone(),
-            vector: stored_vec.clone(),
-            metadata,
-        };
+    /// Insert many records at once. An HNSW index builds their graph links
+    /// on all cores (see [`HnswIndex::insert_bulk`]), much faster than
+    /// calling [`insert`](Self::insert) in a loop for large loads, with
+    /// comparable recall. Later items win over earlier ones with the same id.
+    ///
+    /// The whole batch is validated, and room made for it, before anything
+    /// is logged or applied. Searches wait while the graph is built.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::DimensionMismatch` if any vector has the wrong
+    /// length, `VecBaseError::CapacityExceeded` if the new ids would not
+    /// fit, or `VecBaseError::StorageError` if the vector store cannot grow;
+    /// in all three cases nothing is inserted. If a record cannot be written,
+    /// that item and the ones after it are left out (a replaced id loses its
+    /// old version) and the error is returned.
+    pub fn bulk_load(&self, items: Vec<BatchInsert>) -> Result<()> {
+        if let Some(bad) = items.iter().find(|item| item.vector.len() != self.config.dim) {
+            return Err(VecBaseError::DimensionMismatch {
+                expected: self.config.dim,
+                got: bad.vector.len(),
+            });
+        }
+        let mut wal = self.writer();
 
-        self.records.insert(id.clone(), record);
-        self.index.insert(id, stored_vec);
+        // Keep only the last occurrence of each id.
+        let mut seen = HashSet::new();
+        let mut items: Vec<BatchInsert> = items
+            .into_iter()
+            .rev()
+            .filter(|item| seen.insert(item.id.clone()))
+            .collect();
+        items.reverse();
+        {
+            let state = self.read();
+            let new = items.iter().filter(|item| !state.records.contains(&item.id)).count();
+            if state.records.len() + new > self.config.max_elements {
+                return Err(VecBaseError::CapacityExceeded {
+                    max_elements: self.config.max_elements,
+                });
+            }
+        }
+
+        let ops: Vec<WalOp> = items
+            .into_iter()
+            .map(|item| WalOp::Insert {
+                id: item.id,
+                vector: item.vector,
+                metadata: item.metadata,
+            })
+            .collect();
+        self.write().index.reserve(ops.len())?;
+        if let Some(wal) = wal.as_mut() {
+            wal.append_all(&ops)?;
+        }
+
+        let mut entries = Vec::with_capacity(ops.len());
+        let mut metadata = Vec::with_capacity(ops.len());
+        for op in ops {
+            if let WalOp::Insert { id, vector, metadata: meta } = op {
+                entries.push((id, vector));
+                metadata.push(meta);
+            }
+        }
+        if matches!(self.metric, Metric::Cosine) {
+            entries.par_iter_mut().for_each(|(_, v)| *v = normalize(v));
+        }
 
+        let mut state = self.write();
+        let state = &mut *state;
+        for (id, _) in &entries {
+            if let Some(old) = state.index.row(id) {
+                state.payload.remove(old);
+            }
+        }
+        let ids: Vec<String> = entries.iter().map(|(id, _)| id.clone()).collect();
+        let rows = match state.index.insert_bulk(entries) {
+            Ok(rows) => rows,
+            Err(e) => {
+                ids.iter().for_each(|id| state.discard(id));
+                log_discarded(wal.as_mut(), &ids);
+                return Err(e);
+            }
+        };
+        for (i, (row, metadata)) in rows.into_iter().zip(metadata).enumerate() {
+            state.payload.insert(row, metadata.as_ref());
+            let record = VecRecord { id: ids[i].clone(), row, metadata };
+            if let Err(e) = state.records.put(record) {
+                ids[i..].iter().for_each(|id| state.discard(id));
+                log_discarded(wal.as_mut(), &ids[i..]);
+                return Err(e);
+            }
+        }
         Ok(())
     }
 
     /// Search for the top-k nearest neighbors to the query vector.
     pub fn search(&self, query: &[f32], top_k: usize) -> Vec<SearchResult> {
+        self.search_rows(query, top_k, None)
+    }
+
+    /// Search with a per-query breadth, overriding the index's configured
+    /// one. Larger values raise recall at the cost of latency. What it
+    /// counts depends on `index_type`:
+    ///
+    ///   - hnsw: beam width, overriding `config.ef_search`
+    ///   - ivf:  lists probed, overriding the `nprobe` index param
+    ///   - lsh:  extra buckets probed per table, overriding `probes`
+    ///   - pq:   re-ranked candidates per result, overriding `rerank`
+    ///   - flat: ignored, searches are exact
+    pub fn search_with_breadth(&self, query: &[f32], top_k: usize, breadth: usize) -> Vec<SearchResult> {
+        self.search_rows(query, top_k, Some(breadth))
+    }
+
+    /// Search with a per-query HNSW beam width, overriding `config.ef_search`.
+    /// Larger `ef` raises recall at the cost of latency. The same as
+    /// [`search_with_breadth`](Self::search_with_breadth), whose name also
+    /// fits the other index types.
+    pub fn search_with_ef(&self, query: &[f32], top_k: usize, ef: usize) -> Vec<SearchResult> {
+        self.search_with_breadth(query, top_k, ef)
+    }
+
+    fn search_rows(&self, query: &[f32], top_k: usize, breadth: Option<usize>) -> Vec<SearchResult> {
+        match self.prepare_query(query) {
+            Some(q) => {
+                let state = self.read();
+                state.resolve(state.index.search_rows(&q, top_k, breadth))
+            }
+            None => vec![],
+        }
+    }
+
+    /// Top-k nearest neighbors among records whose metadata passes `filter`.
+    ///
+    /// The filter is checked while the HNSW graph is walked (or the IVF
+    /// lists are scanned) rather than on its output, so selective filters
+    /// still return `top_k` results as long as that many records match.
+    /// When payload indexes narrow the filter to at most
+    /// `config.brute_threshold` rows, those rows are scored exactly instead
+    /// and the index is not touched.
+    pub fn search_filtered(&self, query: &[f32], top_k: usize, filter: &Filter) -> Vec<SearchResult> {
+        let q = match self.prepare_query(query) {
+            Some(q) => q,
+            None => return vec![],
+        };
+        let state = self.read();
+        let plan = self.plan_filter(&state.payload, filter);
+        // Fully indexed filters are answered by the candidates alone;
+        // anything else reads the record of each row it visits.
+        let exact = state.payload.is_exact(filter);
+        let accept = |row: u32| {
+            if exact {
+                return true;
+            }
+            let id = match state.index.id_at(row) {
+                Some(id) => id,
+                None => return false,
+            };
+            state.records.matches(id, filter).unwrap_or_else(|e| {
+                log::warn!("search: cannot read record {}: {}", id, e);
+                false
+            })
+        };
+        let rows = match plan {
+            FilterPlan::Scan(candidates) => {
+                let mut matching = candidates.into_iter().filter(|&row| accept(row));
+                state.index.search_rows_among(&q, top_k, &mut matching)
+            }
+            FilterPlan::Graph(Some(candidates)) => state
+                .index
+                .search_rows_filtered(&q, top_k, &|row| candidates.contains(&row) && accept(row)),
+            FilterPlan::Graph(None) => state.index.search_rows_filtered(&q, top_k, &accept),
+        };
+        state.resolve(rows)
+    }
+
+    /// Choose how to run a filtered search from what the payload indexes
+    /// know about `filter`.
+    fn plan_filter(&self, payload: &PayloadIndexes, filter: &Filter) -> FilterPlan {
+        match payload.candidates(filter) {
+            Some(rows) if rows.len() <= self.config.brute_threshold => FilterPlan::Scan(rows),
+            rows => FilterPlan::Graph(rows),
+        }
+    }
+
+    /// Start keeping a secondary index on metadata `field`, built from the
+    /// records already stored. Replaces any existing index on the field.
+    /// The field is added to `config.payload_indexes`, so it is kept in
+    /// the next snapshot.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the record store cannot be read.
+    pub fn create_payload_index(&mut self, field: &str, kind: PayloadKind) -> Result<()> {
+        self.config.payload_indexes.insert(field.to_string(), kind);
+        let state = self.state_mut();
+        state.payload.create(field, kind);
+        // Feed every stored record's `field` into the (empty) index.
+        for record in state.records.iter() {
+            let record = record?;
+            state.payload.insert_field(field, record.row, record.metadata.as_ref());
+        }
+        Ok(())
+    }
+
+    /// Stop indexing metadata `field`. Returns whether it was indexed.
+    pub fn drop_payload_index(&mut self, field: &str) -> bool {
+        self.config.payload_indexes.remove(field);
+        self.state_mut().payload.drop_field(field)
+    }
+
+    /// Dimension-check a query and normalize it for cosine collections.
+    fn prepare_query(&self, query: &[f32]) -> Option<Vec<f32>> {
         if query.len() != self.config.dim {
             log::warn!(
                 "search: query dim {} ≠ config dim {}",
                 query.len(),
                 self.config.dim
             );
-            return vec![];
+            return None;
         }
 
-        let q = if matches!(self.metric, Metric::Cosine) {
+        Some(if matches!(self.metric, Metric::Cosine) {
             normalize(query)
         } else {
             query.to_vec()
-        };
-
-        let ids = self.index.search(&q, top_k, &self.metric);
-
-        ids.into_iter()
-            .filter_map(|(id, score)| {
-                self.records.get(&id).map(|rec| SearchResult {
-                    id: rec.id.clone(),
-                    score,
-                    metadata: rec.metadata.clone(),
-                })
-            })
-            .collect()
+        })
     }
 
     /// Delete a record by id.
     ///
     /// # Errors
     /// Returns `VecBaseError::NotFound` if the id does not exist.
-    pub fn delete(&mut self, id: &str) -> Result<()> {
-        if self.records.remove(id).is_none() {
+    pub fn delete(&self, id: &str) -> Result<()> {
+        let mut wal = self.writer();
+        if !self.read().records.contains(id) {
             return Err(VecBaseError::NotFound { id: id.to_string() });
         }
-        self.index.remove(id);
+        let op = WalOp::Delete { id: id.to_string() };
+        if let Some(wal) = wal.as_mut() {
+            wal.append(&op)?;
+        }
+        self.apply(op)
+    }
+
+    /// Apply an already validated (and logged) mutation in memory.
+    /// Also used for WAL replay, where deletes of missing ids are no-ops.
+    ///
+    /// The caller must hold the writer lock (or own the `VecBase`), so
+    /// nothing changes the index between planning and commit.
+    fn apply(&self, op: WalOp) -> Result<()> {
+        match op {
+            WalOp::Insert { id, vector, metadata } => {
+                // Normalize for cosine similarity
+                let stored_vec = if matches!(self.metric, Metric::Cosine) {
+                    normalize(&vector)
+                } else {
+                    vector
+                };
+
+                let plan = self.read().index.plan_insert(&id, &stored_vec);
+                let mut state = self.write();
+                let state = &mut *state;
+                if let Some(old) = state.index.row(&id) {
+                    state.payload.remove(old);
+                }
+                let row = match state.index.commit_insert(id.clone(), stored_vec, plan) {
+                    Ok(row) => row,
+                    Err(e) => {
+                        state.discard(&id);
+                        return Err(e);
+                    }
+                };
+                state.payload.insert(row, metadata.as_ref());
+                if let Err(e) = state.records.put(VecRecord { id: id.clone(), row, metadata }) {
+                    state.discard(&id);
+                    return Err(e);
+                }
+            }
+            WalOp::Delete { id } => {
+                let plan = self.read().index.plan_remove(&id);
+                let mut state = self.write();
+                // Unconditional: a persistent store may already be past
+                // this op while the snapshot index is not.
+                state.records.delete(&id)?;
+                if let Some(row) = state.index.row(&id) {
+                    state.payload.remove(row);
+                }
+                state.index.commit_remove(&id, plan);
+            }
+        }
         Ok(())
     }
 
+    /// Shared access to the records and index.
+    fn read(&self) -> RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Exclusive access to the records and index.
+    fn write(&self) -> RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn state_mut(&mut self) -> &mut State {
+        self.state.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The writer lock, which also owns the WAL.
+    fn writer(&self) -> MutexGuard<'_, Option<Wal>> {
+        self.wal.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
     /// Return the total number of stored vectors.
     pub fn len(&self) -> usize {
-        self.records.len()
+        self.read().records.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.records.is_empty()
+        self.read().records.is_empty()
+    }
+
+    /// Copy of the stored vector for `id`. For cosine collections this is
+    /// the normalized vector.
+    pub fn vector(&self, id: &str) -> Option<Vec<f32>> {
+        self.read().index.vector(id).map(<[f32]>::to_vec)
+    }
+
+    /// Approximate heap bytes held by the index (vectors, ids, links).
+    /// Records are not counted, as a record store may keep them on disk.
+    pub fn index_heap_bytes(&self) -> usize {
+        self.read().index.heap_bytes()
     }
 
     /// Retrieve a record by id.
-    pub fn get(&self, id: &str) -> Option<&VecRecord> {
-        self.records.get(id)
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the record store cannot read it.
+    pub fn get(&self, id: &str) -> Result<Option<VecRecord>> {
+        self.read().records.get(id)
+    }
+
+    /// Retrieve a record together with a copy of its stored vector, both
+    /// read under the same lock so a concurrent write cannot pair one id's
+    /// record with another's vector.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the record store cannot read it.
+    pub fn get_with_vector(&self, id: &str) -> Result<Option<(VecRecord, Vec<f32>)>> {
+        let state = self.read();
+        let record = match state.records.get(id)? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let vector = state.index.vector(id).map(<[f32]>::to_vec).unwrap_or_default();
+        Ok(Some((record, vector)))
+    }
+
+    /// Write records, config and the HNSW graph to a snapshot file.
+    /// See [`storage`] for the on-disk layout. Memory-mapped vectors are
+    /// copied to `<path>.vbv` beside it (see [`vectors`]).
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let state = self.read();
+        state.index.flush()?;
+        storage::write_snapshot(
+            path.as_ref(),
+            &SnapshotRef {
+                config: &self.config,
+                records: RecordsRef(state.records.as_ref()),
+                index: IndexRef(state.index.as_ref()),
+            },
+        )
+    }
+
+    /// Write only the HNSW graph to a standalone `.vbi` index file.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the file cannot be written,
+    /// or `VecBaseError::ConfigError` if the collection does not use HNSW.
+    pub fn save_index(&self, path: impl AsRef<Path>) -> Result<()> {
+        match self.read().index.downcast_ref::<HnswIndex>() {
+            Some(index) => index.save(path),
+            None => Err(VecBaseError::ConfigError(
+                ".vbi index files hold HNSW graphs only".to_string(),
+            )),
+        }
+    }
+
+    /// Replace the in-memory graph with one read from a `.vbi` file, instead
+    /// of rebuilding it from the stored vectors.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the file is unreadable or its
+    /// graph does not cover exactly the records held by this instance, or
+    /// `VecBaseError::ConfigError` if the collection does not use HNSW.
+    pub fn load_index(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        if self.config.index_kind() != "hnsw" {
+            return Err(VecBaseError::ConfigError(
+                ".vbi index files hold HNSW graphs only".to_string(),
+            ));
+        }
+        let mut index = HnswIndex::load(path)?;
+        if index.dim() != self.config.dim || *index.metric() != self.metric {
+            return Err(VecBaseError::StorageError(format!(
+                "index file is dim={} {:?}, expected dim={} {:?}",
+                index.dim(),
+                index.metric(),
+                self.config.dim,
+                self.metric
+            )));
+        }
+        index.set_quantization(self.config.quantization(), self.config.rescore);
+        let state = self.state_mut();
+        if index.len() != state.records.len() || !state.records.ids().all(|id| index.contains(id)) {
+            return Err(VecBaseError::StorageError(
+                "index file does not match the stored records".to_string(),
+            ));
+        }
+        state.index = Box::new(index);
+        Ok(())
+    }
+
+    /// Restore a `VecBase` from a snapshot written by [`save`](Self::save).
+    /// The graph is loaded as-is, nothing is re-inserted.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the file is missing, corrupt,
+    /// from an incompatible version, or internally inconsistent.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let snap: Snapshot = storage::read_snapshot(path.as_ref())?;
+        let records = snap.records.len();
+        let mut db = VecBase::restore(path.as_ref(), snap, Box::new(MemoryRecordStore::new()))?;
+        let nodes = db.state_mut().index.len();
+        if nodes != records {
+            return Err(VecBaseError::StorageError(format!(
+                "snapshot holds {} records but {} index nodes",
+                records, nodes
+            )));
+        }
+        Ok(db)
+    }
+
+    /// Build a `VecBase` from a snapshot decoded from `path`, copying its
+    /// records (if it carries any) into `records`.
+    fn restore(path: &Path, snap: Snapshot, mut records: Box<dyn RecordStore>) -> Result<Self> {
+        let mut index = storage::in_snapshot(path, || snap.index.load())?;
+        let mut db = VecBase::with_store(snap.config, Box::new(MemoryRecordStore::new()));
+        if index.dim() != db.config.dim || *index.metric() != db.metric {
+            return Err(VecBaseError::StorageError(
+                "snapshot index does not match its config".to_string(),
+            ));
+        }
+        index.set_quantization(db.config.quantization(), db.config.rescore);
+        for record in snap.records {
+            records.put(record)?;
+        }
+        let state = db.state_mut();
+        state.records = records;
+        state.index = index;
+        for record in state.records.iter() {
+            let record = record?;
+            state.payload.insert(record.row, record.metadata.as_ref());
+        }
+        Ok(db)
+    }
+
+    /// `ConfigError` if `vector_quantization` is set but the index ignores it.
+    pub(crate) fn check_quantization(&self) -> Result<()> {
+        let state = self.read();
+        if self.config.quantization() == Quantization::None || state.index.supports_quantization() {
+            return Ok(());
+        }
+        Err(VecBaseError::ConfigError(format!(
+            "index_type {} does not support vector_quantization {:?}",
+            state.index.kind(),
+            self.config.vector_quantization
+        )))
     }
 }
 
+impl State {
+    /// Drop every trace of `id` after a write to it failed partway, so no
+    /// vector stays searchable without its record. A replaced id loses its
+    /// old version too, as its row may already be gone.
+    fn discard(&mut self, id: &str) {
+        if let Some(row) = self.index.row(id) {
+            self.payload.remove(row);
+        }
+        let plan = self.index.plan_remove(id);
+        self.index.commit_remove(id, plan);
+        if let Err(e) = self.records.delete(id) {
+            log::warn!("cannot drop record {} after a failed write: {}", id, e);
+        }
+    }
+
+    /// Turn scored index rows into search results.
+    fn resolve(&self, rows: Vec<(u32, f32)>) -> Vec<SearchResult> {
+        rows.into_iter()
+            .filter_map(|(row, score)| {
+                let id = self.index.id_at(row)?;
+                match self.records.get(id) {
+                    Ok(rec) => rec.map(|rec| SearchResult {
+                        id: rec.id,
+                        score,
+                        metadata: rec.metadata,
+                    }),
+                    Err(e) => {
+                        log::warn!("search: cannot read record {}: {}", id, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Log deletes for `ids` after [`State::discard`] dropped them, so replaying
+/// the WAL, which already holds their inserts, ends in the same state.
+fn log_discarded(wal: Option<&mut Wal>, ids: &[String]) {
+    let wal = match wal {
+        Some(wal) => wal,
+        None => return,
+    };
+    let ops: Vec<WalOp> = ids.iter().map(|id| WalOp::Delete { id: id.clone() }).collect();
+    if let Err(e) = wal.append_all(&ops) {
+        log::error!("cannot log {} discarded inserts, replay will restore them: {}", ids.len(), e);
+    }
+}
+
+/// How [`VecBase::search_filtered`] runs a query.
+enum FilterPlan {
+    /// Few enough candidate rows to score them all exactly
+    Scan(HashSet<u32>),
+    /// Walk the graph, restricted to the candidate rows if there are any
+    Graph(Option<HashSet<u32>>),
+}
+
 // ── Plugin Interface (cdylib) ─────────────────────────────────────────────────
 
 /// Trait that all VecBase plugins must implement.
@@ -237,7 +1086,7 @@ pub trait Plugin: Send + Sync {
     /// Called once when the plugin is loaded.
     fn on_init(&self);
     /// Called on every insert — can transform or enrich the vector/metadata.
-    fn on_insert(&self, id: &str, vector: &mut Vec<f32>, metadata: &mut Option<String>);
+    fn on_insert(&self, id: &str, vector: &mut Vec<f32>, metadata: &mut Option<Metadata>);
     /// Called on every search result — can rerank or filter.
     fn on_search_results(&self, results: &mut Vec<SearchResult>);
 }
@@ -250,7 +1099,7 @@ pub trait Plugin: Send + Sync {
 #[no_mangle]
 pub extern "C" fn vecbase_plugin_version() -> *const std::ffi::c_char {
     // Safety: static string, NUL-terminated
-    b"0.1.0\0".as_ptr() as *const std::ffi::c_char
+    c"0.1.0".as_ptr()
 }
 
 // ── Tests ─────────────────────────────────────────────────────────────────────
@@ -258,6 +1107,78 @@ pub extern "C" fn vecbase_plugin_version() -> *const std::ffi::c_char {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn tag(s: &str) -> Option<Metadata> {
+        Some(metadata! { "tag" => s })
+    }
+
+    fn plan(db: &VecBase, filter: &Filter) -> FilterPlan {
+        db.plan_filter(&db.read().payload, filter)
+    }
+
+    /// Memory store that fails `put` once `puts_left` runs out and counts
+    /// the lookups made by filtered searches.
+    #[derive(Default)]
+    struct FlakyRecords {
+        inner: MemoryRecordStore,
+        puts_left: Arc<AtomicUsize>,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl FlakyRecords {
+        /// A store allowing `puts` writes, with its counters.
+        fn new(puts: usize) -> (Box<Self>, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+            let store = FlakyRecords::default();
+            store.puts_left.store(puts, Ordering::SeqCst);
+            let (puts, reads) = (store.puts_left.clone(), store.reads.clone());
+            (Box::new(store), puts, reads)
+        }
+
+        /// Swap `db`'s (empty) store for a flaky one; returns its counters.
+        fn install(db: &mut VecBase, puts: usize) -> (Arc<AtomicUsize>, Arc<AtomicUsize>) {
+            let (store, puts, reads) = FlakyRecords::new(puts);
+            db.state_mut().records = store;
+            (puts, reads)
+        }
+    }
+
+    impl RecordStore for FlakyRecords {
+        fn get(&self, id: &str) -> Result<Option<VecRecord>> {
+            self.inner.get(id)
+        }
+        fn put(&mut self, record: VecRecord) -> Result<()> {
+            let left = self.puts_left.load(Ordering::SeqCst);
+            if left == 0 {
+                return Err(VecBaseError::StorageError("disk full".into()));
+            }
+            self.puts_left.store(left - 1, Ordering::SeqCst);
+            self.inner.put(record)
+        }
+        fn delete(&mut self, id: &str) -> Result<bool> {
+            self.inner.delete(id)
+        }
+        fn contains(&self, id: &str) -> bool {
+            self.inner.contains(id)
+        }
+        fn matches(&self, id: &str, filter: &Filter) -> Result<bool> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.matches(id, filter)
+        }
+        fn iter(&self) -> Box<dyn Iterator<Item = Result<VecRecord>> + '_> {
+            self.inner.iter()
+        }
+        fn ids(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+            self.inner.ids()
+        }
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
 
     fn make_db() -> VecBase {
         VecBase::new(VecBaseConfig {
@@ -268,7 +1189,7 @@ mod tests {
 
     #[test]
     fn test_insert_and_len() {
-        let mut db = make_db();
+        let db = make_db();
         db.insert("a".into(), vec![0.1, 0.2, 0.3, 0.4], None)
             .unwrap();
         assert_eq!(db.len(), 1);
@@ -276,14 +1197,14 @@ mod tests {
 
     #[test]
     fn test_dimension_mismatch() {
-        let mut db = make_db();
+        let db = make_db();
         let err = db.insert("x".into(), vec![1.0, 2.0], None).unwrap_err();
         assert!(matches!(err, VecBaseError::DimensionMismatch { .. }));
     }
 
     #[test]
     fn test_search_returns_results() {
-        let mut db = make_db();
+        let db = make_db();
         db.insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], None)
             .unwrap();
         db.insert("b".into(), vec![0.0, 1.0, 0.0, 0.0], None)
@@ -295,24 +1216,843 @@ mod tests {
 
     #[test]
     fn test_delete() {
-        let mut db = make_db();
+        let db = make_db();
         db.insert("del".into(), vec![0.5, 0.5, 0.5, 0.5], None)
             .unwrap();
         db.delete("del").unwrap();
-        assert!(db.get("del").is_none());
+        assert!(db.get("del").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_with_vector() {
+        let db = VecBase::new(VecBaseConfig {
+            dim: 4,
+            metric: "euclidean".into(),
+            ..Default::default()
+        });
+        db.insert("a".into(), vec![1.0, 2.0, 3.0, 4.0], tag("x")).unwrap();
+        let (record, vector) = db.get_with_vector("a").unwrap().unwrap();
+        assert_eq!((record.id.as_str(), record.metadata), ("a", tag("x")));
+        assert_eq!(vector, vec![1.0, 2.0, 3.0, 4.0]);
+        assert!(db.get_with_vector("b").unwrap().is_none());
     }
 
     #[test]
     fn test_delete_not_found() {
-        let mut db = make_db();
+        let db = make_db();
         let err = db.delete("ghost").unwrap_err();
         assert!(matches!(err, VecBaseError::NotFound { .. }));
     }
 
+    #[test]
+    fn test_record_refers_to_index_row() {
+        let db = make_db();
+        db.insert("a".into(), vec![3.0, 0.0, 4.0, 0.0], tag("m")).unwrap();
+        db.insert("b".into(), vec![0.0, 1.0, 0.0, 0.0], None).unwrap();
+        let rec = db.get("a").unwrap().unwrap();
+        assert_eq!(rec.metadata, tag("m"));
+        assert_eq!(Some(rec.row), db.read().index.row("a"));
+        assert_eq!(db.vector("a"), Some(vec![0.6, 0.0, 0.8, 0.0]));
+
+        // Re-inserting keeps the record pointing at the live row.
+        db.insert("a".into(), vec![0.0, 0.0, 0.0, 2.0], None).unwrap();
+        let rec = db.get("a").unwrap().unwrap();
+        assert_eq!(db.read().index.vector_at(rec.row), Some(&[0.0, 0.0, 0.0, 1.0][..]));
+    }
+
+    #[test]
+    fn test_insert_capacity_exceeded() {
+        let db = VecBase::new(VecBaseConfig {
+            dim: 4,
+            max_elements: 2,
+            ..Default::default()
+        });
+        db.insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], None).unwrap();
+        db.insert("b".into(), vec![0.0, 1.0, 0.0, 0.0], None).unwrap();
+        let err = db.insert("c".into(), vec![0.0, 0.0, 1.0, 0.0], None).unwrap_err();
+        assert!(matches!(err, VecBaseError::CapacityExceeded { max_elements: 2 }));
+        // Replacing an existing id is still allowed when full.
+        db.insert("a".into(), vec![0.0, 0.0, 0.0, 1.0], None).unwrap();
+        assert_eq!(db.len(), 2);
+    }
+
+    #[test]
+    fn test_bulk_load() {
+        let mut db = VecBase::new(VecBaseConfig {
+            dim: 4,
+            metric: "euclidean".into(),
+            max_elements: 3_000,
+            ..Default::default()
+        });
+        db.create_payload_index("tag", PayloadKind::Keyword).unwrap();
+        db.insert("v7".into(), vec![-1.0; 4], tag("old")).unwrap();
+
+        let item = |i: usize, t: &str| BatchInsert {
+            id: format!("v{}", i),
+            vector: vec![i as f32, (i % 7) as f32, 1.0, 0.0],
+            metadata: tag(t),
+        };
+        let mut items: Vec<BatchInsert> = (0..2_500).map(|i| item(i, "new")).collect();
+        items.push(item(3, "last"));
+        db.bulk_load(items).unwrap();
+
+        assert_eq!(db.len(), 2_500);
+        assert_eq!(db.vector("v7"), Some(vec![7.0, 0.0, 1.0, 0.0]));
+        assert_eq!(db.get("v3").unwrap().unwrap().metadata, tag("last"));
+        let hits = db.search(&[1234.2, 2.0, 1.0, 0.0], 1);
+        assert_eq!(hits[0].id, "v1234");
+        let old = db.search_filtered(&[7.0, 0.0, 1.0, 0.0], 5, &Filter::eq("tag", "old"));
+        assert!(old.is_empty());
+
+        // Nothing is applied when any item is invalid.
+        let err = db.bulk_load(vec![item(9_000, "x"), BatchInsert { vector: vec![1.0], ..item(9_001, "x") }]);
+        assert!(matches!(err, Err(VecBaseError::DimensionMismatch { expected: 4, got: 1 })));
+        let err = db.bulk_load((2_500..3_001).map(|i| item(i, "x")).collect());
+        assert!(matches!(err, Err(VecBaseError::CapacityExceeded { max_elements: 3_000 })));
+        assert_eq!(db.len(), 2_500);
+    }
+
+    #[test]
+    fn test_failed_writes_match_wal_replay() {
+        let dir = storage::tests::scratch_dir("db-bulk-fail");
+        let config = VecBaseConfig {
+            dim: 4,
+            metric: "euclidean".into(),
+            storage_path: dir.to_string_lossy().into_owned(),
+            payload_indexes: BTreeMap::from([("tag".to_string(), PayloadKind::Keyword)]),
+            ..Default::default()
+        };
+        let (store, puts, _) = FlakyRecords::new(usize::MAX);
+        let db = VecBase::open_with_store(config.clone(), store).unwrap();
+        for i in 0..10 {
+            db.insert(format!("v{}", i), vec![i as f32, 0.0, 0.0, 0.0], tag("old")).unwrap();
+        }
+
+        // The sixth record write fails: v5..v9 are replaced, v10.. dropped.
+        puts.store(5, Ordering::SeqCst);
+        let items = (5..20)
+            .map(|i| BatchInsert {
+                id: format!("v{}", i),
+                vector: vec![i as f32, 1.0, 0.0, 0.0],
+                metadata: tag("new"),
+            })
+            .collect();
+        let err = db.bulk_load(items).unwrap_err();
+        assert!(matches!(err, VecBaseError::StorageError(_)));
+        assert!(db.insert("v3".into(), vec![0.0; 4], tag("new")).is_err());
+
+        let check = |db: &VecBase| {
+            let mut ids: Vec<String> = db.search(&[0.0; 4], 50).into_iter().map(|r| r.id).collect();
+            ids.sort();
+            let expected = ["v0", "v1", "v2", "v4", "v5", "v6", "v7", "v8", "v9"];
+            assert_eq!(ids, expected);
+            assert_eq!(db.len(), expected.len());
+            assert_eq!(db.vector("v5"), Some(vec![5.0, 1.0, 0.0, 0.0]));
+            let old = db.search_filtered(&[0.0; 4], 50, &Filter::eq("tag", "old"));
+            assert_eq!(old.len(), 4);
+        };
+        check(&db);
+        drop(db);
+        check(&VecBase::open(config).unwrap());
+    }
+
+    #[test]
+    fn test_search_filtered() {
+        let db = VecBase::new(VecBaseConfig {
+            dim: 4,
+            metric: "euclidean".into(),
+            brute_threshold: 10,
+            ..Default::default()
+        });
+        for i in 0..400 {
+            let lang = if i % 10 == 0 { "de" } else { "en" };
+            let meta = metadata! { "lang" => lang, "year" => 2000 + (i % 30), "tags" => vec!["x"] };
+            db.insert(format!("v{}", i), vec![i as f32, 0.0, 1.0, 0.0], Some(meta)).unwrap();
+        }
+        db.insert("bare".into(), vec![-5.0, 0.0, 1.0, 0.0], None).unwrap();
+
+        let q = [201.0, 0.0, 1.0, 0.0];
+        let filter = Filter::eq("lang", "de").and(Filter::gte("year", 2010.0));
+        let results = db.search_filtered(&q, 5, &filter);
+        // Matches are i ≡ 0 (mod 10) with i mod 30 ≥ 10: …, 190, 200, 220, 230, 250, …
+        let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["v200", "v190", "v220", "v230", "v170"]);
+        assert!(results.iter().all(|r| filter.matches(r.metadata.as_ref())));
+
+        let not_en = db.search_filtered(&[-5.0, 0.0, 1.0, 0.0], 3, &!Filter::eq("lang", "en"));
+        assert_eq!(not_en[0].id, "bare");
+        assert!(db.search_filtered(&q, 5, &Filter::eq("lang", "fr")).is_empty());
+    }
+
+    #[test]
+    fn test_search_filtered_with_payload_indexes() {
+        let mut db = VecBase::new(VecBaseConfig {
+            dim: 4,
+            metric: "euclidean".into(),
+            brute_threshold: 20,
+            ..Default::default()
+        });
+        db.create_payload_index("lang", PayloadKind::Keyword).unwrap();
+        for i in 0..400 {
+            let lang = if i % 40 == 0 { "de" } else { "en" };
+            let meta = metadata! { "lang" => lang, "year" => 2000 + (i % 30) };
+            db.insert(format!("v{}", i), vec![i as f32, 0.0, 1.0, 0.0], Some(meta)).unwrap();
+        }
+        // Indexes created after the fact are built from existing records.
+        db.create_payload_index("year", PayloadKind::Numeric).unwrap();
+
+        let q = [201.0, 0.0, 1.0, 0.0];
+        let rare = Filter::eq("lang", "de");
+        assert!(matches!(plan(&db, &rare), FilterPlan::Scan(ref rows) if rows.len() == 10));
+        let ids: Vec<String> = db.search_filtered(&q, 3, &rare).into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["v200", "v240", "v160"]);
+
+        // Unindexed parts of the filter are still applied to the candidates.
+        let narrowed = rare.clone().and(Filter::ne("year", 2020));
+        let ids: Vec<String> = db.search_filtered(&q, 2, &narrowed).into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["v240", "v160"]);
+
+        let common = Filter::gte("year", 2005.0);
+        assert!(matches!(plan(&db, &common), FilterPlan::Graph(Some(_))));
+        let results = db.search_filtered(&q, 5, &common);
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|r| common.matches(r.metadata.as_ref())));
+        assert!(matches!(plan(&db, &Filter::ne("lang", "en")), FilterPlan::Graph(None)));
+
+        // Updates and deletes move rows in and out of the indexes.
+        db.insert("v200".into(), vec![200.0, 0.0, 1.0, 0.0], Some(metadata! { "lang" => "en" })).unwrap();
+        db.delete("v160").unwrap();
+        let ids: Vec<String> = db.search_filtered(&q, 2, &rare).into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["v240", "v280"]);
+        assert!(db.drop_payload_index("lang"));
+        assert!(matches!(plan(&db, &rare), FilterPlan::Graph(None)));
+    }
+
+    #[test]
+    fn test_exact_filters_skip_record_reads() {
+        let mut db = make_db();
+        let (_, reads) = FlakyRecords::install(&mut db, usize::MAX);
+        db.create_payload_index("lang", PayloadKind::Keyword).unwrap();
+        for i in 0..50 {
+            let lang = if i % 5 == 0 { "de" } else { "en" };
+            db.insert(format!("v{}", i), vec![i as f32, 1.0, 0.0, 0.0], Some(metadata! { "lang" => lang }))
+                .unwrap();
+        }
+        let q = [10.0, 1.0, 0.0, 0.0];
+        let results = db.search_filtered(&q, 3, &Filter::eq("lang", "de"));
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.metadata == Some(metadata! { "lang" => "de" })));
+        assert_eq!(reads.load(Ordering::SeqCst), 0);
+
+        db.search_filtered(&q, 3, &Filter::ne("lang", "en"));
+        assert!(reads.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn test_failed_record_write_unindexes_vector() {
+        let mut db = make_db();
+        FlakyRecords::install(&mut db, 2);
+        db.create_payload_index("tag", PayloadKind::Keyword).unwrap();
+        db.insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], tag("x")).unwrap();
+        db.insert("b".into(), vec![0.0, 1.0, 0.0, 0.0], tag("x")).unwrap();
+
+        let err = db.insert("c".into(), vec![0.0, 0.0, 1.0, 0.0], tag("x")).unwrap_err();
+        assert!(matches!(err, VecBaseError::StorageError(_)));
+        assert!(db.vector("c").is_none());
+        assert!(db.insert("a".into(), vec![0.0, 0.0, 0.0, 1.0], tag("x")).is_err());
+        assert!(db.vector("a").is_none() && db.get("a").unwrap().is_none());
+
+        let all: Vec<String> = db.search(&[0.5, 0.5, 0.5, 0.5], 10).into_iter().map(|r| r.id).collect();
+        assert_eq!(all, vec!["b"]);
+        let tagged = db.search_filtered(&[0.5, 0.5, 0.5, 0.5], 10, &Filter::eq("tag", "x"));
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(db.len(), 1);
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let path = storage::tests::scratch_dir("db-roundtrip").join("db.snap");
+        let db = VecBase::new(VecBaseConfig {
+            dim: 4,
+            metric: "euclidean".into(),
+            brute_threshold: 8,
+            ..Default::default()
+        });
+        for i in 0..40 {
+            let v = vec![i as f32, (i % 5) as f32, 1.0, -(i as f32)];
+            db.insert(format!("v{}", i), v, tag(&format!("m{}", i))).unwrap();
+        }
+        db.save(&path).unwrap();
+
+        let loaded = VecBase::load(&path).unwrap();
+        assert_eq!(loaded.config, db.config);
+        assert_eq!(loaded.len(), 40);
+        assert_eq!(loaded.get("v7").unwrap().unwrap().metadata, tag("m7"));
+
+        let q = [12.0, 2.0, 1.0, -12.0];
+        let before: Vec<String> = db.search(&q, 5).into_iter().map(|r| r.id).collect();
+        let after: Vec<String> = loaded.search(&q, 5).into_iter().map(|r| r.id).collect();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn test_load_corrupt_snapshot() {
+        let dir = storage::tests::scratch_dir("db-corrupt");
+        let path = dir.join("db.snap");
+        let db = make_db();
+        db.insert("a".into(), vec![0.1, 0.2, 0.3, 0.4], None).unwrap();
+        db.save(&path).unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let mid = bytes.len() / 2;
+        bytes[mid] ^= 0x55;
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(VecBase::load(&path), Err(VecBaseError::StorageError(_))));
+
+        let missing = VecBase::load(dir.join("nope.snap"));
+        assert!(matches!(missing, Err(VecBaseError::StorageError(_))));
+    }
+
+    /// Encodes a fixed one-record database (hash maps iterate in a random
+    /// order, so more would not be reproducible) and compares the payload checksum against the
+    /// one recorded for the current `SNAPSHOT_VERSION`. If this fails, the
+    /// snapshot format changed: bump the version and update both constants.
+    #[test]
+    fn test_snapshot_format_fingerprint() {
+        const FINGERPRINT: (u32, u32) = (1, 2972126311);
+        let path = storage::tests::scratch_dir("db-fingerprint").join("db.snap");
+        let db = VecBase::new(VecBaseConfig {
+            dim: 4,
+            index_type: "flat".into(),
+            payload_indexes: BTreeMap::from([("tag".to_string(), PayloadKind::Keyword)]),
+            ..Default::default()
+        });
+        db.insert("a".into(), vec![1.0, 2.0, 3.0, 4.0], tag("x")).unwrap();
+        db.delete("a").unwrap();
+        db.insert("c".into(), vec![0.0, 1.0, 0.0, 1.0], tag("y")).unwrap();
+        db.save(&path).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let crc = u32::from_le_bytes(bytes[20..24].try_into().unwrap());
+        assert_eq!(
+            (storage::SNAPSHOT_VERSION, crc),
+            FINGERPRINT,
+            "snapshot format changed: bump SNAPSHOT_VERSION and update FINGERPRINT"
+        );
+    }
+
+    #[test]
+    fn test_save_load_index_file() {
+        let dir = storage::tests::scratch_dir("db-vbi");
+        let db = make_db();
+        for i in 0..20 {
+            db.insert(format!("v{}", i), vec![i as f32, 1.0, 0.5, -1.0], None).unwrap();
+        }
+        db.save_index(dir.join("db.vbi")).unwrap();
+
+        let mut other = make_db();
+        for i in 0..20 {
+            other.insert(format!("v{}", i), vec![i as f32, 1.0, 0.5, -1.0], None).unwrap();
+        }
+        other.load_index(dir.join("db.vbi")).unwrap();
+        let q = [3.0, 1.0, 0.5, -1.0];
+        let a: Vec<String> = db.search(&q, 3).into_iter().map(|r| r.id).collect();
+        let b: Vec<String> = other.search(&q, 3).into_iter().map(|r| r.id).collect();
+        assert_eq!(a, b);
+
+        other.delete("v0").unwrap();
+        let err = other.load_index(dir.join("db.vbi")).unwrap_err();
+        assert!(matches!(err, VecBaseError::StorageError(_)));
+    }
+
+    fn durable_config(name: &str) -> VecBaseConfig {
+        VecBaseConfig {
+            dim: 4,
+            storage_path: storage::tests::scratch_dir(name).to_string_lossy().into_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_open_replays_wal() {
+        let config = durable_config("db-wal-replay");
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            db.insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], tag("A")).unwrap();
+            db.insert("b".into(), vec![0.0, 1.0, 0.0, 0.0], None).unwrap();
+            db.insert("c".into(), vec![0.0, 0.0, 1.0, 0.0], None).unwrap();
+            db.delete("b").unwrap();
+            // dropped without checkpoint — simulates a crash
+        }
+
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.len(), 2);
+        assert!(db.get("b").unwrap().is_none());
+        assert_eq!(db.get("a").unwrap().unwrap().metadata, tag("A"));
+        assert_eq!(db.search(&[1.0, 0.0, 0.0, 0.0], 1)[0].id, "a");
+    }
+
+    #[test]
+    fn test_bulk_load_replays_from_wal() {
+        let config = durable_config("db-bulk-wal");
+        let items = |tags: &[&str]| -> Vec<BatchInsert> {
+            tags.iter()
+                .enumerate()
+                .map(|(i, t)| BatchInsert { id: format!("v{}", i), vector: vec![1.0, i as f32, 0.0, 0.0], metadata: tag(t) })
+                .collect()
+        };
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            db.bulk_load(items(&["a", "b", "c"])).unwrap();
+            // dropped without checkpoint — simulates a crash
+        }
+
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.len(), 3);
+        assert_eq!(db.get("v2").unwrap().unwrap().metadata, tag("c"));
+    }
+
+    #[test]
+    fn test_checkpoint_truncates_wal() {
+        let config = durable_config("db-checkpoint");
+        let wal_path = Path::new(&config.storage_path).join(wal::WAL_FILE);
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            db.insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], None).unwrap();
+            db.checkpoint().unwrap();
+            assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+            db.insert("b".into(), vec![0.0, 1.0, 0.0, 0.0], None).unwrap();
+        }
+
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.len(), 2);
+        assert!(db.get("a").unwrap().is_some() && db.get("b").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_open_survives_torn_wal_tail() {
+        let config = durable_config("db-wal-torn");
+        let wal_path = Path::new(&config.storage_path).join(wal::WAL_FILE);
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            db.insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], None).unwrap();
+            db.insert("b".into(), vec![0.0, 1.0, 0.0, 0.0], None).unwrap();
+        }
+        let len = std::fs::metadata(&wal_path).unwrap().len();
+        let f = std::fs::OpenOptions::new().write(true).open(&wal_path).unwrap();
+        f.set_len(len - 5).unwrap();
+        drop(f);
+
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.len(), 1);
+        assert!(db.get("a").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_open_with_mmap_vectors() {
+        let config = VecBaseConfig {
+            vector_storage: "mmap".into(),
+            ..durable_config("db-mmap")
+        };
+        let vector_file = Path::new(&config.storage_path).join(vectors::VECTORS_FILE);
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            for i in 0..30 {
+                db.insert(format!("v{}", i), vec![i as f32, 1.0, 0.0, 2.0], None).unwrap();
+            }
+            db.checkpoint().unwrap();
+            db.insert("late".into(), vec![0.0, 0.0, 1.0, 0.0], None).unwrap();
+        }
+        assert!(vector_file.exists());
+
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.len(), 31);
+        assert_eq!(db.search(&[0.0, 0.0, 1.0, 0.0], 1)[0].id, "late");
+        assert_eq!(db.search(&[29.0, 1.0, 0.0, 2.0], 1)[0].id, "v29");
+    }
+
+    #[test]
+    fn test_mmap_snapshots_are_point_in_time() {
+        let config = VecBaseConfig {
+            metric: "euclidean".into(),
+            vector_storage: "mmap".into(),
+            ..durable_config("db-mmap-backup")
+        };
+        let backup = storage::tests::scratch_dir("db-mmap-backup-to").join("backup.snap");
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            for i in 0..10 {
+                db.insert(format!("v{}", i), vec![i as f32, 1.0, 0.0, 0.0], None).unwrap();
+            }
+            db.checkpoint().unwrap();
+            db.save(&backup).unwrap();
+            // Freed rows are not overwritten before the next checkpoint.
+            db.delete("v3").unwrap();
+            db.insert("new".into(), vec![0.0, 0.0, 9.0, 9.0], None).unwrap();
+            db.insert("v4".into(), vec![0.0, 0.0, 4.0, 4.0], None).unwrap();
+        }
+
+        // The backup is a copy, found relative to the snapshot after a move.
+        let moved = storage::tests::scratch_dir("db-mmap-backup-moved");
+        for name in ["backup.snap", "backup.snap.vbv"] {
+            std::fs::rename(backup.with_file_name(name), moved.join(name)).unwrap();
+        }
+        let old = VecBase::load(moved.join("backup.snap")).unwrap();
+        assert_eq!(old.len(), 10);
+        assert_eq!(old.vector("v3"), Some(vec![3.0, 1.0, 0.0, 0.0]));
+        assert_eq!(old.vector("v4"), Some(vec![4.0, 1.0, 0.0, 0.0]));
+
+        // The checkpoint plus the WAL still describe the live collection.
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.vector("v3"), None);
+        assert_eq!(db.vector("v4"), Some(vec![0.0, 0.0, 4.0, 4.0]));
+        assert_eq!(db.vector("new"), Some(vec![0.0, 0.0, 9.0, 9.0]));
+        assert_eq!(db.vector("v5"), Some(vec![5.0, 1.0, 0.0, 0.0]));
+        db.checkpoint().unwrap();
+        db.insert("reused".into(), vec![1.0; 4], None).unwrap();
+        assert!(db.read().index.row("reused").unwrap() < 12);
+    }
+
+    #[test]
+    fn test_open_restores_quantization() {
+        let config = VecBaseConfig {
+            metric: "euclidean".into(),
+            vector_quantization: "int8".into(),
+            ..durable_config("db-quantized")
+        };
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            for i in 0..30 {
+                db.insert(format!("v{}", i), vec![i as f32, 1.0, 0.0, 2.0], None).unwrap();
+            }
+            db.checkpoint().unwrap();
+            db.insert("late".into(), vec![0.0, 0.0, 1.0, 0.0], None).unwrap();
+        }
+
+        let db = VecBase::open(config.clone()).unwrap();
+        assert_eq!(db.read().index.downcast_ref::<HnswIndex>().unwrap().params().quantization, Quantization::Int8);
+        assert_eq!(db.search(&[0.0, 0.0, 1.0, 0.0], 1)[0].id, "late");
+        let hit = &db.search(&[17.0, 1.0, 0.0, 2.0], 1)[0];
+        assert_eq!((hit.id.as_str(), hit.score), ("v17", 0.0));
+        drop(db);
+
+        let db = VecBase::open(VecBaseConfig { vector_quantization: "f16".into(), ..config }).unwrap();
+        assert_eq!(db.read().index.downcast_ref::<HnswIndex>().unwrap().params().quantization, Quantization::F16);
+        assert_eq!(db.config.vector_quantization, "f16");
+        assert_eq!(db.search(&[29.0, 1.0, 0.0, 2.0], 1)[0].id, "v29");
+    }
+
+    #[test]
+    fn test_quantization_without_rescore_maps_vectors() {
+        let config = VecBaseConfig {
+            metric: "euclidean".into(),
+            vector_quantization: "int8".into(),
+            rescore: false,
+            ..durable_config("db-quantized-mapped")
+        };
+        assert!(config.maps_vectors());
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            let rescored = VecBase::open(VecBaseConfig {
+                rescore: true,
+                storage_path: storage::tests::scratch_dir("db-quantized-rescored").to_string_lossy().into_owned(),
+                ..config.clone()
+            })
+            .unwrap();
+            for i in 0..30 {
+                db.insert(format!("v{}", i), vec![i as f32, 1.0, 0.0, 2.0], None).unwrap();
+                rescored.insert(format!("v{}", i), vec![i as f32, 1.0, 0.0, 2.0], None).unwrap();
+            }
+            // The f32 rows are in the mapped file, not on the heap.
+            assert!(db.read().index.heap_bytes() + 30 * 4 * 4 <= rescored.read().index.heap_bytes());
+            db.checkpoint().unwrap();
+        }
+        assert!(Path::new(&config.storage_path).join(vectors::VECTORS_FILE).exists());
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.vector("v7"), Some(vec![7.0, 1.0, 0.0, 2.0]));
+        assert_eq!(db.search(&[7.0, 1.0, 0.0, 2.0], 1)[0].id, "v7");
+    }
+
+    #[test]
+    fn test_quantization_needs_an_index_that_supports_it() {
+        for index_type in ["ivf", "lsh", "flat", "pq"] {
+            let config = VecBaseConfig {
+                index_type: index_type.into(),
+                vector_quantization: "f16".into(),
+                ..durable_config(&format!("db-quantized-{}", index_type))
+            };
+            let err = VecBase::open(config).err().unwrap();
+            assert!(matches!(err, VecBaseError::ConfigError(_)), "{}: {:?}", index_type, err);
+        }
+    }
+
+    #[test]
+    fn test_ivf_collection() {
+        let config = VecBaseConfig {
+            metric: "euclidean".into(),
+            index_type: "ivf".into(),
+            index_params: [("nlist".into(), 4.into()), ("nprobe".into(), 1.into())].into(),
+            ..durable_config("db-ivf")
+        };
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            for i in 0..400 {
+                let meta = tag(if i % 2 == 0 { "even" } else { "odd" });
+                db.insert(format!("v{}", i), vec![i as f32, (i % 7) as f32, 0.0, 1.0], meta).unwrap();
+            }
+            assert!(db.read().index.downcast_ref::<ivf::IvfIndex>().unwrap().is_trained());
+            db.checkpoint().unwrap();
+            db.delete("v17").unwrap();
+        }
+
+        let db = VecBase::open(config.clone()).unwrap();
+        assert_eq!(db.len(), 399);
+        let hit = &db.search(&[250.0, 5.0, 0.0, 1.0], 1)[0];
+        assert_eq!((hit.id.as_str(), hit.score), ("v250", 0.0));
+        assert!(db.search_with_breadth(&[17.0, 3.0, 0.0, 1.0], 5, 4).iter().all(|r| r.id != "v17"));
+        let odd = db.search_filtered(&[10.0, 3.0, 0.0, 1.0], 3, &Filter::eq("tag", "odd"));
+        assert_eq!(odd.len(), 3);
+        assert!(odd.iter().all(|r| r.metadata == tag("odd")));
+        assert!(matches!(db.save_index(std::env::temp_dir().join("x.vbi")), Err(VecBaseError::ConfigError(_))));
+        drop(db);
+
+        let err = VecBase::open(VecBaseConfig { index_type: "hnsw".into(), ..config }).err().unwrap();
+        assert!(matches!(err, VecBaseError::ConfigError(_)));
+    }
+
+    #[test]
+    fn test_pq_collection() {
+        let config = VecBaseConfig {
+            metric: "euclidean".into(),
+            index_type: "pq".into(),
+            index_params: [("sub_vectors".into(), 2.into()), ("bits".into(), 2.into()), ("rerank".into(), 8.into())]
+                .into(),
+            vector_storage: "mmap".into(),
+            ..durable_config("db-pq")
+        };
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            // Spread over the range from the start, so the first training
+            // sample (39 vectors per centroid, 4 centroids) covers it all.
+            for i in (0..300).map(|i| i * 7 % 300) {
+                let meta = tag(if i % 2 == 0 { "even" } else { "odd" });
+                db.insert(format!("v{}", i), vec![i as f32, (i % 7) as f32, 0.0, 1.0], meta).unwrap();
+            }
+            assert!(db.read().index.downcast_ref::<pq::PqIndex>().unwrap().is_trained());
+            db.checkpoint().unwrap();
+            db.delete("v17").unwrap();
+        }
+
+        let db = VecBase::open(config.clone()).unwrap();
+        assert_eq!(db.len(), 299);
+        assert_eq!(db.vector("v42"), Some(vec![42.0, 0.0, 0.0, 1.0]));
+        // Four codes per sub-space leave ~75 points per cell, more than
+        // the 8 re-ranked by default; re-ranking all of them is exact.
+        assert_eq!(db.search(&[250.0, 5.0, 0.0, 1.0], 5).len(), 5);
+        let hit = &db.search_with_breadth(&[250.0, 5.0, 0.0, 1.0], 1, 300)[0];
+        assert_eq!((hit.id.as_str(), hit.score), ("v250", 0.0));
+        assert!(db.search_with_breadth(&[17.0, 3.0, 0.0, 1.0], 5, 0).iter().all(|r| r.id != "v17"));
+        let odd = db.search_filtered(&[10.0, 3.0, 0.0, 1.0], 3, &Filter::eq("tag", "odd"));
+        assert_eq!(odd.len(), 3);
+        assert!(odd.iter().all(|r| r.metadata == tag("odd")));
+    }
+
+    #[test]
+    fn test_lsh_collection() {
+        let config = VecBaseConfig {
+            index_type: "lsh".into(),
+            ..durable_config("db-lsh")
+        };
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            for round in 0..3 {
+                for i in 0..200 {
+                    let v = vec![1.0, (i % 10) as f32, (i / 10) as f32, round as f32];
+                    db.insert(format!("v{}", i), v, tag(if i % 2 == 0 { "even" } else { "odd" })).unwrap();
+                }
+                for i in (0..200).step_by(3) {
+                    db.delete(&format!("v{}", i)).unwrap();
+                }
+            }
+            db.checkpoint().unwrap();
+            db.insert("v0".into(), vec![1.0, 0.0, 0.0, 9.0], None).unwrap();
+        }
+
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.len(), 134);
+        assert_eq!(db.read().index.kind(), "lsh");
+        let hit = &db.search(&[1.0, 0.0, 0.0, 9.0], 1)[0];
+        assert_eq!(hit.id, "v0");
+        assert!(db.search_with_breadth(&[1.0, 3.0, 0.0, 2.0], 10, 0).iter().all(|r| r.id != "v3"));
+        let odd = db.search_filtered(&[1.0, 1.0, 1.0, 2.0], 5, &Filter::eq("tag", "odd"));
+        assert_eq!(odd.len(), 5);
+        assert!(odd.iter().all(|r| r.metadata == tag("odd")));
+    }
+
+    #[test]
+    fn test_open_with_file_records() {
+        let config = VecBaseConfig {
+            record_storage: "file".into(),
+            ..durable_config("db-file-records")
+        };
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            db.insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], tag("A")).unwrap();
+            db.insert("b".into(), vec![0.0, 1.0, 0.0, 0.0], None).unwrap();
+            db.checkpoint().unwrap();
+            db.delete("a").unwrap();
+            db.insert("c".into(), vec![0.0, 0.0, 1.0, 0.0], tag("C")).unwrap();
+        }
+        assert!(Path::new(&config.storage_path).join(records::RECORDS_FILE).exists());
+
+        let db = VecBase::open(config).unwrap();
+        assert_eq!(db.len(), 2);
+        assert!(db.get("a").unwrap().is_none());
+        assert_eq!(db.get("c").unwrap().unwrap().metadata, tag("C"));
+        let hit = &db.search(&[0.0, 0.0, 1.0, 0.0], 1)[0];
+        assert_eq!((hit.id.as_str(), &hit.metadata), ("c", &tag("C")));
+    }
+
+    #[test]
+    fn test_open_rebuilds_payload_indexes() {
+        let config = VecBaseConfig {
+            brute_threshold: 4,
+            ..durable_config("db-payload")
+        };
+        {
+            let mut db = VecBase::open(config.clone()).unwrap();
+            db.create_payload_index("tag", PayloadKind::Keyword).unwrap();
+            for i in 0..20 {
+                let t = if i < 3 { "A" } else { "B" };
+                db.insert(format!("v{}", i), vec![1.0, i as f32, 0.0, 0.0], tag(t)).unwrap();
+            }
+            db.checkpoint().unwrap();
+            db.delete("v1").unwrap();
+        }
+
+        // Declared in the snapshot, not in `config`.
+        let db = VecBase::open(config.clone()).unwrap();
+        assert!(matches!(plan(&db, &Filter::eq("tag", "A")), FilterPlan::Scan(ref rows) if rows.len() == 2));
+        drop(db);
+
+        let mut declared = config;
+        declared.payload_indexes.insert("missing".into(), PayloadKind::Numeric);
+        let db = VecBase::open(declared).unwrap();
+        assert_eq!(db.config.payload_indexes.len(), 2);
+    }
+
+    #[test]
+    fn test_with_custom_store() {
+        let path = storage::tests::scratch_dir("db-custom-store").join("mine.vbr");
+        let store = FileRecordStore::open(&path).unwrap();
+        let config = VecBaseConfig { dim: 4, ..Default::default() };
+        let db = VecBase::with_store(config, Box::new(store));
+        db.insert("a".into(), vec![0.1, 0.2, 0.3, 0.4], None).unwrap();
+        assert_eq!(db.len(), 1);
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+    }
+
+    #[test]
+    fn test_open_rejects_mismatched_config() {
+        let config = durable_config("db-open-mismatch");
+        {
+            let db = VecBase::open(config.clone()).unwrap();
+            db.insert("a".into(), vec![1.0, 0.0, 0.0, 0.0], None).unwrap();
+            db.checkpoint().unwrap();
+        }
+        let reopened = VecBase::open(VecBaseConfig { dim: 8, ..config });
+        assert!(matches!(reopened, Err(VecBaseError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        fn assert_shareable<T: Send + Sync>() {}
+        assert_shareable::<VecBase>();
+
+        let db = VecBase::new(VecBaseConfig {
+            dim: 8,
+            metric: "euclidean".into(),
+            brute_threshold: 50,
+            ..Default::default()
+        });
+        let point = |i: usize, salt: usize| -> Vec<f32> {
+            (0..8).map(|j| ((i * (j + 1)) as f32 * 0.37 + salt as f32).sin() * 10.0).collect()
+        };
+        // Seed records are never deleted, so every search has ≥ 10 hits.
+        for i in 0..300 {
+            db.insert(format!("s{}", i), point(i, 0), tag(if i % 2 == 0 { "even" } else { "odd" }))
+                .unwrap();
+        }
+
+        const WRITERS: usize = 3;
+        const READERS: usize = 8;
+        const OPS: usize = 200;
+        std::thread::scope(|scope| {
+            for w in 0..WRITERS {
+                let db = &db;
+                scope.spawn(move || {
+                    for i in 0..OPS {
+                        let id = format!("w{}-{}", w, i);
+                        db.insert(id, point(i, w + 1), tag("writer")).unwrap();
+                        if i % 2 == 1 {
+                            db.delete(&format!("w{}-{}", w, i - 1)).unwrap();
+                        }
+                    }
+                });
+            }
+            for r in 0..READERS {
+                let db = &db;
+                scope.spawn(move || {
+                    let filter = Filter::eq("tag", "even");
+                    for i in 0..OPS {
+                        let q = point(i * READERS + r, 3);
+                        let hits = db.search(&q, 10);
+                        assert_eq!(hits.len(), 10);
+                        assert!(hits.windows(2).all(|w| w[0].score >= w[1].score));
+                        let even = db.search_filtered(&q, 5, &filter);
+                        assert_eq!(even.len(), 5);
+                        assert!(even.iter().all(|h| filter.matches(h.metadata.as_ref())));
+                        assert!(db.get(&format!("s{}", i % 300)).unwrap().is_some());
+                    }
+                });
+            }
+        });
+
+        // Every writer kept its odd-numbered inserts.
+        assert_eq!(db.len(), 300 + WRITERS * OPS / 2);
+        let state = db.read();
+        assert_eq!(state.index.len(), state.records.len());
+        for record in state.records.iter() {
+            let record = record.unwrap();
+            assert_eq!(state.index.row(&record.id), Some(record.row));
+        }
+        drop(state);
+        assert_eq!(db.search(&point(7, 0), 1)[0].id, "s7");
+    }
+
     #[test]
     fn test_config_from_default() {
         let cfg = VecBaseConfig::default();
         assert_eq!(cfg.dim, 128);
         assert_eq!(cfg.metric, "cosine");
+        assert_eq!(cfg.hnsw_m, 16);
+        assert_eq!(cfg.ef_search, 64);
+        assert_eq!(cfg.brute_threshold, 500);
+    }
+
+    #[test]
+    fn test_search_with_ef_override() {
+        let db = VecBase::new(VecBaseConfig {
+            dim: 4,
+            brute_threshold: 0,
+            ..Default::default()
+        });
+        for i in 0..50 {
+            let v = vec![i as f32, 1.0, (i % 7) as f32, 0.5];
+            db.insert(format!("v{}", i), v, None).unwrap();
+        }
+        let q = [10.0, 1.0, 3.0, 0.5];
+        assert_eq!(db.search_with_ef(&q, 5, 200).len(), 5);
+        assert_eq!(db.search_with_ef(&q, 5, 1).len(), 5);
     }
 }
@@ -0,0 +1,678 @@
+// VecBase — lsh.rs
+// Locality-sensitive hashing index: multiple hash tables of projections.
+// Author: d65v <https://github.com/d65v>
+//
+// NOTE: Each of `tables` hash tables hashes a vector with `hashes` random
+// projections and files it in the bucket of the combined key.
+//
+//   - Cosine / dot: random hyperplanes (Charikar, 2002). Each projection
+//     contributes one sign bit; two vectors share a bit with probability
+//     1 − θ/π, so buckets gather vectors at small angles.
+//   - Euclidean: p-stable projections (Datar et al., 2004). Each projection
+//     contributes ⌊(a·v + b) / w⌋ for a Gaussian `a` and b ∈ [0, w), so
+//     buckets gather vectors at small distances relative to `bucket_width`.
+//
+// A query gathers every vector sharing a bucket with it in any table and
+// ranks those candidates exactly. Multi-probe (Lv et al., 2007) also visits
+// the `probes` buckets per table whose keys differ in the one projection the
+// query lies closest to a boundary of, raising recall without more tables.
+//
+// Inserts and removes touch `tables` buckets each, never other vectors. Each
+// row remembers its position in every bucket so a remove is a swap-remove;
+// the bucket keys are recomputed from the stored vector.
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::{dot, rank_score, Metric};
+use crate::index::{self, to_scores, AnnIndex, IndexType, TopK};
+use crate::processing::IdTable;
+use crate::vectors::{FreeRows, VectorStore};
+use crate::{Result, VecBaseConfig, VecBaseError};
+
+// ── LSH Index ─────────────────────────────────────────────────────────────────
+
+/// Hashing and search parameters for [`LshIndex`], read from
+/// `index_params` (e.g. `{"tables": 16, "probes": 8}`).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LshParams {
+    /// Number of hash tables; more tables raise recall and memory
+    pub tables: usize,
+    /// Projections combined into each table's key (at most 64); more make
+    /// buckets smaller and more selective
+    pub hashes: usize,
+    /// Euclidean only: width `w` of a p-stable projection's slots, in the
+    /// data's distance units. Roughly the neighbor distance to catch.
+    pub bucket_width: f32,
+    /// Default number of extra buckets probed per table
+    pub probes: usize,
+}
+
+impl Default for LshParams {
+    fn default() -> Self {
+        Self {
+            tables: 8,
+            hashes: 12,
+            bucket_width: 4.0,
+            probes: 4,
+        }
+    }
+}
+
+/// Seed for the random projections.
+const PROJECTION_SEED: u64 = 0x5eed_15a0;
+
+/// `slots` entry of a row that is in no bucket.
+const UNLISTED: u32 = u32::MAX;
+
+/// A locality-sensitive hashing approximate nearest neighbor index. Same
+/// row, id and score conventions as
+/// [`HnswIndex`](crate::processing::HnswIndex).
+///
+/// Results are exact among the candidates the hash tables return; when they
+/// return fewer than `top_k`, the search falls back to an exact scan.
+#[derive(Serialize, Deserialize)]
+pub struct LshIndex {
+    dim: usize,
+    max_elements: usize,
+    metric: Metric,
+    tables: usize,
+    hashes: usize,
+    bucket_width: f32,
+    probes: usize,
+    /// `tables · hashes` projection vectors of `dim` components each
+    projections: Vec<f32>,
+    /// Euclidean only: `tables · hashes` offsets in [0, bucket_width)
+    offsets: Vec<f32>,
+    vectors: VectorStore,
+    /// External id ↔ row
+    ids: IdTable,
+    /// Free rows, reused before the store grows
+    free: FreeRows,
+    /// Per table: bucket key → rows
+    buckets: Vec<HashMap<u64, Vec<u32>>>,
+    /// Row · tables + table → position of the row in its bucket there,
+    /// [`UNLISTED`] for free rows
+    slots: Vec<u32>,
+}
+
+/// One table's hash of a vector: the raw per-projection values and the
+/// bucket key they combine into.
+struct TableHash {
+    /// Sign bits (hyperplanes) or slot numbers (p-stable), one per projection
+    codes: Vec<i32>,
+    /// Distance of each projection to the closest slot boundary, in slots,
+    /// paired with the code change that crosses it
+    margins: Vec<(f32, usize, i32)>,
+}
+
+impl LshIndex {
+    pub fn new(dim: usize, max_elements: usize, metric: Metric) -> Self {
+        Self::with_params(dim, max_elements, metric, LshParams::default())
+    }
+
+    /// Create an index with explicit parameters. `tables` is clamped to at
+    /// least 1 and `hashes` to 1..=64.
+    pub fn with_params(dim: usize, max_elements: usize, metric: Metric, params: LshParams) -> Self {
+        Self::with_store(max_elements, metric, params, VectorStore::memory(dim))
+    }
+
+    /// Create an empty index over an explicit vector store, e.g. a
+    /// memory-mapped one from [`VectorStore::mmap`]. Any rows already in
+    /// the store are treated as free and will be overwritten.
+    pub fn with_store(max_elements: usize, metric: Metric, params: LshParams, vectors: VectorStore) -> Self {
+        let dim = vectors.dim();
+        let tables = params.tables.max(1);
+        let hashes = params.hashes.clamp(1, 64);
+        let bucket_width = if params.bucket_width > 0.0 { params.bucket_width } else { 4.0 };
+
+        let mut rng = StdRng::seed_from_u64(PROJECTION_SEED);
+        let projections = (0..tables * hashes * dim).map(|_| gaussian(&mut rng)).collect();
+        let offsets = match metric {
+            Metric::Euclidean => (0..tables * hashes).map(|_| rng.gen_range(0.0..bucket_width)).collect(),
+            _ => Vec::new(),
+        };
+
+        let allocated = vectors.len() as u32;
+        Self {
+            dim,
+            max_elements,
+            metric,
+            tables,
+            hashes,
+            bucket_width,
+            probes: params.probes,
+            projections,
+            offsets,
+            ids: IdTable::with_free_rows(allocated as usize),
+            free: FreeRows::all(&vectors),
+            buckets: vec![HashMap::new(); tables],
+            slots: vec![UNLISTED; allocated as usize * tables],
+            vectors,
+        }
+    }
+
+    /// Insert a new vector and return the row it was stored at.
+    /// Re-inserting an existing id replaces its vector. O(1) in the number
+    /// of stored vectors.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::CapacityExceeded` if the index already holds
+    /// `max_elements` vectors, or `VecBaseError::StorageError` if the vector
+    /// store cannot grow.
+    pub fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        debug_assert_eq!(
+            vector.len(),
+            self.dim,
+            "insert: vector dim {} ≠ index dim {}",
+            vector.len(),
+            self.dim
+        );
+
+        // The new vector goes through a free row like any other, so a mapped
+        // store keeps the old one for the last checkpoint (see `FreeRows`).
+        self.remove(&id);
+        if self.ids.len() >= self.max_elements {
+            return Err(VecBaseError::CapacityExceeded {
+                max_elements: self.max_elements,
+            });
+        }
+
+        let row = match self.free.pop() {
+            Some(r) => {
+                self.vectors.set(r, &vector);
+                r
+            }
+            None => {
+                let r = self.vectors.push(&vector)?;
+                self.slots.resize(self.slots.len() + self.tables, UNLISTED);
+                r
+            }
+        };
+        self.ids.insert(row, &id);
+        self.file(row);
+        Ok(row)
+    }
+
+    /// Remove `id`; its row is reused by a later insert. O(1) in the number
+    /// of stored vectors.
+    pub fn remove(&mut self, id: &str) {
+        if let Some(row) = self.ids.row(id) {
+            self.unlist(row);
+            self.ids.remove_row(row);
+            self.free.push(row, &self.vectors);
+        }
+    }
+
+    /// File `row` in its bucket of every table.
+    fn file(&mut self, row: u32) {
+        let keys = self.keys(self.vectors.get(row));
+        for (t, key) in keys.into_iter().enumerate() {
+            let bucket = self.buckets[t].entry(key).or_default();
+            self.slots[row as usize * self.tables + t] = bucket.len() as u32;
+            bucket.push(row);
+        }
+    }
+
+    /// Take `row` out of its buckets, moving each bucket's last row into
+    /// its place.
+    fn unlist(&mut self, row: u32) {
+        let keys = self.keys(self.vectors.get(row));
+        for (t, key) in keys.into_iter().enumerate() {
+            let pos = std::mem::replace(&mut self.slots[row as usize * self.tables + t], UNLISTED);
+            if pos == UNLISTED {
+                continue;
+            }
+            let Some(bucket) = self.buckets[t].get_mut(&key) else {
+                continue;
+            };
+            bucket.swap_remove(pos as usize);
+            if let Some(&moved) = bucket.get(pos as usize) {
+                self.slots[moved as usize * self.tables + t] = pos;
+            }
+            if bucket.is_empty() {
+                self.buckets[t].remove(&key);
+            }
+        }
+    }
+
+    /// Bucket key of `v` in every table.
+    fn keys(&self, v: &[f32]) -> Vec<u64> {
+        (0..self.tables).map(|t| combine(&self.hash(t, v, false).codes)).collect()
+    }
+
+    /// Hash `v` for table `t`. Margins are only worked out for queries.
+    fn hash(&self, t: usize, v: &[f32], margins: bool) -> TableHash {
+        let mut hash = TableHash {
+            codes: Vec::with_capacity(self.hashes),
+            margins: Vec::new(),
+        };
+        for j in 0..self.hashes {
+            let p = t * self.hashes + j;
+            let projected = dot(&self.projections[p * self.dim..(p + 1) * self.dim], v);
+            if self.offsets.is_empty() {
+                let bit = projected >= 0.0;
+                hash.codes.push(bit as i32);
+                if margins {
+                    hash.margins.push((projected.abs(), j, if bit { -1 } else { 1 }));
+                }
+            } else {
+                let slot = (projected + self.offsets[p]) / self.bucket_width;
+                let code = slot.floor();
+                hash.codes.push(code as i32);
+                if margins {
+                    let frac = slot - code;
+                    hash.margins.push((frac, j, -1));
+                    hash.margins.push((1.0 - frac, j, 1));
+                }
+            }
+        }
+        hash
+    }
+
+    /// Rows sharing a bucket with `query` in any table, probing `probes`
+    /// extra buckets per table.
+    fn candidates(&self, query: &[f32], probes: usize) -> HashSet<u32> {
+        let mut found = HashSet::new();
+        for t in 0..self.tables {
+            let mut hash = self.hash(t, query, probes > 0);
+            let mut visit = |codes: &[i32]| {
+                if let Some(bucket) = self.buckets[t].get(&combine(codes)) {
+                    found.extend(bucket.iter().copied());
+                }
+            };
+            visit(&hash.codes);
+
+            hash.margins.sort_by(|a, b| a.0.total_cmp(&b.0));
+            for &(_, j, step) in hash.margins.iter().take(probes) {
+                hash.codes[j] += step;
+                visit(&hash.codes);
+                hash.codes[j] -= step;
+            }
+        }
+        found
+    }
+
+    /// Top-k nearest neighbors of `query`, probing the default number of
+    /// extra buckets per table.
+    pub fn search(&self, query: &[f32], top_k: usize) -> Vec<(String, f32)> {
+        self.search_with_probes(query, top_k, self.probes)
+    }
+
+    /// Like [`search`](Self::search), but probing `probes` extra buckets per
+    /// table. More probes trade latency for recall.
+    pub fn search_with_probes(&self, query: &[f32], top_k: usize, probes: usize) -> Vec<(String, f32)> {
+        self.search_rows(query, top_k, probes)
+            .into_iter()
+            .filter_map(|(r, s)| self.ids.id(r).map(|id| (id.to_string(), s)))
+            .collect()
+    }
+
+    /// Like [`search_with_probes`](Self::search_with_probes), but returns
+    /// rows so callers can resolve ids and vectors without copying them.
+    pub fn search_rows(&self, query: &[f32], top_k: usize, probes: usize) -> Vec<(u32, f32)> {
+        self.search_rows_filtered_with(query, top_k, probes, |_| true)
+    }
+
+    /// Search restricted to rows for which `accept` returns true. When the
+    /// hash tables turn up fewer than `top_k` accepted rows, an exact scan
+    /// fills the gap, so fewer than `top_k` results means fewer than
+    /// `top_k` rows match.
+    pub fn search_rows_filtered<F: Fn(u32) -> bool>(&self, query: &[f32], top_k: usize, accept: F) -> Vec<(u32, f32)> {
+        self.search_rows_filtered_with(query, top_k, self.probes, accept)
+    }
+
+    fn search_rows_filtered_with<F: Fn(u32) -> bool>(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        probes: usize,
+        accept: F,
+    ) -> Vec<(u32, f32)> {
+        if top_k == 0 {
+            return vec![];
+        }
+        let candidates = self.candidates(query, probes);
+        let mut best = TopK::new(top_k);
+        for &r in candidates.iter().filter(|&&r| accept(r)) {
+            best.push(r, rank_score(&self.metric, query, self.vectors.get(r)));
+        }
+        if !best.is_full() && candidates.len() < self.ids.len() {
+            best = TopK::new(top_k);
+            for r in self.ids.rows().filter(|&r| accept(r)) {
+                best.push(r, rank_score(&self.metric, query, self.vectors.get(r)));
+            }
+        }
+        to_scores(&self.metric, best.into_sorted())
+    }
+
+    /// Exact top-k over just `rows`, e.g. a candidate set from a payload
+    /// index. Free rows are skipped.
+    pub fn search_rows_among(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        rows: impl IntoIterator<Item = u32>,
+    ) -> Vec<(u32, f32)> {
+        let mut best = TopK::new(top_k);
+        for r in rows.into_iter().filter(|&r| self.ids.id(r).is_some()) {
+            best.push(r, rank_score(&self.metric, query, self.vectors.get(r)));
+        }
+        to_scores(&self.metric, best.into_sorted())
+    }
+
+    /// Number of indexed vectors.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.len() == 0
+    }
+
+    /// Whether `id` is indexed.
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Stored vector for `id`, read straight from the vector store.
+    pub fn vector(&self, id: &str) -> Option<&[f32]> {
+        self.ids.row(id).map(|r| self.vectors.get(r))
+    }
+
+    /// Row `id` is stored at.
+    pub fn row(&self, id: &str) -> Option<u32> {
+        self.ids.row(id)
+    }
+
+    /// Id stored at `row`, or `None` for free or out-of-range rows.
+    pub fn id_at(&self, row: u32) -> Option<&str> {
+        self.ids.id(row)
+    }
+
+    /// Vector stored at `row`, or `None` for free or out-of-range rows.
+    pub fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        self.ids.id(row).map(|_| self.vectors.get(row))
+    }
+
+    /// Approximate heap bytes held by vectors, ids, projections and buckets.
+    pub fn heap_bytes(&self) -> usize {
+        // hashbrown: key + value + one control byte per slot
+        let entry = std::mem::size_of::<(u64, Vec<u32>)>() + 1;
+        let buckets: usize = self
+            .buckets
+            .iter()
+            .map(|table| table.capacity() * entry + table.values().map(|b| b.capacity() * 4).sum::<usize>())
+            .sum();
+        self.vectors.heap_bytes()
+            + self.ids.heap_bytes()
+            + (self.projections.capacity() + self.offsets.capacity()) * 4
+            + buckets
+            + self.slots.capacity() * 4
+            + self.free.heap_bytes()
+    }
+
+    /// Flush the vector store to disk (no-op when held in memory).
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the flush fails.
+    pub fn flush(&self) -> Result<()> {
+        self.vectors.flush()
+    }
+
+    /// Dimensionality of indexed vectors.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Metric vectors are hashed and ranked by.
+    pub fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    /// Parameters this index was created with.
+    pub fn params(&self) -> LshParams {
+        LshParams {
+            tables: self.tables,
+            hashes: self.hashes,
+            bucket_width: self.bucket_width,
+            probes: self.probes,
+        }
+    }
+}
+
+impl AnnIndex for LshIndex {
+    fn kind(&self) -> &'static str {
+        "lsh"
+    }
+
+    fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        LshIndex::insert(self, id, vector)
+    }
+
+    fn remove(&mut self, id: &str) {
+        LshIndex::remove(self, id)
+    }
+
+    fn search_rows(&self, query: &[f32], top_k: usize, breadth: Option<usize>) -> Vec<(u32, f32)> {
+        LshIndex::search_rows(self, query, top_k, breadth.unwrap_or(self.probes))
+    }
+
+    fn search_rows_filtered(&self, query: &[f32], top_k: usize, accept: &dyn Fn(u32) -> bool) -> Vec<(u32, f32)> {
+        LshIndex::search_rows_filtered(self, query, top_k, accept)
+    }
+
+    fn search_rows_among(&self, query: &[f32], top_k: usize, rows: &mut dyn Iterator<Item = u32>) -> Vec<(u32, f32)> {
+        LshIndex::search_rows_among(self, query, top_k, rows)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        index::encode(self)
+    }
+
+    fn row(&self, id: &str) -> Option<u32> {
+        self.ids.row(id)
+    }
+
+    fn id_at(&self, row: u32) -> Option<&str> {
+        self.ids.id(row)
+    }
+
+    fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        LshIndex::vector_at(self, row)
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    fn heap_bytes(&self) -> usize {
+        LshIndex::heap_bytes(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.vectors.flush()
+    }
+
+    fn reserve(&mut self, additional: usize) -> Result<()> {
+        self.vectors.reserve(additional)
+    }
+
+    fn release_free_rows(&mut self) {
+        self.free.release();
+    }
+}
+
+/// Standard normal sample (Box–Muller).
+fn gaussian(rng: &mut StdRng) -> f32 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen_range(0.0..1.0);
+    ((-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()) as f32
+}
+
+/// Fold one table's codes into a bucket key.
+fn combine(codes: &[i32]) -> u64 {
+    codes.iter().fold(0xcbf2_9ce4_8422_2325, |key, &c| {
+        (key ^ c as u32 as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Registry entry for `index_type = "lsh"`, with [`LshParams`] from
+/// `index_params`.
+pub const INDEX_TYPE: IndexType = IndexType {
+    name: "lsh",
+    build: build_index,
+    load: index::decode::<LshIndex>,
+};
+
+fn build_index(config: &VecBaseConfig, metric: Metric, vectors: VectorStore) -> Box<dyn AnnIndex> {
+    Box::new(LshIndex::with_store(config.max_elements, metric, index::parse_params(config), vectors))
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::normalize;
+
+    fn random(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect()
+    }
+
+    /// `n` points around 50 random centers, so near neighbors exist.
+    fn clustered(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let centers = random(50, dim, seed);
+        let noise = random(n, dim, seed + 1);
+        (0..n)
+            .map(|i| centers[i % 50].iter().zip(&noise[i]).map(|(c, e)| c + 0.1 * e).collect())
+            .collect()
+    }
+
+    fn build(data: &[Vec<f32>], metric: Metric, params: LshParams) -> LshIndex {
+        let mut idx = LshIndex::with_params(data[0].len(), 100_000, metric, params);
+        for (i, v) in data.iter().enumerate() {
+            idx.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+        idx
+    }
+
+    fn recall(idx: &LshIndex, queries: &[Vec<f32>], probes: usize) -> f32 {
+        let mut hits = 0;
+        for q in queries {
+            let approx: HashSet<u32> = idx.search_rows(q, 10, probes).into_iter().map(|(r, _)| r).collect();
+            let exact = idx.search_rows_among(q, 10, 0..idx.len() as u32);
+            hits += exact.iter().filter(|(r, _)| approx.contains(r)).count();
+        }
+        hits as f32 / (queries.len() * 10) as f32
+    }
+
+    /// Every live row sits in exactly one bucket per table, at the position
+    /// it records.
+    fn assert_consistent(idx: &LshIndex) {
+        for (t, table) in idx.buckets.iter().enumerate() {
+            let filed: usize = table.values().map(Vec::len).sum();
+            assert_eq!(filed, idx.len());
+            for bucket in table.values() {
+                for (p, &r) in bucket.iter().enumerate() {
+                    assert_eq!(idx.slots[r as usize * idx.tables + t], p as u32);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cosine_recall_rises_with_probes() {
+        let data: Vec<Vec<f32>> = clustered(4_000, 32, 1).iter().map(|v| normalize(v)).collect();
+        let idx = build(&data, Metric::Cosine, LshParams::default());
+        let queries: Vec<Vec<f32>> = clustered(50, 32, 1).iter().map(|v| normalize(v)).collect();
+        let base = recall(&idx, &queries, 0);
+        let probed = recall(&idx, &queries, 8);
+        assert!(probed >= base, "probing lowered recall: {} → {}", base, probed);
+        assert!(probed > 0.8, "cosine recall@10 with 8 probes: {}", probed);
+    }
+
+    #[test]
+    fn test_euclidean_recall() {
+        let data = clustered(4_000, 16, 2);
+        let params = LshParams {
+            bucket_width: 1.0,
+            hashes: 8,
+            ..LshParams::default()
+        };
+        let idx = build(&data, Metric::Euclidean, params);
+        let r = recall(&idx, &clustered(50, 16, 2), 4);
+        assert!(r > 0.8, "euclidean recall@10: {}", r);
+    }
+
+    #[test]
+    fn test_remove_and_replace() {
+        let data = random(500, 8, 3);
+        let mut idx = build(&data, Metric::Euclidean, LshParams::default());
+        assert_consistent(&idx);
+
+        idx.remove("v10");
+        idx.remove("v11");
+        assert!(!idx.contains("v10"));
+        assert!(idx.search(&data[10], 5).iter().all(|(id, _)| id != "v10"));
+        assert_consistent(&idx);
+
+        idx.insert("v12".into(), data[10].clone()).unwrap();
+        idx.insert("new".into(), data[11].clone()).unwrap();
+        assert_eq!(idx.search(&data[10], 1)[0].0, "v12");
+        assert_eq!(idx.len(), 499);
+        assert_consistent(&idx);
+
+        for i in 0..500 {
+            idx.remove(&format!("v{}", i));
+        }
+        assert_eq!(idx.len(), 1);
+        assert!(idx.buckets.iter().all(|t| t.len() == 1));
+    }
+
+    #[test]
+    fn test_short_candidate_lists_fall_back_to_exact() {
+        let data = random(300, 8, 4);
+        let params = LshParams {
+            hashes: 64,
+            probes: 0,
+            ..LshParams::default()
+        };
+        let idx = build(&data, Metric::Cosine, params);
+        let q = random(1, 8, 5).remove(0);
+        assert_eq!(idx.search_rows(&q, 10, 0), idx.search_rows_among(&q, 10, 0..300));
+
+        let found = idx.search_rows_filtered(&q, 10, |r| r % 30 == 0);
+        assert_eq!(found, idx.search_rows_among(&q, 10, (0..300).filter(|r| r % 30 == 0)));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = random(200, 8, 6);
+        let idx = build(&data, Metric::Euclidean, LshParams::default());
+        let loaded = index::decode::<LshIndex>(&idx.to_bytes().unwrap()).unwrap();
+        let loaded = loaded.downcast_ref::<LshIndex>().unwrap();
+        assert_eq!(loaded.params(), idx.params());
+        for q in random(5, 8, 7) {
+            assert_eq!(loaded.search_rows(&q, 5, 2), idx.search_rows(&q, 5, 2));
+        }
+    }
+}
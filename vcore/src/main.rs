@@ -3,7 +3,10 @@
 // Author: d65v <https://github.com/d65v>
 
 use std::env;
+use std::future::Future;
+use std::sync::Arc;
 
+use vcore::server::{ServerConfig, SharedDb};
 use vcore::{VecBase, VecBaseConfig};
 
 fn main() {
@@ -21,6 +24,7 @@ fn main() {
 
     match mode {
         "run" => run_server(),
+        "grpc" => run_grpc(),
         "bench" => run_bench(),
         "help" | "--help" | "-h" => print_help(),
         unknown => {
@@ -31,42 +35,92 @@ fn main() {
 }
 
 fn run_server() {
+    let server = ServerConfig::from_env();
+    let addr = server.addr();
+    serve_until_shutdown(&addr, |listener, db| async move {
+        vcore::server::serve(listener, db, shutdown_signal())
+            .await
+            .map_err(|e| e.to_string())
+    });
+}
+
+fn run_grpc() {
+    let server = ServerConfig::from_env();
+    let addr = server.grpc_addr();
+    serve_until_shutdown(&addr, |listener, db| async move {
+        vcore::grpc::serve(listener, db, shutdown_signal())
+            .await
+            .map_err(|e| e.to_string())
+    });
+}
+
+/// Open the database, serve it on `addr` with `serve` until shutdown, then
+/// write a checkpoint. Exits the process on any failure.
+fn serve_until_shutdown<F, Fut>(addr: &str, serve: F)
+where
+    F: FnOnce(tokio::net::TcpListener, SharedDb) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
     let config = VecBaseConfig::from_env();
 
     log::info!(
-        "Config: dim={}, metric={}, max_elements={}",
+        "Config: dim={}, metric={}, max_elements={}, M={}, ef_construction={}, ef_search={}",
         config.dim,
         config.metric,
-        config.max_elements
+        config.max_elements,
+        config.hnsw_m,
+        config.ef_construction,
+        config.ef_search
     );
 
-    let mut db = VecBase::new(config);
-
-    // Demo: insert a few vectors and query
-    // In a real deployment this would be replaced by a TCP/HTTP/gRPC server loop.
-    log::info!("Inserting demo vectors...");
+    let db = match VecBase::open(config) {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            eprintln!("[VecBase] Cannot open storage: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    for i in 0..10u32 {
-        let id = format!("vec_{}", i);
-        let vector: Vec<f32> = (0..db.config.dim)
-            .map(|j| (i as f32 + j as f32) / 100.0)
-            .collect();
-        db.insert(id.clone(), vector, Some(format!("demo metadata {}", i)))
-            .expect("insert failed");
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start tokio runtime");
+    let served = runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| e.to_string())?;
+        serve(listener, db.clone()).await
+    });
+    if let Err(e) = served {
+        eprintln!("[VecBase] Server error on {}: {}", addr, e);
+        std::process::exit(1);
     }
 
-    log::info!("Inserted 10 demo vectors.");
+    log::info!("Shutting down, writing checkpoint...");
+    if let Err(e) = db.checkpoint() {
+        eprintln!("[VecBase] Checkpoint failed: {}", e);
+        std::process::exit(1);
+    }
+}
 
-    // Query with a random-ish vector
-    let query: Vec<f32> = (0..db.config.dim).map(|j| j as f32 / 100.0).collect();
-    let results = db.search(&query, 3);
+/// Resolves on Ctrl-C (and SIGTERM on Unix, as sent by `docker stop`).
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    println!("\n[VecBase] Top-3 results for demo query:");
-    for r in &results {
-        println!("  id={:8}  score={:.6}  meta={:?}", r.id, r.score, r.metadata);
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
-
-    log::info!("VecBase demo complete.");
 }
 
 fn run_bench() {
@@ -79,7 +133,7 @@ fn run_bench() {
         ..VecBaseConfig::default()
     };
 
-    let mut db = VecBase::new(config.clone());
+    let db = VecBase::new(config.clone());
     let n = 10_000usize;
 
     let t0 = Instant::now();
@@ -107,16 +161,31 @@ USAGE:
   vecbase [MODE]
 
 MODES:
-  run     Start VecBase (default)
+  run     Start the HTTP JSON API on VECBASE_HOST:VECBASE_PORT (default)
+  grpc    Start the gRPC API on VECBASE_HOST:VECBASE_GRPC_PORT
   bench   Run internal performance benchmark
   help    Show this message
 
 ENVIRONMENT:
-  VECBASE_DIM             Vector dimensionality (default: 128)
-  VECBASE_METRIC          Similarity metric: cosine | euclidean | dot (default: cosine)
-  VECBASE_MAX_ELEMENTS    Max vectors to hold in memory (default: 1000000)
-  VECBASE_STORAGE_PATH    Path for persistence (default: ./data)
-  RUST_LOG                Log level: info | debug | warn | error
+  VECBASE_DIM                 Vector dimensionality (default: 128)
+  VECBASE_METRIC              Similarity metric: cosine | euclidean | dot (default: cosine)
+  VECBASE_MAX_ELEMENTS        Max vectors to hold in memory (default: 1000000)
+  VECBASE_STORAGE_PATH        Path for persistence (default: ./data)
+  VECBASE_INDEX_TYPE          ANN index: hnsw | ivf | lsh | flat | pq (default: hnsw)
+  VECBASE_INDEX_PARAMS        Index settings as JSON, e.g. {{"nlist": 1024}} for ivf, {{"tables": 16}} for lsh
+  VECBASE_HNSW_M              HNSW neighbors per node (default: 16)
+  VECBASE_EF_CONSTRUCTION     HNSW build beam width (default: 100)
+  VECBASE_EF_SEARCH           HNSW query beam width (default: 64)
+  VECBASE_BRUTE_THRESHOLD     Max size searched by brute force (default: 500)
+  VECBASE_VECTOR_STORAGE      Vector storage backend: memory | mmap (default: memory)
+  VECBASE_VECTOR_QUANTIZATION Quantized copy searches score: none | f16 | int8 (default: none)
+  VECBASE_RESCORE             Re-score quantized results with full vectors (default: true)
+  VECBASE_RECORD_STORAGE      Record storage backend: memory | file (default: memory)
+  VECBASE_PAYLOAD_INDEXES     Indexed metadata fields, e.g. lang:keyword,year:numeric
+  VECBASE_HOST                Interface for the HTTP API (default: 0.0.0.0)
+  VECBASE_PORT                Port for the HTTP API (default: 7777)
+  VECBASE_GRPC_PORT           Port for the gRPC API (default: 7778)
+  RUST_LOG                    Log level: info | debug | warn | error
 
 AUTHOR:
   d65v <https://github.com/d65v>
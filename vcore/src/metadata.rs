@@ -0,0 +1,362 @@
+// VecBase — metadata.rs
+// Typed record metadata and filter expressions for filtered search.
+// Author: d65v <https://github.com/d65v>
+//
+// Metadata is a JSON-like map from field name to `Value` (string, number,
+// bool, or a list of those). A `Filter` is evaluated against a record's
+// metadata while the HNSW graph is traversed, so filtered queries still fill
+// top-k instead of losing hits to a post-filter.
+//
+// Matching rules:
+//   - Eq / In / Range on a list field match if any element matches
+//   - a missing field never matches Eq, In or Range (so Ne matches it)
+//   - numbers compare as f64; Range only matches numbers
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Record metadata: field name → value.
+pub type Metadata = BTreeMap<String, Value>;
+
+/// Build a [`Metadata`] map from `key => value` pairs.
+///
+/// ```
+/// let meta = vcore::metadata!{ "lang" => "en", "year" => 2021, "tags" => vec!["a", "b"] };
+/// assert_eq!(meta.len(), 3);
+/// ```
+#[macro_export]
+macro_rules! metadata {
+    ($($key:expr => $value:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut meta = $crate::metadata::Metadata::new();
+        $(meta.insert(::std::string::String::from($key), $crate::metadata::Value::from($value));)*
+        meta
+    }};
+}
+
+// ── Values ────────────────────────────────────────────────────────────────────
+
+/// A single metadata value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    List(Vec<Value>),
+}
+
+impl Value {
+    /// Convert from JSON. Returns `None` for `null` and objects, which have
+    /// no metadata equivalent.
+    pub fn from_json(json: &serde_json::Value) -> Option<Self> {
+        match json {
+            serde_json::Value::String(s) => Some(Value::String(s.clone())),
+            serde_json::Value::Number(n) => n.as_f64().map(Value::Number),
+            serde_json::Value::Bool(b) => Some(Value::Bool(*b)),
+            serde_json::Value::Array(items) => items
+                .iter()
+                .map(Value::from_json)
+                .collect::<Option<_>>()
+                .map(Value::List),
+            serde_json::Value::Null | serde_json::Value::Object(_) => None,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::String(s) => serde_json::Value::String(s.clone()),
+            Value::Number(n) => serde_json::Number::from_f64(*n)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            Value::Bool(b) => serde_json::Value::Bool(*b),
+            Value::List(items) => {
+                serde_json::Value::Array(items.iter().map(Value::to_json).collect())
+            }
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// `self` itself, or each element if `self` is a list.
+    pub(crate) fn scalars(&self) -> Box<dyn Iterator<Item = &Value> + '_> {
+        match self {
+            Value::List(items) => Box::new(items.iter()),
+            other => Box::new(std::iter::once(other)),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(n: f32) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Convert a JSON object into metadata. Returns `None` if `json` is not an
+/// object or holds a value [`Value::from_json`] rejects.
+pub fn metadata_from_json(json: &serde_json::Value) -> Option<Metadata> {
+    json.as_object()?
+        .iter()
+        .map(|(k, v)| Value::from_json(v).map(|v| (k.clone(), v)))
+        .collect()
+}
+
+pub fn metadata_to_json(meta: &Metadata) -> serde_json::Value {
+    serde_json::Value::Object(meta.iter().map(|(k, v)| (k.clone(), v.to_json())).collect())
+}
+
+// ── Filters ───────────────────────────────────────────────────────────────────
+
+/// Numeric bounds for [`Filter::Range`]; unset bounds are open.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    pub gt: Option<f64>,
+    pub gte: Option<f64>,
+    pub lt: Option<f64>,
+    pub lte: Option<f64>,
+}
+
+impl Range {
+    pub fn contains(&self, x: f64) -> bool {
+        self.gt.is_none_or(|b| x > b)
+            && self.gte.is_none_or(|b| x >= b)
+            && self.lt.is_none_or(|b| x < b)
+            && self.lte.is_none_or(|b| x <= b)
+    }
+}
+
+/// A boolean expression over record metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+    /// `field == value`
+    Eq(String, Value),
+    /// `field != value` (also true when the field is missing)
+    Ne(String, Value),
+    /// `field` is a number within the range
+    Range(String, Range),
+    /// `field` equals one of the values
+    In(String, Vec<Value>),
+    /// Every sub-filter matches (true when empty)
+    And(Vec<Filter>),
+    /// At least one sub-filter matches (false when empty)
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn eq(field: &str, value: impl Into<Value>) -> Self {
+        Filter::Eq(field.to_string(), value.into())
+    }
+
+    pub fn ne(field: &str, value: impl Into<Value>) -> Self {
+        Filter::Ne(field.to_string(), value.into())
+    }
+
+    pub fn range(field: &str, range: Range) -> Self {
+        Filter::Range(field.to_string(), range)
+    }
+
+    /// `field >= min`
+    pub fn gte(field: &str, min: f64) -> Self {
+        Filter::range(
+            field,
+            Range {
+                gte: Some(min),
+                ..Range::default()
+            },
+        )
+    }
+
+    /// `field <= max`
+    pub fn lte(field: &str, max: f64) -> Self {
+        Filter::range(
+            field,
+            Range {
+                lte: Some(max),
+                ..Range::default()
+            },
+        )
+    }
+
+    pub fn one_of<V: Into<Value>>(field: &str, values: impl IntoIterator<Item = V>) -> Self {
+        Filter::In(
+            field.to_string(),
+            values.into_iter().map(Into::into).collect(),
+        )
+    }
+
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::And(mut all) => {
+                all.push(other);
+                Filter::And(all)
+            }
+            first => Filter::And(vec![first, other]),
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Or(mut any) => {
+                any.push(other);
+                Filter::Or(any)
+            }
+            first => Filter::Or(vec![first, other]),
+        }
+    }
+
+    /// Evaluate against a record's metadata (`None` = no metadata at all).
+    pub fn matches(&self, meta: Option<&Metadata>) -> bool {
+        let field = |name: &str| meta.and_then(|m| m.get(name));
+        match self {
+            Filter::Eq(name, value) => field(name).is_some_and(|v| value_eq(v, value)),
+            Filter::Ne(name, value) => !field(name).is_some_and(|v| value_eq(v, value)),
+            Filter::Range(name, range) => field(name).is_some_and(|v| {
+                v.scalars()
+                    .any(|x| x.as_f64().is_some_and(|x| range.contains(x)))
+            }),
+            Filter::In(name, values) => {
+                field(name).is_some_and(|v| values.iter().any(|value| value_eq(v, value)))
+            }
+            Filter::And(all) => all.iter().all(|f| f.matches(meta)),
+            Filter::Or(any) => any.iter().any(|f| f.matches(meta)),
+            Filter::Not(inner) => !inner.matches(meta),
+        }
+    }
+}
+
+impl std::ops::Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+}
+
+/// Equality with list fields matching on any element.
+fn value_eq(field: &Value, value: &Value) -> bool {
+    field == value || (matches!(field, Value::List(_)) && field.scalars().any(|x| x == value))
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc() -> Metadata {
+        metadata! {
+            "lang" => "en",
+            "year" => 2021,
+            "draft" => false,
+            "tags" => vec!["rust", "db"],
+        }
+    }
+
+    #[test]
+    fn test_filter_eq_ne_in() {
+        let m = doc();
+        assert!(Filter::eq("lang", "en").matches(Some(&m)));
+        assert!(!Filter::eq("lang", "de").matches(Some(&m)));
+        assert!(Filter::eq("year", 2021).matches(Some(&m)));
+        assert!(Filter::eq("tags", "db").matches(Some(&m)));
+        assert!(Filter::ne("lang", "de").matches(Some(&m)));
+        assert!(Filter::ne("missing", 1).matches(Some(&m)));
+        assert!(Filter::ne("lang", "de").matches(None));
+        assert!(Filter::one_of("lang", ["fr", "en"]).matches(Some(&m)));
+        assert!(!Filter::one_of("tags", ["go", "c"]).matches(Some(&m)));
+        assert!(!Filter::eq("lang", "en").matches(None));
+    }
+
+    #[test]
+    fn test_filter_range_and_logic() {
+        let m = doc();
+        assert!(Filter::gte("year", 2020.0).matches(Some(&m)));
+        assert!(!Filter::gte("year", 2022.0).matches(Some(&m)));
+        let exclusive = Range {
+            gt: Some(2021.0),
+            ..Range::default()
+        };
+        assert!(!Filter::range("year", exclusive).matches(Some(&m)));
+        assert!(!Filter::gte("lang", 0.0).matches(Some(&m)));
+
+        let f = Filter::eq("lang", "en").and(Filter::gte("year", 2020.0));
+        assert!(f.matches(Some(&m)));
+        assert!(!(!f.clone()).matches(Some(&m)));
+        assert!(Filter::eq("lang", "de")
+            .or(Filter::eq("draft", false))
+            .matches(Some(&m)));
+        assert!(Filter::And(vec![]).matches(None));
+        assert!(!Filter::Or(vec![]).matches(Some(&m)));
+    }
+
+    #[test]
+    fn test_metadata_json_roundtrip() {
+        let json = serde_json::json!({ "lang": "en", "year": 2021.0, "draft": false, "tags": ["rust", "db"] });
+        let m = metadata_from_json(&json).unwrap();
+        assert_eq!(m, doc());
+        assert_eq!(metadata_to_json(&m), json);
+        assert!(metadata_from_json(&serde_json::json!({ "x": null })).is_none());
+        assert!(metadata_from_json(&serde_json::json!([1, 2])).is_none());
+    }
+}
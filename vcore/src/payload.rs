@@ -0,0 +1,450 @@
+// VecBase — payload.rs
+// Secondary indexes over metadata fields, used to plan filtered searches.
+// Author: d65v <https://github.com/d65v>
+//
+// Two index kinds, both keyed by the dense row of each record:
+//   - Keyword: inverted index, string value → rows
+//   - Numeric: sorted (value, row) pairs for range lookups
+//
+// `PayloadIndexes::candidates` turns the indexable parts of a `Filter` into a
+// superset of the matching rows, exact for fully indexed filters (see
+// `is_exact`). Otherwise the caller checks each candidate against the
+// full filter. Each index also keeps row → indexed values, so updates and
+// deletes never need the old record back.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
+
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::{Filter, Metadata, Range, Value};
+
+/// What kind of secondary index to keep for a metadata field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PayloadKind {
+    /// Inverted index over string values (and strings inside lists)
+    Keyword,
+    /// Sorted index over numeric values (and numbers inside lists)
+    Numeric,
+}
+
+impl PayloadKind {
+    /// Parse `"keyword"` or `"numeric"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keyword" => Some(PayloadKind::Keyword),
+            "numeric" => Some(PayloadKind::Numeric),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a field list such as `"lang:keyword,year:numeric"`, as used by
+/// `VECBASE_PAYLOAD_INDEXES`. Malformed entries are skipped with a warning.
+pub fn parse_fields(spec: &str) -> BTreeMap<String, PayloadKind> {
+    let mut fields = BTreeMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once(':') {
+            Some((name, kind)) if !name.trim().is_empty() => match PayloadKind::parse(kind) {
+                Some(kind) => {
+                    fields.insert(name.trim().to_string(), kind);
+                }
+                None => log::warn!("payload index {:?}: unknown kind {:?}", name, kind),
+            },
+            _ => log::warn!("payload index {:?}: expected field:kind", entry),
+        }
+    }
+    fields
+}
+
+enum FieldIndex {
+    Keyword {
+        postings: HashMap<String, HashSet<u32>>,
+        by_row: HashMap<u32, Vec<String>>,
+    },
+    Numeric {
+        sorted: BTreeSet<(OrderedFloat<f64>, u32)>,
+        by_row: HashMap<u32, Vec<f64>>,
+    },
+}
+
+impl FieldIndex {
+    fn insert(&mut self, row: u32, value: &Value) {
+        match self {
+            FieldIndex::Keyword { postings, by_row } => {
+                let keys: Vec<String> = value
+                    .scalars()
+                    .filter_map(|v| match v {
+                        Value::String(s) => Some(s.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                for key in &keys {
+                    postings.entry(key.clone()).or_default().insert(row);
+                }
+                if !keys.is_empty() {
+                    by_row.insert(row, keys);
+                }
+            }
+            FieldIndex::Numeric { sorted, by_row } => {
+                let nums: Vec<f64> = value.scalars().filter_map(Value::as_f64).collect();
+                for &x in &nums {
+                    sorted.insert((OrderedFloat(x), row));
+                }
+                if !nums.is_empty() {
+                    by_row.insert(row, nums);
+                }
+            }
+        }
+    }
+}
+
+/// The set of payload indexes for one collection.
+#[derive(Default)]
+pub struct PayloadIndexes {
+    fields: BTreeMap<String, FieldIndex>,
+}
+
+impl PayloadIndexes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start indexing `field`. Replaces any existing index on it; the new
+    /// index is empty until rows are inserted.
+    pub fn create(&mut self, field: &str, kind: PayloadKind) {
+        let index = match kind {
+            PayloadKind::Keyword => FieldIndex::Keyword {
+                postings: HashMap::new(),
+                by_row: HashMap::new(),
+            },
+            PayloadKind::Numeric => FieldIndex::Numeric {
+                sorted: BTreeSet::new(),
+                by_row: HashMap::new(),
+            },
+        };
+        self.fields.insert(field.to_string(), index);
+    }
+
+    /// Stop indexing `field`. Returns whether it was indexed.
+    pub fn drop_field(&mut self, field: &str) -> bool {
+        self.fields.remove(field).is_some()
+    }
+
+    /// Indexed fields and their kinds, sorted by field name.
+    pub fn fields(&self) -> Vec<(String, PayloadKind)> {
+        self.fields
+            .iter()
+            .map(|(name, index)| {
+                let kind = match index {
+                    FieldIndex::Keyword { .. } => PayloadKind::Keyword,
+                    FieldIndex::Numeric { .. } => PayloadKind::Numeric,
+                };
+                (name.clone(), kind)
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Index `meta` under `row`. The row must not be indexed already.
+    pub fn insert(&mut self, row: u32, meta: Option<&Metadata>) {
+        let meta = match meta {
+            Some(m) => m,
+            None => return,
+        };
+        for (name, index) in self.fields.iter_mut() {
+            if let Some(value) = meta.get(name) {
+                index.insert(row, value);
+            }
+        }
+    }
+
+    /// Like [`insert`](Self::insert), but only into the index on `field`.
+    pub fn insert_field(&mut self, field: &str, row: u32, meta: Option<&Metadata>) {
+        if let (Some(index), Some(value)) =
+            (self.fields.get_mut(field), meta.and_then(|m| m.get(field)))
+        {
+            index.insert(row, value);
+        }
+    }
+
+    /// Drop every index entry for `row`.
+    pub fn remove(&mut self, row: u32) {
+        for index in self.fields.values_mut() {
+            match index {
+                FieldIndex::Keyword { postings, by_row } => {
+                    for key in by_row.remove(&row).unwrap_or_default() {
+                        if let Some(rows) = postings.get_mut(&key) {
+                            rows.remove(&row);
+                            if rows.is_empty() {
+                                postings.remove(&key);
+                            }
+                        }
+                    }
+                }
+                FieldIndex::Numeric { sorted, by_row } => {
+                    for x in by_row.remove(&row).unwrap_or_default() {
+                        sorted.remove(&(OrderedFloat(x), row));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rows that may match `filter`, or `None` if no index narrows it.
+    ///
+    /// The result is a superset of the matches: `And` intersects whichever
+    /// children are indexed and leaves the rest to the caller, while `Ne`,
+    /// `Not`, unindexed fields and `Or` with any unindexed child give `None`.
+    pub fn candidates(&self, filter: &Filter) -> Option<HashSet<u32>> {
+        match filter {
+            Filter::Eq(field, value) => self.lookup(field, std::slice::from_ref(value)),
+            Filter::In(field, values) => self.lookup(field, values),
+            Filter::Range(field, range) => match self.fields.get(field)? {
+                FieldIndex::Numeric { sorted, .. } => Some(range_rows(sorted, range)),
+                FieldIndex::Keyword { .. } => None,
+            },
+            Filter::And(all) => {
+                let mut sets: Vec<HashSet<u32>> =
+                    all.iter().filter_map(|f| self.candidates(f)).collect();
+                sets.sort_by_key(HashSet::len);
+                let mut iter = sets.into_iter();
+                let first = iter.next()?;
+                Some(iter.fold(first, |acc, s| acc.intersection(&s).copied().collect()))
+            }
+            Filter::Or(any) => {
+                let mut union = HashSet::new();
+                for f in any {
+                    union.extend(self.candidates(f)?);
+                }
+                Some(union)
+            }
+            Filter::Ne(..) | Filter::Not(_) => None,
+        }
+    }
+
+    /// Whether [`candidates`](Self::candidates) gives exactly the rows
+    /// matching `filter`, so they need no check against the records.
+    pub fn is_exact(&self, filter: &Filter) -> bool {
+        match filter {
+            Filter::Eq(field, value) => self.indexes_value(field, value),
+            Filter::In(field, values) => values.iter().all(|v| self.indexes_value(field, v)),
+            Filter::Range(field, _) => {
+                matches!(self.fields.get(field), Some(FieldIndex::Numeric { .. }))
+            }
+            // An empty `And` has no candidates at all.
+            Filter::And(all) => !all.is_empty() && all.iter().all(|f| self.is_exact(f)),
+            Filter::Or(any) => any.iter().all(|f| self.is_exact(f)),
+            Filter::Ne(..) | Filter::Not(_) => false,
+        }
+    }
+
+    /// Whether the index on `field` can look up `value` exactly.
+    fn indexes_value(&self, field: &str, value: &Value) -> bool {
+        matches!(
+            (self.fields.get(field), value),
+            (Some(FieldIndex::Keyword { .. }), Value::String(_))
+                | (Some(FieldIndex::Numeric { .. }), Value::Number(_))
+        )
+    }
+
+    /// Rows whose `field` equals any of `values`.
+    fn lookup(&self, field: &str, values: &[Value]) -> Option<HashSet<u32>> {
+        let mut rows = HashSet::new();
+        match self.fields.get(field)? {
+            FieldIndex::Keyword { postings, .. } => {
+                for value in values {
+                    match value {
+                        Value::String(s) => rows.extend(postings.get(s).into_iter().flatten()),
+                        // Non-string values are never in a keyword index.
+                        _ => return None,
+                    }
+                }
+            }
+            FieldIndex::Numeric { sorted, .. } => {
+                for value in values {
+                    let x = value.as_f64()?;
+                    let exact = Range {
+                        gte: Some(x),
+                        lte: Some(x),
+                        ..Range::default()
+                    };
+                    rows.extend(range_rows(sorted, &exact));
+                }
+            }
+        }
+        Some(rows)
+    }
+}
+
+fn range_rows(sorted: &BTreeSet<(OrderedFloat<f64>, u32)>, range: &Range) -> HashSet<u32> {
+    // Tightest of each pair of bounds; the exclusive one wins on ties.
+    let lower = match (range.gt, range.gte) {
+        (Some(gt), Some(gte)) if gt >= gte => Bound::Excluded((OrderedFloat(gt), u32::MAX)),
+        (_, Some(gte)) => Bound::Included((OrderedFloat(gte), 0)),
+        (Some(gt), None) => Bound::Excluded((OrderedFloat(gt), u32::MAX)),
+        (None, None) => Bound::Unbounded,
+    };
+    let upper = match (range.lt, range.lte) {
+        (Some(lt), Some(lte)) if lt <= lte => Bound::Excluded((OrderedFloat(lt), 0)),
+        (_, Some(lte)) => Bound::Included((OrderedFloat(lte), u32::MAX)),
+        (Some(lt), None) => Bound::Excluded((OrderedFloat(lt), 0)),
+        (None, None) => Bound::Unbounded,
+    };
+    if is_empty_range(&lower, &upper) {
+        return HashSet::new();
+    }
+    sorted.range((lower, upper)).map(|&(_, row)| row).collect()
+}
+
+/// `BTreeSet::range` panics when start > end, so catch that first.
+fn is_empty_range(
+    lower: &Bound<(OrderedFloat<f64>, u32)>,
+    upper: &Bound<(OrderedFloat<f64>, u32)>,
+) -> bool {
+    match (lower, upper) {
+        (Bound::Included(a), Bound::Included(b)) => a > b,
+        (Bound::Included(a), Bound::Excluded(b))
+        | (Bound::Excluded(a), Bound::Included(b))
+        | (Bound::Excluded(a), Bound::Excluded(b)) => a >= b,
+        _ => false,
+    }
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata;
+
+    fn indexes() -> PayloadIndexes {
+        let mut p = PayloadIndexes::new();
+        p.create("lang", PayloadKind::Keyword);
+        p.create("year", PayloadKind::Numeric);
+        let docs = [
+            metadata! { "lang" => "en", "year" => 2019 },
+            metadata! { "lang" => "de", "year" => 2020 },
+            metadata! { "lang" => vec!["en", "de"], "year" => 2021 },
+            metadata! { "lang" => "fr", "year" => vec![2020, 2022] },
+            metadata! { "other" => 1 },
+        ];
+        for (row, meta) in docs.iter().enumerate() {
+            p.insert(row as u32, Some(meta));
+        }
+        p
+    }
+
+    fn sorted(set: Option<HashSet<u32>>) -> Option<Vec<u32>> {
+        set.map(|s| {
+            let mut v: Vec<u32> = s.into_iter().collect();
+            v.sort_unstable();
+            v
+        })
+    }
+
+    #[test]
+    fn test_keyword_and_numeric_lookup() {
+        let p = indexes();
+        assert_eq!(
+            sorted(p.candidates(&Filter::eq("lang", "en"))),
+            Some(vec![0, 2])
+        );
+        assert_eq!(
+            sorted(p.candidates(&Filter::one_of("lang", ["de", "fr"]))),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            sorted(p.candidates(&Filter::eq("year", 2020))),
+            Some(vec![1, 3])
+        );
+        assert_eq!(
+            sorted(p.candidates(&Filter::gte("year", 2021.0))),
+            Some(vec![2, 3])
+        );
+        let open = Range {
+            gt: Some(2019.0),
+            lt: Some(2021.0),
+            ..Range::default()
+        };
+        assert_eq!(
+            sorted(p.candidates(&Filter::range("year", open))),
+            Some(vec![1, 3])
+        );
+        let empty = Range {
+            gt: Some(2022.0),
+            lt: Some(2020.0),
+            ..Range::default()
+        };
+        assert_eq!(
+            sorted(p.candidates(&Filter::range("year", empty))),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_candidates_combine() {
+        let p = indexes();
+        let both = Filter::eq("lang", "en").and(Filter::gte("year", 2020.0));
+        assert_eq!(sorted(p.candidates(&both)), Some(vec![2]));
+        // Unindexed children of And are left to the caller.
+        let partial = Filter::eq("lang", "de").and(Filter::eq("other", 1));
+        assert_eq!(sorted(p.candidates(&partial)), Some(vec![1, 2]));
+        let either = Filter::eq("lang", "fr").or(Filter::lte("year", 2019.0));
+        assert_eq!(sorted(p.candidates(&either)), Some(vec![0, 3]));
+        assert!(p
+            .candidates(&Filter::eq("lang", "fr").or(Filter::eq("other", 1)))
+            .is_none());
+        assert!(p.candidates(&Filter::ne("lang", "en")).is_none());
+        assert!(p.candidates(&Filter::eq("lang", 3)).is_none());
+    }
+
+    #[test]
+    fn test_exact_filters() {
+        let p = indexes();
+        assert!(p.is_exact(&Filter::eq("lang", "en")));
+        assert!(p.is_exact(&Filter::And(vec![
+            Filter::one_of("lang", ["en", "fr"]),
+            Filter::gte("year", 2020.0),
+        ])));
+        assert!(p.is_exact(&Filter::Or(vec![])));
+        assert!(!p.is_exact(&Filter::eq("lang", 1)));
+        assert!(!p.is_exact(&Filter::eq("other", 1)));
+        assert!(!p.is_exact(&Filter::gte("lang", 1.0)));
+        assert!(!p.is_exact(&Filter::And(vec![Filter::eq("lang", "en"), Filter::ne("year", 2019)])));
+        assert!(!p.is_exact(&Filter::And(vec![])));
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let fields = parse_fields("lang:keyword, year:Numeric,bad,x:float,");
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields["lang"], PayloadKind::Keyword);
+        assert_eq!(fields["year"], PayloadKind::Numeric);
+    }
+
+    #[test]
+    fn test_remove_row() {
+        let mut p = indexes();
+        p.remove(2);
+        assert_eq!(
+            sorted(p.candidates(&Filter::eq("lang", "en"))),
+            Some(vec![0])
+        );
+        assert_eq!(
+            sorted(p.candidates(&Filter::gte("year", 2021.0))),
+            Some(vec![3])
+        );
+        p.insert(2, Some(&metadata! { "lang" => "en", "year" => 1990 }));
+        assert_eq!(
+            sorted(p.candidates(&Filter::lte("year", 2000.0))),
+            Some(vec![2])
+        );
+        assert!(p.drop_field("year"));
+        assert!(p.candidates(&Filter::lte("year", 2000.0)).is_none());
+    }
+}
@@ -16,24 +16,34 @@
 //
 // Author: d65v <https://github.com/d65v>
 
-// NOTE: In a real build this would `use vcore::{Plugin, SearchResult}`.
+// NOTE: In a real build these would come from `vcore` itself.
 // Here we inline the trait to keep this file self-contained and readable
 // without requiring a workspace build. When you compile for real, replace
-// the inline definitions with `use vcore::{Plugin, SearchResult};`.
+// the inline definitions with `use vcore::{metadata::{Metadata, Value}, Plugin, SearchResult};`.
 
-// ── Inline types (mirrors vcore/src/lib.rs) ───────────────────────────────────
+// ── Inline types (mirrors vcore/src/lib.rs and metadata.rs) ───────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    List(Vec<Value>),
+}
+
+pub type Metadata = std::collections::BTreeMap<String, Value>;
 
 pub struct SearchResult {
     pub id: String,
     pub score: f32,
-    pub metadata: Option<String>,
+    pub metadata: Option<Metadata>,
 }
 
 pub trait Plugin: Send + Sync {
     fn name(&self) -> &'static str;
     fn version(&self) -> &'static str;
     fn on_init(&self);
-    fn on_insert(&self, id: &str, vector: &mut Vec<f32>, metadata: &mut Option<String>);
+    fn on_insert(&self, id: &str, vector: &mut Vec<f32>, metadata: &mut Option<Metadata>);
     fn on_search_results(&self, results: &mut Vec<SearchResult>);
 }
 
@@ -75,7 +85,7 @@ impl Plugin for ExamplePlugin {
 
     /// Clamp each component to [-1.0, 1.0] before storage.
     /// Useful as a safety guard against out-of-range embeddings.
-    fn on_insert(&self, id: &str, vector: &mut Vec<f32>, _metadata: &mut Option<String>) {
+    fn on_insert(&self, id: &str, vector: &mut Vec<f32>, _metadata: &mut Option<Metadata>) {
         let mut clamped = 0usize;
         for x in vector.iter_mut() {
             let before = *x;
@@ -0,0 +1,993 @@
+// VecBase — pq.rs
+// Product Quantization codec and a compressed PQ index.
+// Author: d65v <https://github.com/d65v>
+//
+// NOTE: Product Quantization (Jégou, Douze & Schmid, 2011). A vector is cut
+// into `m` equal sub-vectors; each sub-space gets its own codebook of 2^bits
+// centroids, trained with k-means. A vector is stored as `m` centroid numbers,
+// one byte each, so a 1536-d f32 vector (6 KiB) with m=96 takes 96 bytes.
+//
+// Search uses asymmetric distance computation (ADC): the query stays exact,
+// and one m × 2^bits table of query-to-centroid scores is built per query.
+// Scoring a code is then m table lookups and adds, with no decoding. The
+// approximate top candidates can optionally be re-ranked against full
+// vectors kept on the side, trading memory back for precision.
+//
+// As `index_type = "pq"` the index keeps full vectors in the collection's
+// vector store, so with `vector_storage = "mmap"` only codes and ids stay in
+// memory. Like IVF, it is searched exactly until it holds enough vectors to
+// train on and retrains as it grows; the insert that crosses a threshold
+// trains in `plan_insert`.
+
+use std::any::Any;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::{dot, euclidean_distance_sq, rank_score, Metric};
+use crate::index::{self, to_scores, AnnIndex, IndexPlan, IndexType, TopK};
+use crate::processing::IdTable;
+use crate::vectors::{FreeRows, VectorStore};
+use crate::{Result, VecBaseConfig, VecBaseError};
+
+// ── Codec ─────────────────────────────────────────────────────────────────────
+
+/// Training and search parameters for [`PqCodec`] and [`PqIndex`], read
+/// from `index_params` for `index_type = "pq"` (e.g.
+/// `{"sub_vectors": 96, "rerank": 10}`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PqParams {
+    /// Number of sub-vectors (bytes per code); must divide the dimension
+    pub sub_vectors: usize,
+    /// Bits per sub-vector code, 1..=8 (2^bits centroids per codebook)
+    pub bits: u8,
+    /// Lloyd iterations per codebook
+    pub train_iters: usize,
+    /// Re-rank the best `rerank · top_k` ADC candidates against full
+    /// vectors. 0 disables re-ranking; indexes from [`PqIndex::train`] then
+    /// keep no full vectors.
+    pub rerank: usize,
+}
+
+impl Default for PqParams {
+    fn default() -> Self {
+        Self {
+            sub_vectors: 8,
+            bits: 8,
+            train_iters: 20,
+            rerank: 0,
+        }
+    }
+}
+
+/// Trained PQ codebooks: encodes vectors to `sub_vectors` bytes and builds
+/// ADC tables for queries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PqCodec {
+    dim: usize,
+    sub_vectors: usize,
+    /// Dimensions per sub-vector
+    sub_dim: usize,
+    /// Centroids per codebook (2^bits)
+    centroids: usize,
+    /// `sub_vectors × centroids × sub_dim` floats, codebook-major
+    codebooks: Vec<f32>,
+}
+
+impl PqCodec {
+    /// Train codebooks on `samples` with k-means, one sub-space per core.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::ConfigError` if `sub_vectors` is 0 or does not
+    /// divide `dim`, `bits` is outside 1..=8, or there are no samples, and
+    /// `VecBaseError::DimensionMismatch` if a sample is not `dim` long.
+    pub fn train(dim: usize, params: &PqParams, samples: &[Vec<f32>]) -> Result<Self> {
+        if params.sub_vectors == 0 || !dim.is_multiple_of(params.sub_vectors) {
+            return Err(VecBaseError::ConfigError(format!(
+                "pq: {} sub-vectors do not divide dim {}",
+                params.sub_vectors, dim
+            )));
+        }
+        if !(1..=8).contains(&params.bits) {
+            return Err(VecBaseError::ConfigError(format!(
+                "pq: bits must be 1..=8, got {}",
+                params.bits
+            )));
+        }
+        if samples.is_empty() {
+            return Err(VecBaseError::ConfigError("pq: no training samples".into()));
+        }
+        if let Some(bad) = samples.iter().find(|s| s.len() != dim) {
+            return Err(VecBaseError::DimensionMismatch {
+                expected: dim,
+                got: bad.len(),
+            });
+        }
+
+        let sub_dim = dim / params.sub_vectors;
+        let centroids = 1usize << params.bits;
+        let codebooks: Vec<f32> = (0..params.sub_vectors)
+            .into_par_iter()
+            .flat_map_iter(|j| {
+                let points: Vec<&[f32]> = samples.iter().map(|s| &s[j * sub_dim..(j + 1) * sub_dim]).collect();
+                kmeans(&points, sub_dim, centroids, params.train_iters, 0x5eed_0000 + j as u64)
+            })
+            .collect();
+
+        Ok(Self {
+            dim,
+            sub_vectors: params.sub_vectors,
+            sub_dim,
+            centroids,
+            codebooks,
+        })
+    }
+
+    /// Code for `v`: the nearest centroid in each sub-space.
+    pub fn encode(&self, v: &[f32]) -> Vec<u8> {
+        debug_assert_eq!(v.len(), self.dim);
+        (0..self.sub_vectors)
+            .map(|j| nearest(self.codebook(j), self.sub_dim, self.sub_vector(v, j)) as u8)
+            .collect()
+    }
+
+    /// Approximate vector for `code` (the concatenated centroids).
+    pub fn decode(&self, code: &[u8]) -> Vec<f32> {
+        code.iter()
+            .enumerate()
+            .flat_map(|(j, &c)| self.centroid(j, c).iter().copied())
+            .collect()
+    }
+
+    /// ADC table for `query`: one rank score (see
+    /// [`rank_score`](crate::embedding::rank_score)) per sub-space and
+    /// centroid. Euclidean entries are negated squared distances, so they
+    /// add up to the negated squared distance to the decoded vector; dot and
+    /// cosine entries add up to the dot product.
+    pub fn distance_table(&self, query: &[f32], metric: &Metric) -> DistanceTable {
+        let mut table = Vec::with_capacity(self.sub_vectors * self.centroids);
+        for j in 0..self.sub_vectors {
+            let q = self.sub_vector(query, j);
+            for c in self.codebook(j).chunks_exact(self.sub_dim) {
+                table.push(match metric {
+                    Metric::Euclidean => -euclidean_distance_sq(q, c),
+                    Metric::Cosine | Metric::DotProduct => dot(q, c),
+                });
+            }
+        }
+        DistanceTable {
+            centroids: self.centroids,
+            table,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Bytes per encoded vector.
+    pub fn code_len(&self) -> usize {
+        self.sub_vectors
+    }
+
+    fn codebook(&self, j: usize) -> &[f32] {
+        let len = self.centroids * self.sub_dim;
+        &self.codebooks[j * len..(j + 1) * len]
+    }
+
+    fn centroid(&self, j: usize, c: u8) -> &[f32] {
+        let start = c as usize * self.sub_dim;
+        &self.codebook(j)[start..start + self.sub_dim]
+    }
+
+    fn sub_vector<'a>(&self, v: &'a [f32], j: usize) -> &'a [f32] {
+        &v[j * self.sub_dim..(j + 1) * self.sub_dim]
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.codebooks.capacity() * 4
+    }
+}
+
+/// Per-query lookup table from [`PqCodec::distance_table`].
+pub struct DistanceTable {
+    centroids: usize,
+    table: Vec<f32>,
+}
+
+impl DistanceTable {
+    /// Approximate rank score of the vector encoded as `code`.
+    #[inline]
+    pub fn score(&self, code: &[u8]) -> f32 {
+        // Four independent sums, so the adds are not one long dependency chain.
+        let mut acc = [0.0f32; 4];
+        let mut rows = self.table.chunks_exact(self.centroids);
+        for (i, (&c, row)) in code.iter().zip(rows.by_ref()).enumerate() {
+            acc[i % 4] += row[c as usize];
+        }
+        (acc[0] + acc[1]) + (acc[2] + acc[3])
+    }
+}
+
+/// Lloyd's k-means over `points` (each `dim` long). Starts from `k` distinct
+/// random points; a centroid left without points is moved to a random point.
+/// With fewer than `k` points, the spare centroids repeat existing ones.
+/// Points are assigned to centroids in parallel. Also trains the coarse
+/// centroids of [`IvfIndex`](crate::ivf::IvfIndex).
+pub(crate) fn kmeans(points: &[&[f32]], dim: usize, k: usize, iters: usize, seed: u64) -> Vec<f32> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    for i in (1..order.len()).rev() {
+        order.swap(i, rng.gen_range(0..=i));
+    }
+    let mut centroids: Vec<f32> = (0..k).flat_map(|c| points[order[c % points.len()]].iter().copied()).collect();
+    if points.len() <= k {
+        return centroids;
+    }
+
+    let mut assign = vec![0usize; points.len()];
+    for _ in 0..iters {
+        let next: Vec<usize> = points.par_iter().map(|p| nearest(&centroids, dim, p)).collect();
+        let changed = next != assign;
+        assign = next;
+
+        let mut sums = vec![0.0f32; k * dim];
+        let mut counts = vec![0usize; k];
+        for (p, &a) in points.iter().zip(&assign) {
+            counts[a] += 1;
+            for (s, x) in sums[a * dim..(a + 1) * dim].iter_mut().zip(p.iter()) {
+                *s += x;
+            }
+        }
+        for c in 0..k {
+            let target = &mut centroids[c * dim..(c + 1) * dim];
+            if counts[c] == 0 {
+                target.copy_from_slice(points[rng.gen_range(0..points.len())]);
+                continue;
+            }
+            for (t, s) in target.iter_mut().zip(&sums[c * dim..(c + 1) * dim]) {
+                *t = s / counts[c] as f32;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    centroids
+}
+
+/// Index of the centroid in `centroids` (`dim`-long rows) closest to `v`.
+pub(crate) fn nearest(centroids: &[f32], dim: usize, v: &[f32]) -> usize {
+    let mut best = (0, f32::MAX);
+    for (i, c) in centroids.chunks_exact(dim).enumerate() {
+        let d = euclidean_distance_sq(v, c);
+        if d < best.1 {
+            best = (i, d);
+        }
+    }
+    best.0
+}
+
+// ── PQ Index ──────────────────────────────────────────────────────────────────
+
+/// An untrained index trains its codebooks once it holds this many vectors
+/// per centroid (`2^bits` centroids per codebook).
+pub const TRAIN_MIN_PER_CENTROID: usize = 39;
+
+/// Training samples at most this many vectors per centroid.
+pub const TRAIN_MAX_PER_CENTROID: usize = 256;
+
+/// The index retrains whenever it has grown this many times past the size
+/// it was last trained at, until the training sample is capped.
+pub const RETRAIN_GROWTH: usize = 4;
+
+/// A compressed flat index: every vector is kept as a PQ code and searched
+/// with ADC tables. With `rerank > 0` the best `rerank · top_k` candidates
+/// are re-scored exactly against full vectors.
+///
+/// Built by [`train`](Self::train) or [`with_codec`](Self::with_codec), it
+/// keeps full vectors only when re-ranking. Built over a vector store by
+/// [`with_store`](Self::with_store), as `VecBase` does for
+/// `index_type = "pq"`, it keeps them in that store (on disk if mapped),
+/// searches exactly until it holds `TRAIN_MIN_PER_CENTROID · 2^bits`
+/// vectors, then trains its codebooks on them, retraining as it grows like
+/// [`IvfIndex`](crate::ivf::IvfIndex).
+///
+/// Like [`HnswIndex`](crate::processing::HnswIndex), cosine assumes
+/// pre-normalized vectors, and returned scores follow
+/// [`score`](crate::embedding::score). Without re-ranking they are the
+/// scores of the decoded vectors.
+#[derive(Serialize, Deserialize)]
+pub struct PqIndex {
+    dim: usize,
+    params: PqParams,
+    /// Trained codebooks; `None` until trained
+    codec: Option<PqCodec>,
+    metric: Metric,
+    max_elements: usize,
+    /// `code_len` bytes per row, once trained
+    codes: Vec<u8>,
+    /// Full vectors per row, when kept
+    vectors: Option<VectorStore>,
+    ids: IdTable,
+    free: FreeRows,
+    /// Number of vectors the codebooks were last trained on; `None` for
+    /// codebooks given up front, which are never retrained
+    trained_on: Option<usize>,
+    /// Bumped by every mutation, so stale training plans are detected
+    #[serde(skip)]
+    version: u64,
+}
+
+/// Codebooks and codes for a pending training run, from
+/// [`PqIndex::plan_training`].
+pub struct TrainPlan {
+    /// Index version the plan was made against
+    version: u64,
+    codec: PqCodec,
+    /// Codes of every stored row, free rows zeroed
+    codes: Vec<u8>,
+}
+
+impl PqIndex {
+    /// An empty index whose codebooks are trained on `samples`.
+    ///
+    /// # Errors
+    /// As for [`PqCodec::train`].
+    pub fn train(
+        dim: usize,
+        max_elements: usize,
+        metric: Metric,
+        params: PqParams,
+        samples: &[Vec<f32>],
+    ) -> Result<Self> {
+        let codec = PqCodec::train(dim, &params, samples)?;
+        Ok(Self::with_codec(codec, max_elements, metric, params.rerank))
+    }
+
+    /// An empty index over already trained codebooks.
+    pub fn with_codec(codec: PqCodec, max_elements: usize, metric: Metric, rerank: usize) -> Self {
+        let params = PqParams {
+            sub_vectors: codec.sub_vectors,
+            bits: codec.centroids.trailing_zeros() as u8,
+            rerank,
+            ..PqParams::default()
+        };
+        Self {
+            dim: codec.dim(),
+            params,
+            vectors: (rerank > 0).then(|| VectorStore::memory(codec.dim())),
+            codec: Some(codec),
+            metric,
+            max_elements,
+            codes: Vec::new(),
+            ids: IdTable::default(),
+            free: FreeRows::default(),
+            trained_on: None,
+            version: 0,
+        }
+    }
+
+    /// An empty, untrained index keeping full vectors in `vectors`, e.g. a
+    /// memory-mapped store from [`VectorStore::mmap`]. Any rows already in
+    /// the store are treated as free and will be overwritten. `sub_vectors`
+    /// is cut down to a divisor of the dimension and `bits` clamped to
+    /// 1..=8.
+    pub fn with_store(max_elements: usize, metric: Metric, mut params: PqParams, vectors: VectorStore) -> Self {
+        let dim = vectors.dim();
+        params.sub_vectors = gcd(dim, params.sub_vectors.max(1));
+        params.bits = params.bits.clamp(1, 8);
+        Self {
+            dim,
+            params,
+            codec: None,
+            metric,
+            max_elements,
+            codes: Vec::new(),
+            ids: IdTable::with_free_rows(vectors.len()),
+            free: FreeRows::all(&vectors),
+            vectors: Some(vectors),
+            trained_on: Some(0),
+            version: 0,
+        }
+    }
+
+    /// Insert (or replace) a vector and return the row it was stored at.
+    /// May train the codebooks (see [`with_store`](Self::with_store)).
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::DimensionMismatch` for a vector of the wrong
+    /// length, `VecBaseError::CapacityExceeded` if a new id would exceed
+    /// `max_elements`, or `VecBaseError::StorageError` if the vector store
+    /// cannot grow.
+    pub fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        if vector.len() != self.dim {
+            return Err(VecBaseError::DimensionMismatch {
+                expected: self.dim,
+                got: vector.len(),
+            });
+        }
+        // The new vector goes through a free row like any other, so a mapped
+        // store keeps the old one for the last checkpoint (see `FreeRows`).
+        self.remove(&id);
+        if self.ids.len() >= self.max_elements {
+            return Err(VecBaseError::CapacityExceeded {
+                max_elements: self.max_elements,
+            });
+        }
+
+        let code = self.codec.as_ref().map(|c| c.encode(&vector));
+        let len = self.params.sub_vectors;
+        let row = match self.free.pop() {
+            Some(r) => {
+                if let Some(vectors) = self.vectors.as_mut() {
+                    vectors.set(r, &vector);
+                }
+                if let Some(code) = &code {
+                    self.codes[r as usize * len..(r as usize + 1) * len].copy_from_slice(code);
+                }
+                r
+            }
+            None => {
+                let r = match self.vectors.as_mut() {
+                    Some(vectors) => vectors.push(&vector)?,
+                    None => (self.codes.len() / len) as u32,
+                };
+                if let Some(code) = &code {
+                    self.codes.extend_from_slice(code);
+                }
+                r
+            }
+        };
+        self.ids.insert(row, &id);
+        self.version += 1;
+        if self.needs_training(self.ids.len()) {
+            self.train_codebooks();
+        }
+        Ok(row)
+    }
+
+    /// Like [`insert`](Self::insert), but first swaps in the codebooks of
+    /// `plan` (see [`plan_insert`](Self::plan_insert)) if the index has not
+    /// changed since it was made.
+    ///
+    /// # Errors
+    /// As for [`insert`](Self::insert).
+    pub fn commit_insert(&mut self, id: String, vector: Vec<f32>, plan: Option<TrainPlan>) -> Result<u32> {
+        if let Some(plan) = plan {
+            self.commit_training(plan);
+        }
+        self.insert(id, vector)
+    }
+
+    /// First half of an insert of `id`: if it would make the index
+    /// (re)train, do the training now, under a shared borrow.
+    pub fn plan_insert(&self, id: &str) -> Option<TrainPlan> {
+        let len = self.ids.len() + usize::from(self.ids.row(id).is_none());
+        if self.needs_training(len) {
+            self.plan_training()
+        } else {
+            None
+        }
+    }
+
+    /// Remove `id`; its row is reused by a later insert.
+    pub fn remove(&mut self, id: &str) {
+        if let Some(row) = self.ids.row(id) {
+            self.ids.remove_row(row);
+            match self.vectors.as_ref() {
+                Some(vectors) => self.free.push(row, vectors),
+                None => self.free.push(row, &VectorStore::memory(self.dim)),
+            }
+            self.version += 1;
+        }
+    }
+
+    /// The expensive part of training: k-means codebooks over a sample of
+    /// the stored vectors, and the code of every row. `None` if the index
+    /// is empty or keeps no full vectors.
+    pub fn plan_training(&self) -> Option<TrainPlan> {
+        let vectors = self.vectors.as_ref()?;
+        let mut rows: Vec<u32> = self.ids.rows().collect();
+        if rows.is_empty() {
+            return None;
+        }
+        // Sorted first so the sample does not depend on hash order.
+        rows.sort_unstable();
+        let mut rng = StdRng::seed_from_u64(0x5eed_0009 + rows.len() as u64);
+        let take = rows.len().min(TRAIN_MAX_PER_CENTROID << self.params.bits);
+        for i in 0..take {
+            let j = rng.gen_range(i..rows.len());
+            rows.swap(i, j);
+        }
+        let sample: Vec<Vec<f32>> = rows[..take].iter().map(|&r| vectors.get(r).to_vec()).collect();
+        let codec = PqCodec::train(self.dim, &self.params, &sample)
+            .map_err(|e| log::warn!("pq training failed: {}", e))
+            .ok()?;
+        let codes = (0..vectors.len() as u32)
+            .into_par_iter()
+            .flat_map_iter(|r| match self.ids.id(r) {
+                Some(_) => codec.encode(vectors.get(r)),
+                None => vec![0; codec.code_len()],
+            })
+            .collect();
+        Some(TrainPlan {
+            version: self.version,
+            codec,
+            codes,
+        })
+    }
+
+    /// Swap in the codebooks and codes of `plan`. Does nothing if the index
+    /// changed since the plan was made.
+    pub fn commit_training(&mut self, plan: TrainPlan) {
+        if plan.version != self.version {
+            return;
+        }
+        self.codec = Some(plan.codec);
+        self.codes = plan.codes;
+        self.trained_on = Some(self.ids.len());
+        self.version += 1;
+    }
+
+    fn train_codebooks(&mut self) {
+        if let Some(plan) = self.plan_training() {
+            self.commit_training(plan);
+        }
+    }
+
+    /// Whether the codebooks should be (re)trained once the index holds
+    /// `len` vectors.
+    fn needs_training(&self, len: usize) -> bool {
+        match self.trained_on {
+            None => false,
+            Some(0) => len >= TRAIN_MIN_PER_CENTROID << self.params.bits,
+            Some(n) => n < TRAIN_MAX_PER_CENTROID << self.params.bits && len >= n * RETRAIN_GROWTH,
+        }
+    }
+
+    /// Top-k nearest neighbors of `query` as (id, score).
+    pub fn search(&self, query: &[f32], top_k: usize) -> Vec<(String, f32)> {
+        self.search_rows(query, top_k)
+            .into_iter()
+            .filter_map(|(r, s)| self.ids.id(r).map(|id| (id.to_string(), s)))
+            .collect()
+    }
+
+    /// Like [`search`](Self::search), but returns rows.
+    pub fn search_rows(&self, query: &[f32], top_k: usize) -> Vec<(u32, f32)> {
+        self.search_rows_filtered_with(query, top_k, self.params.rerank, |_| true)
+    }
+
+    /// Search restricted to rows for which `accept` returns true. Rejected
+    /// rows are skipped while scanning, so fewer than `top_k` results means
+    /// fewer than `top_k` rows match.
+    pub fn search_rows_filtered<F: Fn(u32) -> bool>(&self, query: &[f32], top_k: usize, accept: F) -> Vec<(u32, f32)> {
+        self.search_rows_filtered_with(query, top_k, self.params.rerank, accept)
+    }
+
+    fn search_rows_filtered_with<F: Fn(u32) -> bool>(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        rerank: usize,
+        accept: F,
+    ) -> Vec<(u32, f32)> {
+        if top_k == 0 || query.len() != self.dim {
+            return vec![];
+        }
+        let (codec, vectors) = match (self.codec.as_ref(), self.vectors.as_ref()) {
+            (Some(codec), vectors) => (codec, vectors.filter(|_| rerank > 0)),
+            (None, Some(vectors)) => {
+                let mut best = TopK::new(top_k);
+                for r in self.ids.rows().filter(|&r| accept(r)) {
+                    best.push(r, rank_score(&self.metric, query, vectors.get(r)));
+                }
+                return to_scores(&self.metric, best.into_sorted());
+            }
+            (None, None) => return vec![],
+        };
+        let table = codec.distance_table(query, &self.metric);
+        let vectors = match vectors {
+            Some(v) => v,
+            None => return to_scores(&self.metric, self.scan(codec, &table, top_k, &accept)),
+        };
+
+        let mut found: Vec<(u32, f32)> = self
+            .scan(codec, &table, top_k.saturating_mul(rerank), &accept)
+            .into_iter()
+            .map(|(r, _)| (r, rank_score(&self.metric, query, vectors.get(r))))
+            .collect();
+        found.sort_by(|a, b| b.1.total_cmp(&a.1));
+        found.truncate(top_k);
+        to_scores(&self.metric, found)
+    }
+
+    /// Best `k` live, accepted rows by ADC rank score, best first.
+    fn scan(&self, codec: &PqCodec, table: &DistanceTable, k: usize, accept: &impl Fn(u32) -> bool) -> Vec<(u32, f32)> {
+        let mut best = TopK::new(k);
+        for (row, code) in self.codes.chunks_exact(codec.code_len()).enumerate() {
+            let row = row as u32;
+            if self.ids.id(row).is_some() && accept(row) {
+                best.push(row, table.score(code));
+            }
+        }
+        best.into_sorted()
+    }
+
+    /// Top-k over just `rows`, e.g. a candidate set from a payload index,
+    /// exact when full vectors are kept. Free rows are skipped.
+    pub fn search_rows_among(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        rows: impl IntoIterator<Item = u32>,
+    ) -> Vec<(u32, f32)> {
+        let mut best = TopK::new(top_k);
+        let table = match (&self.vectors, &self.codec) {
+            (None, Some(codec)) => Some((codec, codec.distance_table(query, &self.metric))),
+            _ => None,
+        };
+        for r in rows.into_iter().filter(|&r| self.ids.id(r).is_some()) {
+            let s = match (&self.vectors, &table) {
+                (Some(vectors), _) => rank_score(&self.metric, query, vectors.get(r)),
+                (None, Some((codec, table))) => {
+                    let len = codec.code_len();
+                    table.score(&self.codes[r as usize * len..(r as usize + 1) * len])
+                }
+                (None, None) => continue,
+            };
+            best.push(r, s);
+        }
+        to_scores(&self.metric, best.into_sorted())
+    }
+
+    /// Number of indexed vectors.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.len() == 0
+    }
+
+    /// Whether `id` is indexed.
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Full vector stored at `row`, if full vectors are kept and the row
+    /// is live.
+    pub fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        self.ids.id(row).and(self.vectors.as_ref()).map(|v| v.get(row))
+    }
+
+    /// The trained codebooks, `None` until trained.
+    pub fn codec(&self) -> Option<&PqCodec> {
+        self.codec.as_ref()
+    }
+
+    /// Parameters this index was created with.
+    pub fn params(&self) -> PqParams {
+        self.params
+    }
+
+    /// Whether codebooks have been trained (before that, searches are exact).
+    pub fn is_trained(&self) -> bool {
+        self.codec.is_some()
+    }
+
+    /// Approximate heap bytes held by codes, codebooks, ids and (when
+    /// kept in memory) full vectors.
+    pub fn heap_bytes(&self) -> usize {
+        self.codes.capacity()
+            + self.codec.as_ref().map_or(0, PqCodec::heap_bytes)
+            + self.ids.heap_bytes()
+            + self.vectors.as_ref().map_or(0, VectorStore::heap_bytes)
+            + self.free.heap_bytes()
+    }
+}
+
+impl AnnIndex for PqIndex {
+    fn kind(&self) -> &'static str {
+        "pq"
+    }
+
+    fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        PqIndex::insert(self, id, vector)
+    }
+
+    /// Trains ahead of the insert that would trigger it, see
+    /// [`PqIndex::plan_insert`].
+    fn plan_insert(&self, id: &str, _vector: &[f32]) -> Option<IndexPlan> {
+        PqIndex::plan_insert(self, id).map(|p| Box::new(p) as IndexPlan)
+    }
+
+    fn commit_insert(&mut self, id: String, vector: Vec<f32>, plan: Option<IndexPlan>) -> Result<u32> {
+        let plan = plan.and_then(|p| p.downcast::<TrainPlan>().ok()).map(|p| *p);
+        PqIndex::commit_insert(self, id, vector, plan)
+    }
+
+    fn remove(&mut self, id: &str) {
+        PqIndex::remove(self, id)
+    }
+
+    /// `breadth` overrides `rerank`: the best `breadth · top_k` ADC
+    /// candidates are re-scored exactly.
+    fn search_rows(&self, query: &[f32], top_k: usize, breadth: Option<usize>) -> Vec<(u32, f32)> {
+        let rerank = breadth.unwrap_or(self.params.rerank);
+        self.search_rows_filtered_with(query, top_k, rerank, |_| true)
+    }
+
+    fn search_rows_filtered(&self, query: &[f32], top_k: usize, accept: &dyn Fn(u32) -> bool) -> Vec<(u32, f32)> {
+        PqIndex::search_rows_filtered(self, query, top_k, accept)
+    }
+
+    fn search_rows_among(&self, query: &[f32], top_k: usize, rows: &mut dyn Iterator<Item = u32>) -> Vec<(u32, f32)> {
+        PqIndex::search_rows_among(self, query, top_k, rows)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        index::encode(self)
+    }
+
+    fn row(&self, id: &str) -> Option<u32> {
+        self.ids.row(id)
+    }
+
+    fn id_at(&self, row: u32) -> Option<&str> {
+        self.ids.id(row)
+    }
+
+    fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        PqIndex::vector_at(self, row)
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    fn heap_bytes(&self) -> usize {
+        PqIndex::heap_bytes(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.vectors.as_ref().map_or(Ok(()), VectorStore::flush)
+    }
+
+    fn reserve(&mut self, additional: usize) -> Result<()> {
+        match self.vectors.as_mut() {
+            Some(vectors) => vectors.reserve(additional),
+            None => Ok(()),
+        }
+    }
+
+    fn release_free_rows(&mut self) {
+        self.free.release();
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Registry entry for `index_type = "pq"`, with [`PqParams`] from
+/// `index_params`.
+pub const INDEX_TYPE: IndexType = IndexType {
+    name: "pq",
+    build: build_index,
+    load: index::decode::<PqIndex>,
+};
+
+fn build_index(config: &VecBaseConfig, metric: Metric, vectors: VectorStore) -> Box<dyn AnnIndex> {
+    Box::new(PqIndex::with_store(config.max_elements, metric, index::parse_params(config), vectors))
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    use crate::embedding::score;
+    use crate::processing::HnswIndex;
+
+    /// `n` points around 32 random centers, so PQ has structure to learn.
+    fn clustered(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let centers: Vec<Vec<f32>> = (0..32)
+            .map(|_| (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect();
+        (0..n)
+            .map(|i| centers[i % 32].iter().map(|x| x + rng.gen_range(-0.1..0.1)).collect())
+            .collect()
+    }
+
+    /// Recall@10 of `search` over `queries`, against an exact scan of `data`.
+    fn recall_at_10(data: &[Vec<f32>], queries: &[Vec<f32>], search: impl Fn(&[f32]) -> Vec<(String, f32)>) -> f32 {
+        let mut hits = 0;
+        for q in queries {
+            let mut exact: Vec<(usize, f32)> = data.iter().map(|v| score(&Metric::Euclidean, q, v)).enumerate().collect();
+            exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let exact: HashSet<String> = exact[..10].iter().map(|(i, _)| format!("v{}", i)).collect();
+            hits += search(q).iter().filter(|(id, _)| exact.contains(id)).count();
+        }
+        hits as f32 / (queries.len() * 10) as f32
+    }
+
+    #[test]
+    fn test_codec_rejects_bad_params() {
+        let samples = clustered(10, 8, 1);
+        let train = |sub_vectors, bits| {
+            PqCodec::train(8, &PqParams { sub_vectors, bits, ..PqParams::default() }, &samples)
+        };
+        assert!(matches!(train(3, 8), Err(VecBaseError::ConfigError(_))));
+        assert!(matches!(train(0, 8), Err(VecBaseError::ConfigError(_))));
+        assert!(matches!(train(4, 9), Err(VecBaseError::ConfigError(_))));
+        assert!(matches!(train(4, 0), Err(VecBaseError::ConfigError(_))));
+        assert!(PqCodec::train(8, &PqParams::default(), &[]).is_err());
+        assert!(matches!(
+            PqCodec::train(8, &PqParams::default(), &[vec![0.0; 7]]),
+            Err(VecBaseError::DimensionMismatch { expected: 8, got: 7 })
+        ));
+    }
+
+    #[test]
+    fn test_codec_roundtrip_and_tables() {
+        let dim = 16;
+        let data = clustered(2_000, dim, 2);
+        let params = PqParams { sub_vectors: 4, bits: 6, ..PqParams::default() };
+        let codec = PqCodec::train(dim, &params, &data).unwrap();
+
+        let mut err = 0.0;
+        for v in &data[..200] {
+            let code = codec.encode(v);
+            assert_eq!(code.len(), 4);
+            assert!(code.iter().all(|&c| c < 64));
+            err += euclidean_distance_sq(v, &codec.decode(&code));
+        }
+        // Centers spread ±1 per dimension (≈5.3 squared per vector), noise
+        // ±0.1 (≈0.053): the codes must capture the clusters, leaving about
+        // the noise.
+        assert!(err / 200.0 < 0.06, "mean squared reconstruction error {}", err / 200.0);
+
+        // ADC sums equal exact scores against the decoded vector.
+        let q = &data[1_999];
+        let code = codec.encode(&data[7]);
+        let decoded = codec.decode(&code);
+        for metric in [Metric::Euclidean, Metric::DotProduct] {
+            let adc = codec.distance_table(q, &metric).score(&code);
+            let exact = rank_score(&metric, q, &decoded);
+            assert!((adc - exact).abs() < 1e-3, "{:?}: {} vs {}", metric, adc, exact);
+        }
+    }
+
+    #[test]
+    fn test_pq_index_insert_search_remove() {
+        let dim = 8;
+        let data = clustered(500, dim, 3);
+        let params = PqParams { sub_vectors: 4, bits: 4, rerank: 4, ..PqParams::default() };
+        let mut idx = PqIndex::train(dim, 500, Metric::Euclidean, params, &data).unwrap();
+        for (i, v) in data.iter().enumerate() {
+            idx.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+        assert_eq!(idx.len(), 500);
+        assert!(matches!(
+            idx.insert("extra".into(), data[0].clone()),
+            Err(VecBaseError::CapacityExceeded { max_elements: 500 })
+        ));
+        assert!(matches!(idx.insert("v0".into(), vec![1.0]), Err(VecBaseError::DimensionMismatch { .. })));
+
+        // Re-ranked scores are exact.
+        let hits = idx.search(&data[42], 3);
+        assert_eq!(hits[0].0, "v42");
+        assert_eq!(hits[0].1, 0.0);
+        let (id, s) = &hits[1];
+        let row: usize = id[1..].parse().unwrap();
+        assert_eq!(*s, score(&Metric::Euclidean, &data[42], &data[row]));
+
+        idx.remove("v42");
+        assert!(!idx.contains("v42"));
+        assert!(idx.search(&data[42], 10).iter().all(|(id, _)| id != "v42"));
+        let row = idx.insert("new".into(), data[42].clone()).unwrap();
+        assert_eq!(idx.len(), 500);
+        assert_eq!(idx.search_rows(&data[42], 1)[0].0, row);
+
+        // Replacing keeps the row.
+        let again = idx.insert("new".into(), data[1].clone()).unwrap();
+        assert_eq!(again, row);
+        assert_eq!(idx.search(&data[1], 2).iter().filter(|(id, _)| id == "new").count(), 1);
+        assert!(idx.search(&data[1], 0).is_empty());
+        assert!(idx.search(&[1.0], 5).is_empty());
+        // top_k far beyond the index size returns every row.
+        assert_eq!(idx.search(&data[1], usize::MAX).len(), 500);
+    }
+
+    #[test]
+    fn test_store_backed_index_trains_when_full() {
+        let dim = 8;
+        let data = clustered(400, dim, 6);
+        // 6 does not divide 8: cut down to 2 sub-vectors of 4 dimensions.
+        let params = PqParams { sub_vectors: 6, bits: 2, ..PqParams::default() };
+        let mut idx = PqIndex::with_store(1_000, Metric::Euclidean, params, VectorStore::memory(dim));
+        assert_eq!(idx.params().sub_vectors, 2);
+
+        let threshold = TRAIN_MIN_PER_CENTROID << 2;
+        for (i, v) in data[..threshold - 1].iter().enumerate() {
+            idx.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+        assert!(!idx.is_trained());
+        assert_eq!(idx.search_rows(&data[3], 1)[0], (3, 0.0));
+
+        // A plan made before another write is stale and ignored.
+        let stale = idx.plan_insert("next").unwrap();
+        idx.remove("v0");
+        idx.commit_insert("v0".into(), data[0].clone(), Some(stale)).unwrap();
+        assert!(!idx.is_trained());
+
+        let plan = idx.plan_insert("next");
+        assert!(plan.is_some());
+        idx.commit_insert("next".into(), data[threshold].clone(), plan).unwrap();
+        assert!(idx.is_trained());
+        assert_eq!(idx.codec().unwrap().code_len(), 2);
+        assert_eq!(AnnIndex::vector_at(&idx, 5), Some(&data[5][..]));
+
+        // Without re-ranking scores come from codes; a breadth re-ranks.
+        let exact = AnnIndex::search_rows(&idx, &data[9], 1, Some(50));
+        assert_eq!(exact[0], (9, 0.0));
+        let among = AnnIndex::search_rows_among(&idx, &data[9], 2, &mut [9, 10, 999].into_iter());
+        assert_eq!(among.len(), 2);
+        assert_eq!(among[0], (9, 0.0));
+        assert_eq!(AnnIndex::search_rows(&idx, &data[9], 4_000_000_000, Some(0)).len(), idx.len());
+    }
+
+    #[test]
+    fn test_pq_recall_and_memory_vs_hnsw() {
+        let dim = 32;
+        let data = clustered(2_000, dim, 4);
+        let queries = clustered(30, dim, 5);
+        let params = PqParams { sub_vectors: 8, bits: 6, ..PqParams::default() };
+
+        let mut hnsw = HnswIndex::new(dim, 10_000, Metric::Euclidean);
+        let mut plain = PqIndex::train(dim, 10_000, Metric::Euclidean, params, &data).unwrap();
+        let mut reranked = PqIndex::with_codec(plain.codec().unwrap().clone(), 10_000, Metric::Euclidean, 10);
+        for (i, v) in data.iter().enumerate() {
+            hnsw.insert(format!("v{}", i), v.clone()).unwrap();
+            plain.insert(format!("v{}", i), v.clone()).unwrap();
+            reranked.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+
+        let plain_recall = recall_at_10(&data, &queries, |q| plain.search(q, 10));
+        let reranked_recall = recall_at_10(&data, &queries, |q| reranked.search(q, 10));
+        // Neighbors within a cluster differ only by noise the codes cannot
+        // resolve; picking 10 of the ~62 cluster members at random would
+        // score ≈0.16. Re-ranking recovers exact order.
+        assert!(plain_recall >= 0.2, "pq recall@10 {}", plain_recall);
+        assert!(reranked_recall >= 0.95, "re-ranked pq recall@10 {}", reranked_recall);
+        assert!(reranked_recall >= recall_at_10(&data, &queries, |q| hnsw.search(q, 10)) - 0.05);
+
+        // 8 code bytes per vector instead of 128 vector bytes plus links;
+        // what remains is mostly ids.
+        assert!(plain.heap_bytes() * 4 < hnsw.heap_bytes());
+    }
+}
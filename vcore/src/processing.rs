@@ -1,55 +1,227 @@
 // VecBase — processing.rs
-// In-memory HNSW-lite index, batch insert/query processing.
+// In-memory HNSW index, batch insert and parallel batch search.
 // Author: d65v <https://github.com/d65v>
 //
-// NOTE: This is a simplified HNSW-inspired structure. A production HNSW
-// implementation requires skip-list layers, entry-point management, and more
-// careful neighbor pruning. This provides the skeleton with correct API and
-// brute-force fallback for correctness.
+// NOTE: Hierarchical Navigable Small World graph (Malkov & Yashunin, 2016).
+// Every node gets a random top level drawn from an exponential distribution;
+// upper layers are sparse "express lanes", layer 0 holds every node. Search
+// greedily descends from the top-level entry point, then runs a beam search
+// on layer 0. Small datasets use a brute-force fallback for exactness.
+// Candidates are compared by `rank_score` (negated squared distance for
+// Euclidean, so no sqrt per candidate); only the final top-k are converted
+// to public scores. With scalar quantization on, searches score the
+// quantized copy and optionally re-score the final candidates against the
+// full vectors; the graph itself is always wired with full vectors.
 
-use std::collections::HashMap;
+use std::any::Any;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
-use crate::embedding::{score, Metric};
+use ordered_float::OrderedFloat;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-// ── HNSW Node ─────────────────────────────────────────────────────────────────
+use crate::embedding::{rank_score, score_from_rank, Metric};
+use crate::index::{self, AnnIndex, IndexPlan, IndexType};
+use crate::quantize::{Quantization, QuantizedVectors};
+use crate::vectors::{FreeRows, VectorStore};
+use crate::{Result, VecBaseConfig, VecBaseError};
 
-#[derive(Debug, Clone)]
-struct Node {
-    id: String,
-    vector: Vec<f32>,
-    /// Neighbor lists per layer (layer 0 = densest)
-    neighbors: Vec<Vec<String>>,
-}
+pub mod vbi;
 
 // ── HNSW Index ────────────────────────────────────────────────────────────────
 
-/// A lightweight HNSW-inspired approximate nearest neighbor index.
-/// Falls back to brute-force when the dataset is small (< BRUTE_THRESHOLD).
+/// Build and search parameters for [`HnswIndex`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HnswParams {
+    /// Maximum neighbors per node on layers ≥ 1 (layer 0 allows 2·M)
+    pub m: usize,
+    /// Beam width used while wiring a new node into the graph
+    pub ef_construction: usize,
+    /// Default beam width for queries (raised to top_k when smaller)
+    pub ef_search: usize,
+    /// Datasets with at most this many vectors are searched exactly
+    pub brute_threshold: usize,
+    /// Keep a quantized copy of every vector and search that instead
+    pub quantization: Quantization,
+    /// Re-score the final candidates of a quantized search against the
+    /// full vectors
+    pub rescore: bool,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 100,
+            ef_search: 64,
+            brute_threshold: 500,
+            quantization: Quantization::None,
+            rescore: true,
+        }
+    }
+}
+
+/// A hierarchical navigable small world approximate nearest neighbor index.
+/// Falls back to brute-force when the dataset is small (≤ `brute_threshold`).
+///
+/// Nodes are addressed internally by a dense `u32` row number: the vector
+/// lives at that row of the [`VectorStore`], adjacency lists hold rows, and
+/// external string ids are only touched at the API boundary. Rows freed by
+/// `remove` are reused by later inserts.
+#[derive(Serialize, Deserialize)]
 pub struct HnswIndex {
     dim: usize,
     max_elements: usize,
-    nodes: HashMap<String, Node>,
-    /// Maximum neighbors per node per layer
+    /// Metric used both to wire neighbor lists and to rank search results
+    metric: Metric,
+    /// Full vector for each row — the copy `vector()`, graph wiring and
+    /// rescoring read
+    vectors: VectorStore,
+    /// Quantized copy searches score instead, rebuilt from `vectors` on load
+    #[serde(skip)]
+    quantized: Option<QuantizedVectors>,
+    /// Re-score quantized search candidates against `vectors`
+    #[serde(skip)]
+    rescore: bool,
+    /// External id ↔ row
+    ids: IdTable,
+    /// Row → neighbor rows per layer (layer 0 = densest).
+    /// `links[r].len() - 1` is the row's top level; empty for free rows.
+    links: Vec<Vec<Vec<u32>>>,
+    /// Free rows, reused before the store grows
+    free: FreeRows,
+    /// Maximum neighbors per node on layers ≥ 1
     m: usize,
-    /// Entry point (id of the top-layer node)
-    entry: Option<String>,
+    /// Maximum neighbors per node on layer 0 (conventionally 2·M)
+    m0: usize,
+    /// Beam width used while wiring a new node into the graph
+    ef_construction: usize,
+    /// Default beam width for queries
+    ef_search: usize,
+    brute_threshold: usize,
+    /// Level generation factor, 1 / ln(M)
+    level_mult: f64,
+    /// Entry point (row of a node on the top layer)
+    entry: Option<u32>,
+    /// Level of the current entry point
+    max_level: usize,
+    #[serde(skip, default = "level_rng")]
+    rng: Mutex<StdRng>,
+    /// Bumped by every mutation, so stale insert/remove plans are detected
+    #[serde(skip)]
+    version: u64,
+}
+
+fn level_rng() -> Mutex<StdRng> {
+    Mutex::new(StdRng::seed_from_u64(0x5eed_ba5e))
+}
+
+/// Level and neighbors for a pending insert, from [`HnswIndex::plan_insert`].
+#[derive(Debug)]
+pub struct InsertPlan {
+    /// Index version the plan was made against
+    version: u64,
+    level: usize,
+    /// Chosen neighbor rows per layer, 0..=level
+    links: Vec<Vec<u32>>,
+    /// The same id's current node, removed on commit
+    replaces: Option<RemovePlan>,
+}
+
+/// Rows linking to a node about to be removed, from [`HnswIndex::plan_remove`].
+#[derive(Debug)]
+pub struct RemovePlan {
+    /// Index version the plan was made against
+    version: u64,
+    row: u32,
+    referrers: Vec<u32>,
 }
 
-const BRUTE_THRESHOLD: usize = 500;
+/// [`HnswIndex::insert_bulk`] inserts this many nodes one by one before it
+/// starts planning in parallel.
+pub const BULK_SEED: usize = 1_000;
+
+/// A bulk-insert wave holds at most `len / BULK_WAVE_FRACTION` nodes.
+pub const BULK_WAVE_FRACTION: usize = 10;
+
+/// Upper bound on the nodes in one bulk-insert wave.
+pub const BULK_MAX_WAVE: usize = 8_192;
+
+/// Candidate during graph traversal: (rank score, row). Higher = closer.
+type Scored = (OrderedFloat<f32>, u32);
+
+/// A query vector and the copy of the index vectors it is ranked against:
+/// the quantized one for searches when kept, the full one otherwise.
+struct Query<'a> {
+    vector: &'a [f32],
+    quantized: Option<&'a QuantizedVectors>,
+}
 
 impl HnswIndex {
-    pub fn new(dim: usize, max_elements: usize) -> Self {
+    pub fn new(dim: usize, max_elements: usize, metric: Metric) -> Self {
+        Self::with_params(dim, max_elements, metric, HnswParams::default())
+    }
+
+    /// Create an index with explicit build/search parameters.
+    /// `m` is clamped to at least 2 and `ef_construction` to at least `m`.
+    pub fn with_params(dim: usize, max_elements: usize, metric: Metric, params: HnswParams) -> Self {
+        Self::with_store(max_elements, metric, params, VectorStore::memory(dim))
+    }
+
+    /// Create an empty index over an explicit vector store, e.g. a
+    /// memory-mapped one from [`VectorStore::mmap`]. Any rows already in
+    /// the store are treated as free and will be overwritten.
+    pub fn with_store(max_elements: usize, metric: Metric, params: HnswParams, vectors: VectorStore) -> Self {
+        let m = params.m.max(2);
+        let allocated = vectors.len() as u32;
         Self {
-            dim,
+            dim: vectors.dim(),
             max_elements,
-            nodes: HashMap::new(),
-            m: 16,
+            metric,
+            ids: IdTable::with_free_rows(allocated as usize),
+            links: vec![Vec::new(); allocated as usize],
+            free: FreeRows::all(&vectors),
+            quantized: QuantizedVectors::new(params.quantization, vectors.dim()),
+            rescore: params.rescore,
+            vectors,
+            m,
+            m0: m * 2,
+            ef_construction: params.ef_construction.max(m),
+            ef_search: params.ef_search.max(1),
+            brute_threshold: params.brute_threshold,
+            level_mult: 1.0 / (m as f64).ln(),
             entry: None,
+            max_level: 0,
+            rng: level_rng(),
+            version: 0,
         }
     }
 
-    /// Insert a new vector into the index.
-    pub fn insert(&mut self, id: String, vector: Vec<f32>) {
+    /// Insert a new vector into the index and return the row it was stored
+    /// at. Re-inserting an existing id replaces its vector and links.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::CapacityExceeded` if the index already holds
+    /// `max_elements` vectors, or `VecBaseError::StorageError` if the vector
+    /// store cannot grow.
+    pub fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        let plan = self.plan_insert(&id, &vector);
+        self.commit_insert(id, vector, plan)
+    }
+
+    /// First half of [`insert`](Self::insert): pick the new node's level and
+    /// neighbors. This is the expensive part (a beam search per layer) and
+    /// only needs a shared borrow, so searches can run alongside it.
+    pub fn plan_insert(&self, id: &str, vector: &[f32]) -> InsertPlan {
+        self.plan_insert_at(id, vector, self.random_level())
+    }
+
+    fn plan_insert_at(&self, id: &str, vector: &[f32], level: usize) -> InsertPlan {
         debug_assert_eq!(
             vector.len(),
             self.dim,
@@ -58,79 +230,345 @@ impl HnswIndex {
             self.dim
         );
 
-        if self.nodes.len() >= self.max_elements {
-            log::warn!("HnswIndex: max_elements ({}) reached, skipping insert for '{}'",
-                self.max_elements, id);
-            return;
-        }
+        let replaces = self.ids.row(id).map(|row| self.plan_remove_row(row));
+        let mut links = vec![Vec::new(); level + 1];
+        let query = self.exact(vector);
 
-        let node = Node {
-            id: id.clone(),
-            vector,
-            neighbors: vec![Vec::new()], // layer 0 only for now
-        };
+        if let Some(entry) = self.entry {
+            // Phase 1: greedy descent through the layers above the new node's level.
+            let mut ep = entry;
+            for layer in (level + 1..=self.max_level).rev() {
+                ep = self.greedy_closest(&query, ep, layer);
+            }
 
-        // If we have existing nodes, wire up nearest neighbors
-        if !self.nodes.is_empty() {
-            let nearest = self.brute_search(&node.vector, self.m, &Metric::Cosine);
-            let mut n = node.clone();
-            n.neighbors[0] = nearest.iter().map(|(nid, _)| nid.clone()).collect();
-            self.nodes.insert(id.clone(), n);
-
-            // Back-link: add this node to its neighbors' neighbor lists
-            for (nid, _) in &nearest {
-                if let Some(neighbor) = self.nodes.get_mut(nid) {
-                    if neighbor.neighbors[0].len() < self.m {
-                        neighbor.neighbors[0].push(id.clone());
-                    }
+            // Phase 2: beam search on each shared layer and link a diverse set
+            // of up to M neighbors chosen by the selection heuristic. The node
+            // being replaced is skipped, as it is gone by the time we link.
+            let replaced = replaces.as_ref().map(|p| p.row);
+            for layer in (0..=level.min(self.max_level)).rev() {
+                let mut found = self.search_layer(&query, &[ep], self.ef_construction, layer);
+                if let Some(&(closest, _)) = found.first() {
+                    ep = closest;
                 }
+                found.retain(|&(r, _)| Some(r) != replaced);
+                links[layer] = self.select_neighbors(&found, self.m);
             }
+        }
+
+        InsertPlan {
+            version: self.version,
+            level,
+            links,
+            replaces,
+        }
+    }
+
+    /// Second half of [`insert`](Self::insert): store the vector and wire in
+    /// the links chosen by `plan`. Cheap, but needs exclusive access. If the
+    /// index changed since `plan` was made it is re-planned first.
+    ///
+    /// # Errors
+    /// As for [`insert`](Self::insert).
+    pub fn commit_insert(&mut self, id: String, vector: Vec<f32>, plan: InsertPlan) -> Result<u32> {
+        let plan = if plan.version == self.version {
+            plan
         } else {
-            self.nodes.insert(id.clone(), node);
+            self.plan_insert(&id, &vector)
+        };
+        self.version += 1;
+        self.link(id, vector, plan)
+    }
+
+    /// Store the vector and wire in `plan`'s links, trusting that every row
+    /// the plan refers to is still live.
+    fn link(&mut self, id: String, vector: Vec<f32>, plan: InsertPlan) -> Result<u32> {
+        if let Some(old) = plan.replaces {
+            self.apply_remove(old);
         }
 
+        if self.ids.len() >= self.max_elements {
+            return Err(VecBaseError::CapacityExceeded {
+                max_elements: self.max_elements,
+            });
+        }
+
+        let level = plan.level;
         if self.entry.is_none() {
-            self.entry = Some(id);
+            let row = self.alloc_row(&id, &vector, vec![Vec::new(); level + 1])?;
+            self.entry = Some(row);
+            self.max_level = level;
+            return Ok(row);
+        }
+
+        let links = plan.links;
+        let row = self.alloc_row(&id, &vector, links.clone())?;
+
+        // Back-link: add this node to its neighbors' neighbor lists, and
+        // re-select the best max_conn whenever a list overflows.
+        for (layer, selected) in links.into_iter().enumerate() {
+            let max_conn = self.max_connections(layer);
+            for nb in selected {
+                let list = &mut self.links[nb as usize][layer];
+                list.push(row);
+                if list.len() > max_conn {
+                    self.prune_neighbors(nb, layer, max_conn);
+                }
+            }
         }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry = Some(row);
+        }
+        Ok(row)
+    }
+
+    /// Insert many vectors at once, planning their links on all cores.
+    /// Returns the row of each item, in input order.
+    ///
+    /// Items go in as waves: every node of a wave is planned in parallel
+    /// against the graph as it stood before the wave, then linked in input
+    /// order. A wave never exceeds 1/[`BULK_WAVE_FRACTION`] of the nodes
+    /// already indexed, so it only misses links between its own members —
+    /// a small share of any node's neighborhood — and later back-links fill
+    /// most of those in. The first [`BULK_SEED`] nodes are inserted one by
+    /// one. Recall ends up close to inserting the items one at a time.
+    ///
+    /// Ids that are already indexed, or repeat within a wave, are inserted
+    /// one by one after the rest of their wave, so they still replace the
+    /// earlier vector.
+    ///
+    /// # Errors
+    /// As for [`insert`](Self::insert). Items before the failing one stay
+    /// inserted.
+    pub fn insert_bulk(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<Vec<u32>> {
+        let mut rows = Vec::with_capacity(items.len());
+        let mut items = items.into_iter().peekable();
+
+        while items.peek().is_some() {
+            let wave_len = (self.len() / BULK_WAVE_FRACTION).min(BULK_MAX_WAVE);
+            if self.len() < BULK_SEED || wave_len <= 1 {
+                let (id, vector) = items.next().expect("peeked");
+                rows.push(self.insert(id, vector)?);
+                continue;
+            }
+
+            let wave: Vec<(String, Vec<f32>)> = items.by_ref().take(wave_len).collect();
+            // Levels are drawn up front so the graph does not depend on
+            // which thread plans which node.
+            let levels: Vec<usize> = wave.iter().map(|_| self.random_level()).collect();
+            let plans: Vec<Option<InsertPlan>> = wave
+                .par_iter()
+                .zip(levels)
+                .map(|((id, vector), level)| {
+                    (!self.ids.contains(id)).then(|| self.plan_insert_at(id, vector, level))
+                })
+                .collect();
+
+            let first = rows.len();
+            let mut deferred = Vec::new();
+            self.version += 1;
+            for (i, ((id, vector), plan)) in wave.into_iter().zip(plans).enumerate() {
+                match plan {
+                    Some(plan) if !self.ids.contains(&id) => rows.push(self.link(id, vector, plan)?),
+                    _ => {
+                        rows.push(u32::MAX);
+                        deferred.push((first + i, id, vector));
+                    }
+                }
+            }
+            for (i, id, vector) in deferred {
+                rows[i] = self.insert(id, vector)?;
+            }
+        }
+        Ok(rows)
     }
 
     /// Remove a node from the index.
     pub fn remove(&mut self, id: &str) {
-        self.nodes.remove(id);
+        let plan = self.plan_remove(id);
+        self.commit_remove(plan);
+    }
+
+    /// First half of [`remove`](Self::remove): find every node linking to
+    /// `id`. Scans the whole graph, but only needs a shared borrow.
+    /// `None` if `id` is not indexed.
+    pub fn plan_remove(&self, id: &str) -> Option<RemovePlan> {
+        self.ids.row(id).map(|row| self.plan_remove_row(row))
+    }
+
+    fn plan_remove_row(&self, row: u32) -> RemovePlan {
+        let referrers = self
+            .live_rows()
+            .filter(|&r| r != row && self.links[r as usize].iter().any(|l| l.contains(&row)))
+            .collect();
+        RemovePlan {
+            version: self.version,
+            row,
+            referrers,
+        }
+    }
+
+    /// Second half of [`remove`](Self::remove): unlink and free the node.
+    /// If the index changed since `plan` was made it is re-planned first.
+    pub fn commit_remove(&mut self, plan: Option<RemovePlan>) {
+        let plan = match plan {
+            Some(p) if p.version == self.version => p,
+            Some(p) => match self.ids.id(p.row) {
+                Some(_) => self.plan_remove_row(p.row),
+                None => return,
+            },
+            None => return,
+        };
+        self.version += 1;
+        self.apply_remove(plan);
+    }
+
+    fn apply_remove(&mut self, plan: RemovePlan) {
+        let row = plan.row;
+        self.ids.remove_row(row);
+        self.links[row as usize].clear();
+        self.free.push(row, &self.vectors);
+
         // Remove back-references
-        for node in self.nodes.values_mut() {
-            node.neighbors[0].retain(|nid| nid != id);
+        for r in plan.referrers {
+            for layer in self.links[r as usize].iter_mut() {
+                layer.retain(|&nb| nb != row);
+            }
         }
-        // Update entry point if needed
-        if self.entry.as_deref() == Some(id) {
-            self.entry = self.nodes.keys().next().cloned();
+        // Promote the highest remaining node if the entry point went away
+        if self.entry == Some(row) {
+            match self.live_rows().max_by_key(|&r| self.level(r)) {
+                Some(top) => {
+                    self.max_level = self.level(top);
+                    self.entry = Some(top);
+                }
+                None => {
+                    self.max_level = 0;
+                    self.entry = None;
+                }
+            }
         }
     }
 
     /// Search for top-k nearest neighbors.
     /// Uses brute-force for small datasets, graph traversal for larger ones.
-    pub fn search(&self, query: &[f32], top_k: usize, metric: &Metric) -> Vec<(String, f32)> {
-        if self.nodes.is_empty() {
-            return vec![];
+    pub fn search(&self, query: &[f32], top_k: usize) -> Vec<(String, f32)> {
+        self.search_with_ef(query, top_k, self.ef_search)
+    }
+
+    /// Like [`search`](Self::search), but with an explicit beam width for
+    /// the layer-0 graph traversal. Larger `ef` trades latency for recall.
+    pub fn search_with_ef(&self, query: &[f32], top_k: usize, ef: usize) -> Vec<(String, f32)> {
+        self.search_rows(query, top_k, ef)
+            .into_iter()
+            .filter_map(|(r, s)| self.ids.id(r).map(|id| (id.to_string(), s)))
+            .collect()
+    }
+
+    /// Like [`search_with_ef`](Self::search_with_ef), but returns rows so
+    /// callers can resolve ids and vectors without copying them.
+    pub fn search_rows(&self, query: &[f32], top_k: usize, ef: usize) -> Vec<(u32, f32)> {
+        let keep = self.candidates(top_k, ef);
+        let found = if self.ids.len() <= self.brute_threshold {
+            self.brute_search(&self.query(query), keep)
+        } else {
+            self.graph_search(&self.query(query), keep, ef)
+        };
+        self.to_scores(self.rescored(query, found, top_k))
+    }
+
+    /// Search restricted to rows for which `accept` returns true.
+    ///
+    /// The filter is applied during traversal: rejected nodes are still
+    /// walked through to reach accepted ones, but never enter the result
+    /// set, so the beam keeps expanding until it holds `ef` accepted rows.
+    /// If the graph walk still comes up short of `top_k`, an exact scan over
+    /// all rows fills the gap, so fewer than `top_k` results means fewer
+    /// than `top_k` rows match.
+    pub fn search_rows_filtered<F: Fn(u32) -> bool>(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        ef: usize,
+        accept: F,
+    ) -> Vec<(u32, f32)> {
+        let keep = self.candidates(top_k, ef);
+        let mut found = Vec::new();
+        if self.ids.len() > self.brute_threshold {
+            found = self.graph_search_filtered(&self.query(query), keep, ef, &accept);
+        }
+        if found.len() < top_k {
+            found = self.brute_search_filtered(&self.query(query), keep, &accept);
+        }
+        self.to_scores(self.rescored(query, found, top_k))
+    }
+
+    /// How many candidates a search keeps before rescoring: all `ef` of
+    /// them when quantized results get re-scored, else just `top_k`.
+    fn candidates(&self, top_k: usize, ef: usize) -> usize {
+        if self.rescoring() {
+            ef.max(top_k)
+        } else {
+            top_k
         }
+    }
 
-        if self.nodes.len() <= BRUTE_THRESHOLD {
-            return self.brute_search(query, top_k, metric);
+    fn rescoring(&self) -> bool {
+        self.rescore && self.quantized.is_some()
+    }
+
+    /// Re-rank quantized candidates against the full vectors (when
+    /// rescoring is on) and keep the best `top_k`.
+    fn rescored(&self, query: &[f32], mut found: Vec<(u32, f32)>, top_k: usize) -> Vec<(u32, f32)> {
+        if self.rescoring() {
+            for (r, s) in found.iter_mut() {
+                *s = rank_score(&self.metric, query, self.vectors.get(*r));
+            }
+            found.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
         }
+        found.truncate(top_k);
+        found
+    }
 
-        self.graph_search(query, top_k, metric)
+    /// Convert rank scores (see [`rank_score`]) to public scores. Only the
+    /// final top-k pay for this, not every candidate.
+    fn to_scores(&self, mut found: Vec<(u32, f32)>) -> Vec<(u32, f32)> {
+        for (_, s) in found.iter_mut() {
+            *s = score_from_rank(&self.metric, *s);
+        }
+        found
     }
 
     // ── Private: Brute-Force Search ───────────────────────────────────────────
 
-    fn brute_search(&self, query: &[f32], top_k: usize, metric: &Metric) -> Vec<(String, f32)> {
-        let mut scored: Vec<(String, f32)> = self
-            .nodes
-            .values()
-            .map(|node| {
-                let s = score(metric, query, &node.vector);
-                (node.id.clone(), s)
-            })
+    fn brute_search(&self, query: &Query, top_k: usize) -> Vec<(u32, f32)> {
+        self.brute_search_filtered(query, top_k, |_| true)
+    }
+
+    fn brute_search_filtered<F: Fn(u32) -> bool>(&self, query: &Query, top_k: usize, accept: F) -> Vec<(u32, f32)> {
+        self.rank_among(query, top_k, self.live_rows().filter(|&r| accept(r)))
+    }
+
+    /// Exact top-k over just `rows`, e.g. a candidate set from a payload
+    /// index. Free rows are skipped.
+    pub fn search_rows_among(
+        &self,
+        query: &[f32],
+        top_k: usize,
+        rows: impl IntoIterator<Item = u32>,
+    ) -> Vec<(u32, f32)> {
+        let keep = self.candidates(top_k, self.ef_search);
+        let found = self.rank_among(&self.query(query), keep, rows);
+        self.to_scores(self.rescored(query, found, top_k))
+    }
+
+    /// [`search_rows_among`](Self::search_rows_among) with rank scores.
+    fn rank_among(&self, query: &Query, top_k: usize, rows: impl IntoIterator<Item = u32>) -> Vec<(u32, f32)> {
+        let mut scored: Vec<(u32, f32)> = rows
+            .into_iter()
+            .filter(|&r| self.ids.id(r).is_some())
+            .map(|r| (r, self.rank(query, r)))
             .collect();
 
         // Sort descending by score (higher = better)
@@ -139,62 +577,541 @@ impl HnswIndex {
         scored
     }
 
-    // ── Private: Graph-Based Search (HNSW-lite) ───────────────────────────────
+    // ── Private: Graph-Based Search (HNSW) ────────────────────────────────────
 
-    fn graph_search(&self, query: &[f32], top_k: usize, metric: &Metric) -> Vec<(String, f32)> {
-        let entry_id = match &self.entry {
-            Some(e) => e.clone(),
+    fn graph_search(&self, query: &Query, top_k: usize, ef: usize) -> Vec<(u32, f32)> {
+        self.graph_search_filtered(query, top_k, ef, |_| true)
+    }
+
+    fn graph_search_filtered<F: Fn(u32) -> bool>(
+        &self,
+        query: &Query,
+        top_k: usize,
+        ef: usize,
+        accept: F,
+    ) -> Vec<(u32, f32)> {
+        let mut ep = match self.entry {
+            Some(e) => e,
             None => return vec![],
         };
 
-        let mut visited: std::collections::HashSet<String> = std::collections::HashSet::new();
-        // candidate queue: (score, id) — max-heap by score
-        let mut candidates: Vec<(ordered_float::OrderedFloat<f32>, String)> = Vec::new();
-        let mut results: Vec<(String, f32)> = Vec::new();
+        for layer in (1..=self.max_level).rev() {
+            ep = self.greedy_closest(query, ep, layer);
+        }
 
-        // Seed with entry point
-        if let Some(entry_node) = self.nodes.get(&entry_id) {
-            let s = score(metric, query, &entry_node.vector);
-            candidates.push((ordered_float::OrderedFloat(s), entry_id.clone()));
-            visited.insert(entry_id.clone());
+        let ef = ef.max(top_k); // exploration factor
+        let mut results = self.search_layer_filtered(query, &[ep], ef, 0, accept);
+        results.truncate(top_k);
+        results
+    }
+
+    /// Walk greedily along `layer` from `start` until no neighbor is closer.
+    fn greedy_closest(&self, query: &Query, start: u32, layer: usize) -> u32 {
+        let mut cur = start;
+        let mut cur_score = self.rank(query, cur);
+
+        loop {
+            let mut improved = false;
+            for &nb in self.layer_neighbors(cur, layer) {
+                let s = self.rank(query, nb);
+                if s > cur_score {
+                    cur_score = s;
+                    cur = nb;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return cur;
+            }
         }
+    }
 
-        let ef = top_k * 4; // exploration factor
+    /// Beam search restricted to `layer`. Returns up to `ef` rows sorted by
+    /// descending score.
+    fn search_layer(&self, query: &Query, entry_points: &[u32], ef: usize, layer: usize) -> Vec<(u32, f32)> {
+        self.search_layer_filtered(query, entry_points, ef, layer, |_| true)
+    }
 
-        while !candidates.is_empty() && results.len() < ef {
-            // Pick best candidate
-            candidates.sort_by(|a, b| b.0.cmp(&a.0));
-            let (cur_score, cur_id) = candidates.remove(0);
-            results.push((cur_id.clone(), cur_score.into_inner()));
+    /// [`search_layer`](Self::search_layer) where only rows passing `accept`
+    /// may enter the results; every visited row still joins the candidates.
+    fn search_layer_filtered<F: Fn(u32) -> bool>(
+        &self,
+        query: &Query,
+        entry_points: &[u32],
+        ef: usize,
+        layer: usize,
+        accept: F,
+    ) -> Vec<(u32, f32)> {
+        let mut visited: HashSet<u32> = HashSet::new();
+        // candidates: max-heap by score; results: min-heap by score, capped at ef
+        let mut candidates: BinaryHeap<Scored> = BinaryHeap::new();
+        let mut results: BinaryHeap<Reverse<Scored>> = BinaryHeap::new();
 
-            // Explore neighbors
-            if let Some(node) = self.nodes.get(&cur_id) {
-                for nid in &node.neighbors[0] {
-                    if visited.contains(nid) {
-                        continue;
-                    }
-                    visited.insert(nid.clone());
-                    if let Some(n) = self.nodes.get(nid) {
-                        let s = score(metric, query, &n.vector);
-                        candidates.push((ordered_float::OrderedFloat(s), nid.clone()));
+        for &ep in entry_points {
+            let s = OrderedFloat(self.rank(query, ep));
+            visited.insert(ep);
+            candidates.push((s, ep));
+            if accept(ep) {
+                results.push(Reverse((s, ep)));
+            }
+        }
+
+        while let Some((cur_score, cur)) = candidates.pop() {
+            let worst = results.peek().map(|r| r.0 .0).unwrap_or(OrderedFloat(f32::MIN));
+            if cur_score < worst && results.len() >= ef {
+                break;
+            }
+
+            for &nb in self.layer_neighbors(cur, layer) {
+                if !visited.insert(nb) {
+                    continue;
+                }
+                let s = OrderedFloat(self.rank(query, nb));
+                let worst = results.peek().map(|r| r.0 .0).unwrap_or(OrderedFloat(f32::MIN));
+                if results.len() < ef || s > worst {
+                    candidates.push((s, nb));
+                    if accept(nb) {
+                        results.push(Reverse((s, nb)));
+                        if results.len() > ef {
+                            results.pop();
+                        }
                     }
                 }
             }
         }
 
-        // Final sort and truncate
-        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        results.truncate(top_k);
-        results
+        let mut out: Vec<(u32, f32)> = results
+            .into_iter()
+            .map(|Reverse((s, r))| (r, s.into_inner()))
+            .collect();
+        out.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        out
+    }
+
+    /// Query that searches score against, quantized when a copy is kept.
+    fn query<'a>(&'a self, vector: &'a [f32]) -> Query<'a> {
+        Query {
+            vector,
+            quantized: self.quantized.as_ref(),
+        }
+    }
+
+    /// Query scored against the full vectors, used to wire the graph.
+    fn exact<'a>(&self, vector: &'a [f32]) -> Query<'a> {
+        Query { vector, quantized: None }
+    }
+
+    #[inline]
+    fn rank(&self, query: &Query, row: u32) -> f32 {
+        match query.quantized {
+            Some(q) => q.rank(&self.metric, query.vector, row),
+            None => rank_score(&self.metric, query.vector, self.vectors.get(row)),
+        }
+    }
+
+    /// HNSW neighbor-selection heuristic (Malkov & Yashunin, Algorithm 4).
+    ///
+    /// `candidates` must be sorted by descending score to the base point. A
+    /// candidate is kept only if it is closer to the base point than to every
+    /// neighbor already kept, which favours links pointing in different
+    /// directions over a tight clump from the same cluster.
+    fn select_neighbors(&self, candidates: &[(u32, f32)], m: usize) -> Vec<u32> {
+        let mut kept: Vec<u32> = Vec::with_capacity(m);
+        for &(c, c_score) in candidates {
+            if kept.len() >= m {
+                break;
+            }
+            let cv = self.vectors.get(c);
+            let diverse = kept
+                .iter()
+                .all(|&k| rank_score(&self.metric, cv, self.vectors.get(k)) < c_score);
+            if diverse {
+                kept.push(c);
+            }
+        }
+        kept
+    }
+
+    /// Shrink `row`'s neighbor list on `layer` back to `max_conn` links using
+    /// the selection heuristic.
+    fn prune_neighbors(&mut self, row: u32, layer: usize, max_conn: usize) {
+        let base = self.vectors.get(row);
+        let mut scored: Vec<(u32, f32)> = self.links[row as usize][layer]
+            .iter()
+            .map(|&nb| (nb, rank_score(&self.metric, base, self.vectors.get(nb))))
+            .collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        self.links[row as usize][layer] = self.select_neighbors(&scored, max_conn);
+    }
+
+    /// Store `vector` in a free (or new) row and register it under `id`.
+    fn alloc_row(&mut self, id: &str, vector: &[f32], links: Vec<Vec<u32>>) -> Result<u32> {
+        let row = match self.free.pop() {
+            Some(r) => {
+                self.vectors.set(r, vector);
+                r
+            }
+            None => {
+                let r = self.vectors.push(vector)?;
+                self.links.push(Vec::new());
+                r
+            }
+        };
+        if let Some(q) = &mut self.quantized {
+            q.set(row, vector);
+        }
+        self.ids.insert(row, id);
+        self.links[row as usize] = links;
+        Ok(row)
+    }
+
+    fn live_rows(&self) -> impl Iterator<Item = u32> + '_ {
+        self.ids.rows()
+    }
+
+    fn level(&self, row: u32) -> usize {
+        self.links[row as usize].len().saturating_sub(1)
+    }
+
+    fn layer_neighbors(&self, row: u32, layer: usize) -> &[u32] {
+        self.links[row as usize]
+            .get(layer)
+            .map(|l| l.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn max_connections(&self, layer: usize) -> usize {
+        if layer == 0 { self.m0 } else { self.m }
+    }
+
+    /// Draw a level from the exponential distribution floor(-ln(U) · mL).
+    fn random_level(&self) -> usize {
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+        (-u.ln() * self.level_mult).floor() as usize
     }
 
     /// Number of indexed vectors.
     pub fn len(&self) -> usize {
-        self.nodes.len()
+        self.ids.len()
+    }
+
+    /// Whether `id` is present in the graph.
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Stored vector for `id`, read straight from the vector store.
+    pub fn vector(&self, id: &str) -> Option<&[f32]> {
+        self.ids.row(id).map(|r| self.vectors.get(r))
+    }
+
+    /// Row `id` is stored at.
+    pub fn row(&self, id: &str) -> Option<u32> {
+        self.ids.row(id)
+    }
+
+    /// Id stored at `row`, or `None` for free or out-of-range rows.
+    pub fn id_at(&self, row: u32) -> Option<&str> {
+        self.ids.id(row)
+    }
+
+    /// Vector stored at `row`, or `None` for free or out-of-range rows.
+    pub fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        self.ids.id(row).map(|_| self.vectors.get(row))
+    }
+
+    /// Approximate heap bytes held by vectors, ids and links.
+    pub fn heap_bytes(&self) -> usize {
+        let links: usize = self
+            .links
+            .iter()
+            .map(|layers| {
+                layers.capacity() * std::mem::size_of::<Vec<u32>>()
+                    + layers.iter().map(|l| l.capacity() * 4).sum::<usize>()
+            })
+            .sum();
+        let quantized = self.quantized.as_ref().map_or(0, |q| q.heap_bytes());
+        self.vectors.heap_bytes() + quantized + self.ids.heap_bytes() + links + self.free.heap_bytes()
+    }
+
+    /// Keep a quantized copy of every vector for searches to score (or
+    /// drop it with [`Quantization::None`]). The copy is rebuilt from the
+    /// full vectors, with int8 ranges fitted to the current data. Indexes
+    /// read from snapshots or `.vbi` files come back unquantized, so this
+    /// is how their quantization is restored.
+    pub fn set_quantization(&mut self, quantization: Quantization, rescore: bool) {
+        let rows: Vec<u32> = self.live_rows().collect();
+        let mut quantized = QuantizedVectors::new(quantization, self.dim);
+        if let Some(q) = &mut quantized {
+            q.fit(rows.iter().map(|&r| self.vectors.get(r)));
+            for &r in &rows {
+                q.set(r, self.vectors.get(r));
+            }
+        }
+        self.quantized = quantized;
+        self.rescore = rescore;
+    }
+
+    /// Flush the vector store to disk (no-op when held in memory).
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the flush fails.
+    pub fn flush(&self) -> Result<()> {
+        self.vectors.flush()
+    }
+
+    /// Dimensionality of indexed vectors.
+    pub fn dim(&self) -> usize {
+        self.dim
     }
 
     pub fn is_empty(&self) -> bool {
-        self.nodes.is_empty()
+        self.ids.len() == 0
+    }
+
+    /// Number of layers above layer 0 in the current graph.
+    pub fn max_level(&self) -> usize {
+        self.max_level
+    }
+
+    /// Metric the graph was built for.
+    pub fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    /// Build/search parameters this index was created with.
+    pub fn params(&self) -> HnswParams {
+        HnswParams {
+            m: self.m,
+            ef_construction: self.ef_construction,
+            ef_search: self.ef_search,
+            brute_threshold: self.brute_threshold,
+            quantization: self.quantized.as_ref().map_or(Quantization::None, |q| q.kind()),
+            rescore: self.rescore,
+        }
+    }
+
+    /// Id of the current top-layer entry point.
+    pub fn entry_point(&self) -> Option<&str> {
+        self.entry.and_then(|r| self.ids.id(r))
+    }
+}
+
+impl AnnIndex for HnswIndex {
+    fn kind(&self) -> &'static str {
+        "hnsw"
+    }
+
+    fn insert(&mut self, id: String, vector: Vec<f32>) -> Result<u32> {
+        HnswIndex::insert(self, id, vector)
+    }
+
+    fn remove(&mut self, id: &str) {
+        HnswIndex::remove(self, id)
+    }
+
+    fn search_rows(&self, query: &[f32], top_k: usize, breadth: Option<usize>) -> Vec<(u32, f32)> {
+        HnswIndex::search_rows(self, query, top_k, breadth.unwrap_or(self.ef_search))
+    }
+
+    fn search_rows_filtered(&self, query: &[f32], top_k: usize, accept: &dyn Fn(u32) -> bool) -> Vec<(u32, f32)> {
+        HnswIndex::search_rows_filtered(self, query, top_k, self.ef_search, accept)
+    }
+
+    fn search_rows_among(&self, query: &[f32], top_k: usize, rows: &mut dyn Iterator<Item = u32>) -> Vec<(u32, f32)> {
+        HnswIndex::search_rows_among(self, query, top_k, rows)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        index::encode(self)
+    }
+
+    fn row(&self, id: &str) -> Option<u32> {
+        self.ids.row(id)
+    }
+
+    fn id_at(&self, row: u32) -> Option<&str> {
+        self.ids.id(row)
+    }
+
+    fn vector_at(&self, row: u32) -> Option<&[f32]> {
+        HnswIndex::vector_at(self, row)
+    }
+
+    fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn metric(&self) -> &Metric {
+        &self.metric
+    }
+
+    fn heap_bytes(&self) -> usize {
+        HnswIndex::heap_bytes(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn insert_bulk(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<Vec<u32>> {
+        HnswIndex::insert_bulk(self, items)
+    }
+
+    /// Plans the new node's level and neighbors, see
+    /// [`HnswIndex::plan_insert`].
+    fn plan_insert(&self, id: &str, vector: &[f32]) -> Option<IndexPlan> {
+        Some(Box::new(HnswIndex::plan_insert(self, id, vector)))
+    }
+
+    fn commit_insert(&mut self, id: String, vector: Vec<f32>, plan: Option<IndexPlan>) -> Result<u32> {
+        match plan.and_then(|p| p.downcast::<InsertPlan>().ok()) {
+            Some(plan) => HnswIndex::commit_insert(self, id, vector, *plan),
+            None => HnswIndex::insert(self, id, vector),
+        }
+    }
+
+    /// Finds the nodes linking to `id`, see [`HnswIndex::plan_remove`].
+    fn plan_remove(&self, id: &str) -> Option<IndexPlan> {
+        HnswIndex::plan_remove(self, id).map(|p| Box::new(p) as IndexPlan)
+    }
+
+    fn commit_remove(&mut self, id: &str, plan: Option<IndexPlan>) {
+        match plan.and_then(|p| p.downcast::<RemovePlan>().ok()) {
+            Some(plan) => HnswIndex::commit_remove(self, Some(*plan)),
+            None => HnswIndex::remove(self, id),
+        }
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.vectors.flush()
+    }
+
+    fn supports_quantization(&self) -> bool {
+        true
+    }
+
+    fn set_quantization(&mut self, quantization: Quantization, rescore: bool) {
+        HnswIndex::set_quantization(self, quantization, rescore)
+    }
+
+    fn reserve(&mut self, additional: usize) -> Result<()> {
+        self.vectors.reserve(additional)
+    }
+
+    fn release_free_rows(&mut self) {
+        self.free.release();
+    }
+}
+
+/// Registry entry for `index_type = "hnsw"`. HNSW predates `index_params`
+/// and reads the `hnsw_m` / `ef_*` fields of the config instead.
+pub const INDEX_TYPE: IndexType = IndexType {
+    name: "hnsw",
+    build: build_index,
+    load: index::decode::<HnswIndex>,
+};
+
+fn build_index(config: &VecBaseConfig, metric: Metric, vectors: VectorStore) -> Box<dyn AnnIndex> {
+    Box::new(HnswIndex::with_store(config.max_elements, metric, config.hnsw_params(), vectors))
+}
+
+// ── Id Table ──────────────────────────────────────────────────────────────────
+
+/// Two-way map between external ids and rows. Each id is allocated once and
+/// shared by both directions. Serialized as the row → id list only.
+#[derive(Default)]
+pub(crate) struct IdTable {
+    /// Row → id (`None` = free row awaiting reuse)
+    ids: Vec<Option<Arc<str>>>,
+    /// Id → row
+    rows: HashMap<Arc<str>, u32>,
+}
+
+impl IdTable {
+    pub(crate) fn with_free_rows(n: usize) -> Self {
+        Self {
+            ids: vec![None; n],
+            rows: HashMap::new(),
+        }
+    }
+
+    /// Register `id` at `row`, growing the table if needed.
+    pub(crate) fn insert(&mut self, row: u32, id: &str) {
+        let id: Arc<str> = Arc::from(id);
+        if self.ids.len() <= row as usize {
+            self.ids.resize(row as usize + 1, None);
+        }
+        self.ids[row as usize] = Some(id.clone());
+        self.rows.insert(id, row);
+    }
+
+    /// Free `row`, returning the id that was stored there.
+    pub(crate) fn remove_row(&mut self, row: u32) -> Option<Arc<str>> {
+        let id = self.ids.get_mut(row as usize)?.take()?;
+        self.rows.remove(&id);
+        Some(id)
+    }
+
+    pub(crate) fn row(&self, id: &str) -> Option<u32> {
+        self.rows.get(id).copied()
+    }
+
+    pub(crate) fn id(&self, row: u32) -> Option<&str> {
+        self.ids.get(row as usize)?.as_deref()
+    }
+
+    pub(crate) fn contains(&self, id: &str) -> bool {
+        self.rows.contains_key(id)
+    }
+
+    /// Live rows, in no particular order.
+    pub(crate) fn rows(&self) -> impl Iterator<Item = u32> + '_ {
+        self.rows.values().copied()
+    }
+
+    /// Number of live ids.
+    pub(crate) fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Number of rows, live or free.
+    pub(crate) fn capacity(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        // Arc header (two counters) + bytes per id; hashbrown adds a control byte per slot.
+        let arc_header = 2 * std::mem::size_of::<usize>();
+        let strings: usize = self.rows.keys().map(|id| arc_header + id.len()).sum();
+        let entry = std::mem::size_of::<(Arc<str>, u32)>() + 1;
+        strings
+            + self.ids.capacity() * std::mem::size_of::<Option<Arc<str>>>()
+            + self.rows.capacity() * entry
+    }
+}
+
+impl Serialize for IdTable {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.collect_seq(self.ids.iter().map(|id| id.as_deref()))
+    }
+}
+
+impl<'de> Deserialize<'de> for IdTable {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let ids = Vec::<Option<String>>::deserialize(d)?;
+        let mut table = IdTable::with_free_rows(ids.len());
+        for (row, id) in ids.into_iter().enumerate() {
+            if let Some(id) = id {
+                table.insert(row as u32, &id);
+            }
+        }
+        Ok(table)
     }
 }
 
@@ -204,7 +1121,7 @@ impl HnswIndex {
 pub struct BatchInsert {
     pub id: String,
     pub vector: Vec<f32>,
-    pub metadata: Option<String>,
+    pub metadata: Option<crate::metadata::Metadata>,
 }
 
 /// Result of a batch operation.
@@ -216,7 +1133,7 @@ pub struct BatchResult {
 
 /// Process a batch of inserts against a VecBase instance.
 /// Returns how many succeeded and which failed with reasons.
-pub fn batch_insert(db: &mut crate::VecBase, items: Vec<BatchInsert>) -> BatchResult {
+pub fn batch_insert(db: &crate::VecBase, items: Vec<BatchInsert>) -> BatchResult {
     let mut inserted = 0usize;
     let mut failed = Vec::new();
 
@@ -230,6 +1147,38 @@ pub fn batch_insert(db: &mut crate::VecBase, items: Vec<BatchInsert>) -> BatchRe
     BatchResult { inserted, failed }
 }
 
+/// Top-k results for every query in `queries`, searched in parallel on the
+/// global rayon pool (one thread per core unless `RAYON_NUM_THREADS` says
+/// otherwise). `results[i]` belongs to `queries[i]`; a query of the wrong
+/// dimension gets an empty list, as with [`VecBase::search`](crate::VecBase::search).
+pub fn batch_search(db: &crate::VecBase, queries: &[Vec<f32>], top_k: usize) -> Vec<Vec<crate::SearchResult>> {
+    queries.par_iter().map(|q| db.search(q, top_k)).collect()
+}
+
+/// [`batch_search`] on a caller-owned pool, e.g. one from [`search_pool`]
+/// sized to leave cores free for other work.
+pub fn batch_search_in(
+    pool: &ThreadPool,
+    db: &crate::VecBase,
+    queries: &[Vec<f32>],
+    top_k: usize,
+) -> Vec<Vec<crate::SearchResult>> {
+    pool.install(|| batch_search(db, queries, top_k))
+}
+
+/// A thread pool for [`batch_search_in`] with `threads` workers
+/// (0 = one per core).
+///
+/// # Errors
+/// Returns `VecBaseError::ConfigError` if the threads cannot be spawned.
+pub fn search_pool(threads: usize) -> Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("vecbase-search-{}", i))
+        .build()
+        .map_err(|e| VecBaseError::ConfigError(format!("cannot start search pool: {}", e)))
+}
+
 // ── Tests ─────────────────────────────────────────────────────────────────────
 
 #[cfg(test)]
@@ -238,43 +1187,482 @@ mod tests {
 
     #[test]
     fn test_hnsw_insert_search() {
-        let mut idx = HnswIndex::new(3, 1000);
-        idx.insert("a".into(), vec![1.0, 0.0, 0.0]);
-        idx.insert("b".into(), vec![0.0, 1.0, 0.0]);
-        idx.insert("c".into(), vec![0.0, 0.0, 1.0]);
+        let mut idx = HnswIndex::new(3, 1000, Metric::Cosine);
+        idx.insert("a".into(), vec![1.0, 0.0, 0.0]).unwrap();
+        idx.insert("b".into(), vec![0.0, 1.0, 0.0]).unwrap();
+        idx.insert("c".into(), vec![0.0, 0.0, 1.0]).unwrap();
 
-        let results = idx.search(&[1.0, 0.0, 0.0], 2, &Metric::Cosine);
+        let results = idx.search(&[1.0, 0.0, 0.0], 2);
         assert!(!results.is_empty());
         assert_eq!(results[0].0, "a");
     }
 
     #[test]
     fn test_hnsw_remove() {
-        let mut idx = HnswIndex::new(2, 100);
-        idx.insert("x".into(), vec![1.0, 0.0]);
+        let mut idx = HnswIndex::new(2, 100, Metric::Cosine);
+        idx.insert("x".into(), vec![1.0, 0.0]).unwrap();
         idx.remove("x");
         assert_eq!(idx.len(), 0);
     }
 
     #[test]
     fn test_hnsw_empty_search() {
-        let idx = HnswIndex::new(4, 100);
-        let results = idx.search(&[1.0, 0.0, 0.0, 0.0], 5, &Metric::Cosine);
+        let idx = HnswIndex::new(4, 100, Metric::Cosine);
+        let results = idx.search(&[1.0, 0.0, 0.0, 0.0], 5);
         assert!(results.is_empty());
     }
 
+    fn random_unit_vectors(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|_| {
+                let v: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+                crate::embedding::normalize(&v)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hnsw_builds_upper_layers() {
+        let mut idx = HnswIndex::new(8, 10_000, Metric::Cosine);
+        for (i, v) in random_unit_vectors(2_000, 8, 1).into_iter().enumerate() {
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+        assert!(idx.max_level() > 0, "2000 nodes should produce at least one upper layer");
+
+        let entry = idx.entry.unwrap();
+        assert_eq!(idx.level(entry), idx.max_level());
+        assert!(idx.live_rows().all(|r| idx.level(r) <= idx.max_level()));
+    }
+
+    #[test]
+    fn test_hnsw_graph_recall() {
+        let dim = 16;
+        let mut idx = HnswIndex::new(dim, 10_000, Metric::Cosine);
+        for (i, v) in random_unit_vectors(1_000, dim, 2).into_iter().enumerate() {
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+
+        let queries = random_unit_vectors(20, dim, 3);
+        let mut hits = 0;
+        for q in &queries {
+            let exact: HashSet<u32> = idx
+                .brute_search(&idx.exact(q), 10)
+                .into_iter()
+                .map(|(r, _)| r)
+                .collect();
+            hits += idx
+                .graph_search(&idx.exact(q), 10, 64)
+                .iter()
+                .filter(|(r, _)| exact.contains(r))
+                .count();
+        }
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        assert!(recall >= 0.9, "graph recall@10 too low: {}", recall);
+    }
+
+    #[test]
+    fn test_hnsw_remove_entry_point() {
+        let mut idx = HnswIndex::new(4, 1_000, Metric::Cosine);
+        for (i, v) in random_unit_vectors(600, 4, 4).into_iter().enumerate() {
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+        let entry = idx.entry.unwrap();
+        let entry_id = idx.entry_point().unwrap().to_string();
+        idx.remove(&entry_id);
+
+        let new_entry = idx.entry.unwrap();
+        assert_ne!(new_entry, entry);
+        assert_eq!(idx.level(new_entry), idx.max_level());
+        assert!(idx.links.iter().flatten().flatten().all(|&r| r != entry));
+        assert_eq!(idx.search(&[1.0, 0.0, 0.0, 0.0], 5).len(), 5);
+    }
+
+    #[test]
+    fn test_hnsw_stale_plans_are_replanned() {
+        let mut idx = HnswIndex::new(4, 1_000, Metric::Cosine);
+        let data = random_unit_vectors(600, 4, 10);
+        for (i, v) in data.iter().enumerate().take(599) {
+            idx.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+
+        // Planned against the graph before "v3" went away.
+        let insert = idx.plan_insert("late", &data[599]);
+        let remove = idx.plan_remove("v5");
+        idx.remove("v3");
+        let row = idx.commit_insert("late".into(), data[599].clone(), insert).unwrap();
+        idx.commit_remove(remove);
+
+        assert_eq!(idx.len(), 598);
+        let freed = [idx.row("v3"), idx.row("v5")];
+        assert_eq!(freed, [None, None]);
+        // No link may point at a freed row.
+        let live: HashSet<u32> = idx.live_rows().collect();
+        assert!(idx.links.iter().flatten().flatten().all(|r| live.contains(r)));
+        assert_eq!(idx.search(&data[599], 1)[0].0, "late");
+        assert_eq!(idx.id_at(row), Some("late"));
+    }
+
+    #[test]
+    fn test_hnsw_reinsert_replaces() {
+        let mut idx = HnswIndex::new(2, 100, Metric::Cosine);
+        idx.insert("a".into(), vec![1.0, 0.0]).unwrap();
+        idx.insert("a".into(), vec![0.0, 1.0]).unwrap();
+        assert_eq!(idx.len(), 1);
+        let results = idx.search(&[0.0, 1.0], 1);
+        assert!((results[0].1 - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_hnsw_euclidean_graph_recall_matches_brute_force() {
+        // Graph path (N > brute_threshold) must find at least 95% of the
+        // exact Euclidean top-10 on unnormalized data.
+        const TOLERANCE: f32 = 0.05;
+        let dim = 16;
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut idx = HnswIndex::new(dim, 10_000, Metric::Euclidean);
+        for i in 0..1_000 {
+            let scale = rng.gen_range(0.5..20.0);
+            let v: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0) * scale).collect();
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+        assert!(idx.len() > idx.params().brute_threshold);
+
+        let mut hits = 0;
+        let n_queries = 20;
+        for _ in 0..n_queries {
+            let q: Vec<f32> = (0..dim).map(|_| rng.gen_range(-10.0..10.0)).collect();
+            let exact: HashSet<String> = idx
+                .brute_search(&idx.exact(&q), 10)
+                .into_iter()
+                .filter_map(|(r, _)| idx.id_at(r).map(String::from))
+                .collect();
+            let approx = idx.search(&q, 10);
+            assert_eq!(approx.len(), 10);
+            hits += approx.iter().filter(|(id, _)| exact.contains(id)).count();
+        }
+        let recall = hits as f32 / (n_queries * 10) as f32;
+        assert!(recall >= 1.0 - TOLERANCE, "euclidean recall@10 {} below {}", recall, 1.0 - TOLERANCE);
+    }
+
+    /// `per_cluster` points scattered tightly around each of `centers`.
+    fn clustered_vectors(centers: &[Vec<f32>], per_cluster: usize, spread: f32, rng: &mut StdRng) -> Vec<Vec<f32>> {
+        let mut out = Vec::with_capacity(centers.len() * per_cluster);
+        for _ in 0..per_cluster {
+            for c in centers {
+                out.push(c.iter().map(|x| x + rng.gen_range(-spread..spread)).collect());
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_hnsw_clustered_recall() {
+        // Small M and ef make the graph sensitive to neighbor selection.
+        let dim = 32;
+        let mut rng = StdRng::seed_from_u64(8);
+        let centers: Vec<Vec<f32>> = (0..100)
+            .map(|_| (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect();
+        let data = clustered_vectors(&centers, 20, 0.05, &mut rng);
+        let mut idx = HnswIndex::with_params(
+            dim,
+            10_000,
+            Metric::Euclidean,
+            HnswParams { m: 4, ef_construction: 32, ..HnswParams::default() },
+        );
+        for (i, v) in data.iter().enumerate() {
+            idx.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+
+        let queries = clustered_vectors(&centers, 1, 0.05, &mut rng);
+        let mut hits = 0;
+        for q in &queries {
+            let exact: HashSet<u32> = idx.brute_search(&idx.exact(q), 10).into_iter().map(|(r, _)| r).collect();
+            hits += idx
+                .graph_search(&idx.exact(q), 10, 10)
+                .iter()
+                .filter(|(r, _)| exact.contains(r))
+                .count();
+        }
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        // Capping back-links at M (no pruning) scored ≈0.67 on this data.
+        assert!(recall >= 0.8, "clustered recall@10 too low: {}", recall);
+    }
+
+    #[test]
+    fn test_insert_bulk_recall_matches_sequential() {
+        let dim = 16;
+        let data = random_unit_vectors(3_000, dim, 14);
+        let queries = random_unit_vectors(50, dim, 15);
+        let items: Vec<(String, Vec<f32>)> = data
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (format!("v{}", i), v.clone()))
+            .collect();
+
+        let mut sequential = HnswIndex::new(dim, 10_000, Metric::Cosine);
+        for (id, v) in items.clone() {
+            sequential.insert(id, v).unwrap();
+        }
+        let mut bulk = HnswIndex::new(dim, 10_000, Metric::Cosine);
+        let rows = bulk.insert_bulk(items).unwrap();
+        assert_eq!(bulk.len(), data.len());
+        assert!(rows.iter().enumerate().all(|(i, &r)| bulk.id_at(r) == Some(format!("v{}", i).as_str())));
+
+        let recall = |idx: &HnswIndex| {
+            let mut hits = 0;
+            for q in &queries {
+                let exact: HashSet<u32> = idx.brute_search(&idx.exact(q), 10).into_iter().map(|(r, _)| r).collect();
+                hits += idx.graph_search(&idx.exact(q), 10, 32).iter().filter(|(r, _)| exact.contains(r)).count();
+            }
+            hits as f32 / (queries.len() * 10) as f32
+        };
+        let (seq, par) = (recall(&sequential), recall(&bulk));
+        assert!(par >= seq - 0.03, "bulk recall@10 {} vs sequential {}", par, seq);
+    }
+
+    #[test]
+    fn test_insert_bulk_replaces_existing_ids() {
+        let dim = 8;
+        let data = random_unit_vectors(2_000, dim, 16);
+        let mut idx = HnswIndex::new(dim, 10_000, Metric::Cosine);
+        let first: Vec<(String, Vec<f32>)> = (0..1_500).map(|i| (format!("v{}", i), data[i].clone())).collect();
+        idx.insert_bulk(first).unwrap();
+
+        // v0 exists before the call; v1600 is inserted earlier in the same call.
+        let mut second: Vec<(String, Vec<f32>)> = (1_500..2_000).map(|i| (format!("v{}", i), data[i].clone())).collect();
+        second.push(("v0".into(), data[1_999].clone()));
+        second.push(("v1600".into(), data[1_998].clone()));
+        let rows = idx.insert_bulk(second).unwrap();
+
+        assert_eq!(idx.len(), 2_000);
+        assert_eq!(idx.vector("v0"), Some(data[1_999].as_slice()));
+        assert_eq!(idx.vector("v1600"), Some(data[1_998].as_slice()));
+        assert_eq!(idx.id_at(rows[rows.len() - 1]), Some("v1600"));
+        let live: HashSet<u32> = idx.live_rows().collect();
+        assert!(idx.links.iter().flatten().flatten().all(|r| live.contains(r)));
+    }
+
+    #[test]
+    fn test_hnsw_neighbor_lists_bounded() {
+        let mut idx = HnswIndex::with_params(
+            8,
+            10_000,
+            Metric::Cosine,
+            HnswParams { m: 4, ..HnswParams::default() },
+        );
+        for (i, v) in random_unit_vectors(1_000, 8, 9).into_iter().enumerate() {
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+        for layers in &idx.links {
+            for (layer, links) in layers.iter().enumerate() {
+                assert!(links.len() <= idx.max_connections(layer));
+            }
+        }
+    }
+
+    #[test]
+    fn test_euclidean_scores_are_public_distances() {
+        let dim = 8;
+        let mut rng = StdRng::seed_from_u64(17);
+        let mut idx = HnswIndex::with_params(
+            dim,
+            1_000,
+            Metric::Euclidean,
+            HnswParams { brute_threshold: 100, ..HnswParams::default() },
+        );
+        for i in 0..300 {
+            let v: Vec<f32> = (0..dim).map(|_| rng.gen_range(-5.0..5.0)).collect();
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+        let q: Vec<f32> = (0..dim).map(|_| rng.gen_range(-5.0..5.0)).collect();
+        let exact = |results: Vec<(u32, f32)>| {
+            assert_eq!(results.len(), 5);
+            for (r, s) in results {
+                let want = crate::embedding::score(&Metric::Euclidean, &q, idx.vector_at(r).unwrap());
+                assert_eq!(s, want);
+            }
+        };
+        exact(idx.search_rows(&q, 5, 64));
+        exact(idx.search_rows_filtered(&q, 5, 64, |r| r % 2 == 0));
+        exact(idx.search_rows_filtered(&q, 5, 64, |r| r < 5));
+        exact(idx.search_rows_among(&q, 5, 0..50));
+        // The graph ranks on squared distance but returns the same top-k as
+        // ranking on distance.
+        let by_distance = idx.search_rows_among(&q, 5, idx.live_rows());
+        assert_eq!(idx.search_rows(&q, 5, 300), by_distance);
+    }
+
+    #[test]
+    fn test_quantized_search_rescores_with_full_vectors() {
+        let dim = 32;
+        let data = random_unit_vectors(2_000, dim, 21);
+        let queries = random_unit_vectors(30, dim, 22);
+        let mut idx = HnswIndex::with_params(
+            dim,
+            10_000,
+            Metric::Cosine,
+            HnswParams { quantization: Quantization::Int8, rescore: false, ..HnswParams::default() },
+        );
+        for (i, v) in data.iter().enumerate() {
+            idx.insert(format!("v{}", i), v.clone()).unwrap();
+        }
+        let with_codes = idx.heap_bytes();
+
+        let recall = |idx: &HnswIndex| {
+            let mut hits = 0;
+            for q in &queries {
+                let exact: HashSet<u32> = idx.brute_search(&idx.exact(q), 10).into_iter().map(|(r, _)| r).collect();
+                hits += idx.search_rows(q, 10, 64).iter().filter(|(r, _)| exact.contains(r)).count();
+            }
+            hits as f32 / (queries.len() * 10) as f32
+        };
+        let quantized = recall(&idx);
+        assert!(quantized >= 0.85, "int8 recall@10 {}", quantized);
+
+        idx.set_quantization(Quantization::Int8, true);
+        assert_eq!(idx.params().quantization, Quantization::Int8);
+        let rescored = recall(&idx);
+        assert!(rescored >= quantized, "rescored recall@10 {} vs {}", rescored, quantized);
+        // Rescored hits carry exact scores.
+        for (r, s) in idx.search_rows(&queries[0], 10, 64) {
+            assert_eq!(s, crate::embedding::score(&Metric::Cosine, &queries[0], idx.vector_at(r).unwrap()));
+        }
+
+        idx.set_quantization(Quantization::None, true);
+        assert_eq!(idx.params().quantization, Quantization::None);
+        assert!(with_codes - idx.heap_bytes() >= data.len() * dim);
+    }
+
+    #[test]
+    fn test_select_neighbors_prefers_diverse_links() {
+        let mut idx = HnswIndex::new(2, 100, Metric::Euclidean);
+        idx.insert("near".into(), vec![1.0, 0.0]).unwrap();
+        idx.insert("behind_near".into(), vec![1.1, 0.0]).unwrap();
+        idx.insert("other_side".into(), vec![-1.2, 0.0]).unwrap();
+
+        // Seen from the origin, "behind_near" is closer to "near" than to the
+        // origin, so the heuristic drops it in favour of "other_side".
+        let base = [0.0, 0.0];
+        let mut cands: Vec<(u32, f32)> = ["near", "behind_near", "other_side"]
+            .iter()
+            .map(|id| (idx.row(id).unwrap(), rank_score(&Metric::Euclidean, &base, idx.vector(id).unwrap())))
+            .collect();
+        cands.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let kept = idx.select_neighbors(&cands, 2);
+        assert_eq!(kept, vec![idx.row("near").unwrap(), idx.row("other_side").unwrap()]);
+    }
+
+    #[test]
+    fn test_hnsw_params_clamped() {
+        let idx = HnswIndex::with_params(
+            4,
+            100,
+            Metric::Cosine,
+            HnswParams { m: 1, ef_construction: 0, ef_search: 0, brute_threshold: 10, ..HnswParams::default() },
+        );
+        let p = idx.params();
+        assert_eq!(p.m, 2);
+        assert_eq!(p.ef_construction, 2);
+        assert_eq!(p.ef_search, 1);
+        assert_eq!(p.brute_threshold, 10);
+    }
+
+    #[test]
+    fn test_hnsw_ef_override() {
+        let dim = 16;
+        let mut idx = HnswIndex::with_params(
+            dim,
+            10_000,
+            Metric::Cosine,
+            HnswParams { brute_threshold: 0, ..HnswParams::default() },
+        );
+        for (i, v) in random_unit_vectors(300, dim, 5).into_iter().enumerate() {
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+
+        // With ef ≥ N the beam covers the whole connected graph → exact.
+        let q = &random_unit_vectors(1, dim, 6)[0];
+        let exact = idx.brute_search(&idx.exact(q), 10);
+        let wide = idx.graph_search(&idx.exact(q), 10, 300);
+        assert_eq!(
+            exact.iter().map(|(r, _)| r).collect::<Vec<_>>(),
+            wide.iter().map(|(r, _)| r).collect::<Vec<_>>()
+        );
+        assert_eq!(idx.search_with_ef(q, 10, 1).len(), 10);
+    }
+
+    #[test]
+    fn test_hnsw_filtered_search_fills_top_k() {
+        let dim = 16;
+        let mut idx = HnswIndex::with_params(
+            dim,
+            10_000,
+            Metric::Cosine,
+            HnswParams { brute_threshold: 0, ..HnswParams::default() },
+        );
+        for (i, v) in random_unit_vectors(2_000, dim, 8).into_iter().enumerate() {
+            idx.insert(format!("v{}", i), v).unwrap();
+        }
+        // 2% of rows pass; a post-filter over the top-64 would keep ~1.
+        let accept = |r: u32| r % 50 == 7;
+
+        let mut hits = 0;
+        let queries = random_unit_vectors(20, dim, 9);
+        for q in &queries {
+            let found = idx.search_rows_filtered(q, 10, 64, accept);
+            assert_eq!(found.len(), 10);
+            assert!(found.iter().all(|&(r, _)| accept(r)));
+            let exact: HashSet<u32> =
+                idx.brute_search_filtered(&idx.exact(q), 10, accept).into_iter().map(|(r, _)| r).collect();
+            hits += found.iter().filter(|(r, _)| exact.contains(r)).count();
+        }
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        assert!(recall >= 0.9, "filtered recall {} < 0.9", recall);
+
+        // Fewer matches than top_k: return all of them.
+        assert_eq!(idx.search_rows_filtered(&queries[0], 10, 64, |r| r < 3).len(), 3);
+    }
+
+    #[test]
+    fn test_batch_search_keeps_input_order() {
+        use crate::{VecBase, VecBaseConfig};
+
+        let dim = 16;
+        let db = VecBase::new(VecBaseConfig { dim, brute_threshold: 50, ..Default::default() });
+        for (i, v) in random_unit_vectors(800, dim, 12).into_iter().enumerate() {
+            db.insert(format!("v{}", i), v, None).unwrap();
+        }
+        let mut queries = random_unit_vectors(100, dim, 13);
+        queries[42] = vec![1.0; 3]; // wrong dim
+
+        let ids = |results: &[crate::SearchResult]| -> Vec<String> {
+            results.iter().map(|r| r.id.clone()).collect()
+        };
+        let pool = search_pool(3).unwrap();
+        assert_eq!(pool.current_num_threads(), 3);
+        let parallel = batch_search_in(&pool, &db, &queries, 5);
+        assert_eq!(parallel.len(), queries.len());
+        for (q, found) in queries.iter().zip(&parallel) {
+            assert_eq!(ids(found), ids(&db.search(q, 5)));
+        }
+        assert!(parallel[42].is_empty());
+        assert_eq!(batch_search(&db, &queries[..10], 5).len(), 10);
+    }
+
     #[test]
     fn test_batch_insert() {
         use crate::{VecBase, VecBaseConfig};
 
-        let mut db = VecBase::new(VecBaseConfig { dim: 3, ..Default::default() });
+        let db = VecBase::new(VecBaseConfig { dim: 3, ..Default::default() });
         let items = vec![
             BatchInsert { id: "v1".into(), vector: vec![1.0, 0.0, 0.0], metadata: None },
             BatchInsert { id: "v2".into(), vector: vec![0.0, 1.0, 0.0], metadata: None },
             // Wrong dimension — should fail
             BatchInsert { id: "v3".into(), vector: vec![1.0, 2.0], metadata: None },
         ];
-        let result = batch_insert(&mut db, items);
+        let result = batch_insert(&db, items);
         assert_eq!(result.inserted, 2);
         assert_eq!(result.failed.len(), 1);
         assert_eq!(result.failed[0].0, "v3");
@@ -0,0 +1,419 @@
+// VecBase — processing/vbi.rs
+// `.vbi` index file: a compact, versioned dump of an HnswIndex graph.
+// Author: d65v <https://github.com/d65v>
+//
+// The file stores the graph only (ids, vectors, per-layer adjacency, entry
+// point and build parameters) — records and metadata live in the snapshot.
+// Live rows are renumbered to dense ordinals 0..N (free rows are dropped), so
+// adjacency is written as u32 ordinals and loads straight back into rows.
+//
+// Layout (all integers little-endian):
+//
+//   offset  size  field
+//   0       4     magic            b"VBI\0"
+//   4       4     version          u32
+//   8       1     metric           u8 (0 = cosine, 1 = euclidean, 2 = dot)
+//   9       4     dim              u32
+//   13      4     m                u32
+//   17      4     ef_construction  u32
+//   21      4     ef_search        u32
+//   25      8     brute_threshold  u64
+//   33      8     max_elements     u64
+//   41      8     node count       u64
+//   49      4     max_level        u32
+//   53      4     entry ordinal    u32 (u32::MAX = empty index)
+//   57      …     nodes, in ordinal order:
+//                   u32 id length, id bytes (UTF-8)
+//                   u8  level
+//                   dim × f32 vector
+//                   for each layer 0..=level: u32 count, count × u32 ordinals
+//   end-4   4     crc32            u32, CRC-32 of every preceding byte
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::{level_rng, HnswIndex, HnswParams, IdTable};
+use crate::embedding::Metric;
+use crate::vectors::{FreeRows, VectorStore};
+use crate::storage::{storage_err, tmp_path};
+use crate::{Result, VecBaseError};
+
+/// Magic bytes at the start of every `.vbi` file.
+pub const VBI_MAGIC: [u8; 4] = *b"VBI\0";
+
+/// Current `.vbi` format version.
+pub const VBI_VERSION: u32 = 1;
+
+const NO_ENTRY: u32 = u32::MAX;
+
+impl HnswIndex {
+    /// Write the graph to a `.vbi` file at `path`.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on I/O failure.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| storage_err("create index dir", e))?;
+        }
+
+        let tmp = tmp_path(path);
+        let file = File::create(&tmp).map_err(|e| storage_err("create index file", e))?;
+        let mut w = CrcWriter::new(BufWriter::new(file));
+        self.encode(&mut w).map_err(|e| storage_err("write index file", e))?;
+        w.finish_file()
+            .and_then(|f| f.sync_all())
+            .map_err(|e| storage_err("write index file", e))?;
+
+        fs::rename(&tmp, path).map_err(|e| storage_err("rename index file", e))?;
+        Ok(())
+    }
+
+    /// Restore a graph written by [`save`](Self::save). Nothing is
+    /// re-inserted: layers, adjacency and entry point come back verbatim.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the file is missing, corrupt,
+    /// or from an incompatible version.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| storage_err(&format!("open index file {}", path.display()), e))?;
+        let mut r = CrcReader::new(BufReader::new(file));
+        let index = Self::decode(&mut r)?;
+
+        let expected = r.hasher.clone().finalize();
+        let mut trailer = [0u8; 4];
+        r.inner
+            .read_exact(&mut trailer)
+            .map_err(|e| storage_err("read index checksum", e))?;
+        if u32::from_le_bytes(trailer) != expected {
+            return Err(VecBaseError::StorageError(
+                "index file checksum mismatch".to_string(),
+            ));
+        }
+        if r.inner.read(&mut [0u8; 1]).map_err(|e| storage_err("read index file", e))? != 0 {
+            return Err(VecBaseError::StorageError(
+                "trailing bytes after index checksum".to_string(),
+            ));
+        }
+        Ok(index)
+    }
+
+    fn encode<W: Write>(&self, w: &mut CrcWriter<W>) -> std::io::Result<()> {
+        // Live rows in row order, renumbered densely.
+        let order: Vec<u32> = (0..self.ids.capacity() as u32)
+            .filter(|&r| self.ids.id(r).is_some())
+            .collect();
+        let mut ordinal = vec![NO_ENTRY; self.ids.capacity()];
+        for (i, &r) in order.iter().enumerate() {
+            ordinal[r as usize] = i as u32;
+        }
+
+        w.put(&VBI_MAGIC)?;
+        w.put(&VBI_VERSION.to_le_bytes())?;
+        w.put(&[metric_tag(&self.metric)])?;
+        w.put(&(self.dim as u32).to_le_bytes())?;
+        w.put(&(self.m as u32).to_le_bytes())?;
+        w.put(&(self.ef_construction as u32).to_le_bytes())?;
+        w.put(&(self.ef_search as u32).to_le_bytes())?;
+        w.put(&(self.brute_threshold as u64).to_le_bytes())?;
+        w.put(&(self.max_elements as u64).to_le_bytes())?;
+        w.put(&(order.len() as u64).to_le_bytes())?;
+        w.put(&(self.max_level as u32).to_le_bytes())?;
+        let entry = self.entry.map(|e| ordinal[e as usize]).unwrap_or(NO_ENTRY);
+        w.put(&entry.to_le_bytes())?;
+
+        for &row in &order {
+            let id = self.ids.id(row).unwrap_or_default();
+            w.put(&(id.len() as u32).to_le_bytes())?;
+            w.put(id.as_bytes())?;
+            w.put(&[self.level(row) as u8])?;
+            for x in self.vectors.get(row) {
+                w.put(&x.to_le_bytes())?;
+            }
+            for layer in &self.links[row as usize] {
+                w.put(&(layer.len() as u32).to_le_bytes())?;
+                for &nb in layer {
+                    w.put(&ordinal[nb as usize].to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode<R: Read>(r: &mut CrcReader<R>) -> Result<Self> {
+        if r.array::<4>()? != VBI_MAGIC {
+            return Err(VecBaseError::StorageError(
+                "not a VecBase index file (bad magic)".to_string(),
+            ));
+        }
+        let version = r.u32()?;
+        if version != VBI_VERSION {
+            return Err(VecBaseError::StorageError(format!(
+                "unsupported index file version {} (expected {})",
+                version, VBI_VERSION
+            )));
+        }
+
+        let metric = metric_from_tag(r.array::<1>()?[0])?;
+        let dim = r.u32()? as usize;
+        let m = r.u32()? as usize;
+        let ef_construction = r.u32()? as usize;
+        let ef_search = r.u32()? as usize;
+        let brute_threshold = r.u64()? as usize;
+        let max_elements = r.u64()? as usize;
+        let count = r.u64()? as usize;
+        let max_level = r.u32()? as usize;
+        let entry = r.u32()?;
+
+        let mut vectors = VectorStore::memory(dim);
+        let mut ids = IdTable::default();
+        let mut links = Vec::with_capacity(count.min(1 << 20));
+        let mut vector = vec![0.0f32; dim];
+        for row in 0..count as u32 {
+            let id_len = r.u32()? as usize;
+            let id = String::from_utf8(r.bytes(id_len)?)
+                .map_err(|e| storage_err("decode index node id", e))?;
+            let level = r.array::<1>()?[0] as usize;
+            for x in vector.iter_mut() {
+                *x = f32::from_le_bytes(r.array::<4>()?);
+            }
+            vectors.push(&vector)?;
+
+            let mut layers = Vec::with_capacity(level + 1);
+            for _ in 0..=level {
+                let n = r.u32()? as usize;
+                let mut layer = Vec::with_capacity(n.min(1024));
+                for _ in 0..n {
+                    let nb = r.u32()?;
+                    if nb as usize >= count {
+                        return Err(VecBaseError::StorageError(format!(
+                            "index file references unknown node {}",
+                            nb
+                        )));
+                    }
+                    layer.push(nb);
+                }
+                layers.push(layer);
+            }
+            if ids.contains(&id) {
+                return Err(VecBaseError::StorageError(format!(
+                    "index file holds id {:?} twice",
+                    id
+                )));
+            }
+            ids.insert(row, &id);
+            links.push(layers);
+        }
+
+        let entry = match entry {
+            NO_ENTRY => None,
+            e if (e as usize) < count => Some(e),
+            e => {
+                return Err(VecBaseError::StorageError(format!(
+                    "index file entry point {} out of range",
+                    e
+                )))
+            }
+        };
+
+        Ok(Self {
+            dim,
+            max_elements,
+            metric,
+            vectors,
+            quantized: None,
+            rescore: HnswParams::default().rescore,
+            ids,
+            links,
+            free: FreeRows::default(),
+            m,
+            m0: m * 2,
+            ef_construction,
+            ef_search,
+            brute_threshold,
+            level_mult: 1.0 / (m.max(2) as f64).ln(),
+            entry,
+            max_level,
+            rng: level_rng(),
+            version: 0,
+        })
+    }
+}
+
+fn metric_tag(metric: &Metric) -> u8 {
+    match metric {
+        Metric::Cosine => 0,
+        Metric::Euclidean => 1,
+        Metric::DotProduct => 2,
+    }
+}
+
+fn metric_from_tag(tag: u8) -> Result<Metric> {
+    match tag {
+        0 => Ok(Metric::Cosine),
+        1 => Ok(Metric::Euclidean),
+        2 => Ok(Metric::DotProduct),
+        t => Err(VecBaseError::StorageError(format!("unknown metric tag {} in index file", t))),
+    }
+}
+
+// ── Checksummed I/O ───────────────────────────────────────────────────────────
+
+struct CrcWriter<W: Write> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> CrcWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, hasher: crc32fast::Hasher::new() }
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.hasher.update(bytes);
+        self.inner.write_all(bytes)
+    }
+
+    /// Append the CRC trailer and flush.
+    fn finish(mut self) -> std::io::Result<W> {
+        let crc = self.hasher.clone().finalize();
+        self.inner.write_all(&crc.to_le_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl CrcWriter<BufWriter<File>> {
+    fn finish_file(self) -> std::io::Result<File> {
+        self.finish()?.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+struct CrcReader<R: Read> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+}
+
+impl<R: Read> CrcReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, hasher: crc32fast::Hasher::new() }
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|e| storage_err("index file truncated", e))?;
+        self.hasher.update(&buf);
+        Ok(buf)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        (&mut self.inner)
+            .take(n as u64)
+            .read_to_end(&mut buf)
+            .map_err(|e| storage_err("read index file", e))?;
+        if buf.len() != n {
+            return Err(VecBaseError::StorageError("index file truncated".to_string()));
+        }
+        self.hasher.update(&buf);
+        Ok(buf)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array::<4>()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array::<8>()?))
+    }
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::HnswParams;
+    use crate::storage::tests::scratch_dir;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn build(n: usize, dim: usize, metric: Metric) -> HnswIndex {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut idx = HnswIndex::with_params(
+            dim,
+            10_000,
+            metric,
+            HnswParams { m: 6, ef_search: 32, brute_threshold: 50, ..HnswParams::default() },
+        );
+        for i in 0..n {
+            let v: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            idx.insert(format!("n{}", i), v).unwrap();
+        }
+        idx
+    }
+
+    #[test]
+    fn test_vbi_roundtrip_identical_graph() {
+        let path = scratch_dir("vbi-roundtrip").join("graph.vbi");
+        let mut idx = build(800, 8, Metric::Euclidean);
+        // Leave a free row behind so ordinals differ from rows.
+        idx.remove("n3");
+        idx.save(&path).unwrap();
+        let loaded = HnswIndex::load(&path).unwrap();
+
+        assert_eq!(loaded.params(), idx.params());
+        assert_eq!(loaded.metric(), idx.metric());
+        assert_eq!(loaded.entry_point(), idx.entry_point());
+        assert_eq!(loaded.max_level(), idx.max_level());
+        assert_eq!(loaded.len(), idx.len());
+        let adjacency = |index: &HnswIndex, id: &str| -> Vec<Vec<String>> {
+            index.links[index.row(id).unwrap() as usize]
+                .iter()
+                .map(|layer| layer.iter().map(|&nb| index.id_at(nb).unwrap().to_string()).collect())
+                .collect()
+        };
+        for id in idx.ids.rows().map(|r| idx.id_at(r).unwrap()) {
+            assert_eq!(loaded.vector(id), idx.vector(id));
+            assert_eq!(adjacency(&loaded, id), adjacency(&idx, id), "adjacency differs for {}", id);
+        }
+
+        let q = [0.3, -0.2, 0.9, 0.0, 0.1, -0.7, 0.5, 0.2];
+        assert_eq!(loaded.search(&q, 10), idx.search(&q, 10));
+    }
+
+    #[test]
+    fn test_vbi_empty_index() {
+        let path = scratch_dir("vbi-empty").join("graph.vbi");
+        HnswIndex::new(4, 10, Metric::Cosine).save(&path).unwrap();
+        let loaded = HnswIndex::load(&path).unwrap();
+        assert!(loaded.is_empty());
+        assert!(loaded.entry_point().is_none());
+    }
+
+    #[test]
+    fn test_vbi_detects_corruption() {
+        let path = scratch_dir("vbi-corrupt").join("graph.vbi");
+        build(100, 4, Metric::Cosine).save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let mut flipped = bytes.clone();
+        flipped[bytes.len() / 2] ^= 0x01;
+        fs::write(&path, &flipped).unwrap();
+        assert!(matches!(HnswIndex::load(&path), Err(VecBaseError::StorageError(_))));
+
+        fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        assert!(matches!(HnswIndex::load(&path), Err(VecBaseError::StorageError(_))));
+
+        let mut bad_version = bytes.clone();
+        bad_version[4..8].copy_from_slice(&(VBI_VERSION + 1).to_le_bytes());
+        fs::write(&path, &bad_version).unwrap();
+        let err = HnswIndex::load(&path).err().unwrap();
+        assert!(err.to_string().contains("version"));
+    }
+}
@@ -0,0 +1,638 @@
+// VecBase — quantize.rs
+// Scalar quantization (f16 / int8) of index vectors.
+// Author: d65v <https://github.com/d65v>
+//
+// NOTE: Each component is stored on its own, with no codebooks to train:
+//   - F16:  IEEE half precision, 2 bytes per component, ~3 significant digits
+//   - Int8: one byte per component, min/max-scaled per dimension, so the
+//           code 0..=255 spans exactly the range that dimension takes
+//
+// Int8 ranges follow the data: a vector outside the current range widens it
+// (with some headroom) and the affected dimensions of every stored row are
+// re-encoded. Ranges are only tight when fitted to the whole set up front,
+// e.g. by `HnswIndex::set_quantization` on load.
+//
+// Kernels score codes against an f32 query directly. With AVX2 + FMA (and
+// F16C for f16) they decode 8 components in registers and fuse them into
+// the dot / L2 accumulation; elsewhere a block of components is decoded onto
+// the stack and handed to the f32 kernels from `embedding::simd`. Either
+// way a candidate is never expanded to a full `Vec<f32>`.
+
+use std::sync::OnceLock;
+
+use half::f16;
+use half::slice::HalfFloatSliceExt;
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::{dot, euclidean_distance_sq, score_from_rank, Metric};
+use crate::vectors::CHUNK_BYTES;
+
+/// Components decoded per kernel call.
+const BLOCK: usize = 64;
+
+/// Share of a dimension's new span added as headroom when an int8 range has
+/// to widen, so a stream of slowly growing values does not re-encode the
+/// store every time.
+const HEADROOM: f32 = 0.25;
+
+/// Levels per int8 component.
+const LEVELS: f32 = 255.0;
+
+// ── Quantization Kind ─────────────────────────────────────────────────────────
+
+/// How index vectors are stored for scoring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Quantization {
+    /// Full f32 vectors only
+    #[default]
+    None,
+    /// Half-precision floats, 2 bytes per component
+    F16,
+    /// Per-dimension min/max-scaled bytes, 1 byte per component
+    Int8,
+}
+
+impl Quantization {
+    /// Parse a config value: `"none"`, `"f16"` or `"int8"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "none" => Some(Quantization::None),
+            "f16" => Some(Quantization::F16),
+            "int8" => Some(Quantization::Int8),
+            _ => None,
+        }
+    }
+}
+
+// ── Quantized Vectors ─────────────────────────────────────────────────────────
+
+/// Row-addressed quantized copies of index vectors, scored directly against
+/// f32 queries.
+pub struct QuantizedVectors {
+    dim: usize,
+    codes: Codes,
+}
+
+enum Codes {
+    F16(Rows<f16>),
+    Int8 {
+        codes: Rows<u8>,
+        /// Per-dimension value of code 0
+        min: Vec<f32>,
+        /// Per-dimension value step between adjacent codes
+        step: Vec<f32>,
+        /// False until the first vector sets the ranges
+        fitted: bool,
+    },
+}
+
+impl QuantizedVectors {
+    /// Empty store, or `None` for [`Quantization::None`].
+    pub fn new(kind: Quantization, dim: usize) -> Option<Self> {
+        let codes = match kind {
+            Quantization::None => return None,
+            Quantization::F16 => Codes::F16(Rows::new(dim)),
+            Quantization::Int8 => Codes::Int8 {
+                codes: Rows::new(dim),
+                min: vec![0.0; dim],
+                step: vec![0.0; dim],
+                fitted: false,
+            },
+        };
+        Some(Self { dim, codes })
+    }
+
+    /// Build a store holding `vectors` at rows `0..n`, with int8 ranges
+    /// fitted tightly to them.
+    pub fn from_vectors<'a>(kind: Quantization, dim: usize, vectors: impl IntoIterator<Item = &'a [f32]>) -> Option<Self> {
+        let vectors: Vec<&[f32]> = vectors.into_iter().collect();
+        let mut store = Self::new(kind, dim)?;
+        store.fit(vectors.iter().copied());
+        for (row, v) in vectors.into_iter().enumerate() {
+            store.set(row as u32, v);
+        }
+        Some(store)
+    }
+
+    pub fn kind(&self) -> Quantization {
+        match self.codes {
+            Codes::F16(_) => Quantization::F16,
+            Codes::Int8 { .. } => Quantization::Int8,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Number of rows allocated.
+    pub fn len(&self) -> usize {
+        match &self.codes {
+            Codes::F16(c) => c.rows,
+            Codes::Int8 { codes, .. } => codes.rows,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Set int8 ranges to exactly cover `vectors`, re-encoding any rows
+    /// already stored. No-op for f16.
+    pub fn fit<'a>(&mut self, vectors: impl IntoIterator<Item = &'a [f32]>) {
+        let Codes::Int8 { .. } = self.codes else {
+            return;
+        };
+        let mut lo = vec![f32::INFINITY; self.dim];
+        let mut hi = vec![f32::NEG_INFINITY; self.dim];
+        let mut any = false;
+        for v in vectors {
+            any = true;
+            for (d, &x) in v.iter().enumerate() {
+                lo[d] = lo[d].min(x);
+                hi[d] = hi[d].max(x);
+            }
+        }
+        if any {
+            self.rescale(&lo, &hi);
+        }
+    }
+
+    /// Store `vector` at `row`, growing the store if needed.
+    pub fn set(&mut self, row: u32, vector: &[f32]) {
+        debug_assert_eq!(vector.len(), self.dim, "quantize: vector dim {} ≠ {}", vector.len(), self.dim);
+        let row = row as usize;
+        match &mut self.codes {
+            Codes::F16(c) => c.grow_to(row + 1),
+            Codes::Int8 { codes, .. } => codes.grow_to(row + 1),
+        }
+        self.widen(vector);
+
+        match &mut self.codes {
+            Codes::F16(c) => c.row_mut(row).convert_from_f32_slice(vector),
+            Codes::Int8 { codes, min, step, .. } => {
+                for (d, (c, &x)) in codes.row_mut(row).iter_mut().zip(vector).enumerate() {
+                    *c = encode(x, min[d], step[d]);
+                }
+            }
+        }
+    }
+
+    /// Decoded (approximate) vector at `row`.
+    pub fn get(&self, row: u32) -> Vec<f32> {
+        let mut out = vec![0.0; self.dim];
+        self.decode(row, 0, &mut out);
+        out
+    }
+
+    /// [`rank_score`](crate::embedding::rank_score) of the vector at `row`
+    /// against an f32 query.
+    #[inline]
+    pub fn rank(&self, metric: &Metric, query: &[f32], row: u32) -> f32 {
+        debug_assert_eq!(query.len(), self.dim);
+        let k = kernels();
+        let row = row as usize;
+        match (&self.codes, metric) {
+            (Codes::F16(c), Metric::Euclidean) => -(k.f16_l2_sq)(query, c.row(row)),
+            (Codes::F16(c), _) => (k.f16_dot)(query, c.row(row)),
+            (Codes::Int8 { codes, min, step, .. }, Metric::Euclidean) => {
+                -(k.int8_l2_sq)(query, codes.row(row), min, step)
+            }
+            (Codes::Int8 { codes, min, step, .. }, _) => (k.int8_dot)(query, codes.row(row), min, step),
+        }
+    }
+
+    /// [`score`](crate::embedding::score) of the vector at `row` against an
+    /// f32 query.
+    pub fn score(&self, metric: &Metric, query: &[f32], row: u32) -> f32 {
+        score_from_rank(metric, self.rank(metric, query, row))
+    }
+
+    /// Heap bytes held by codes and int8 ranges.
+    pub fn heap_bytes(&self) -> usize {
+        match &self.codes {
+            Codes::F16(c) => c.heap_bytes(),
+            Codes::Int8 { codes, min, step, .. } => codes.heap_bytes() + (min.capacity() + step.capacity()) * 4,
+        }
+    }
+
+    /// Decode `out.len()` components of `row`, starting at component `from`.
+    fn decode(&self, row: u32, from: usize, out: &mut [f32]) {
+        let range = from..from + out.len();
+        match &self.codes {
+            Codes::F16(c) => c.row(row as usize)[range].convert_to_f32_slice(out),
+            Codes::Int8 { codes, min, step, .. } => {
+                decode_int8(&codes.row(row as usize)[range], &min[from..], &step[from..], out)
+            }
+        }
+    }
+
+    /// Grow int8 ranges so they cover `vector`, with headroom.
+    fn widen(&mut self, vector: &[f32]) {
+        let Codes::Int8 { min, step, fitted, .. } = &self.codes else {
+            return;
+        };
+        if !*fitted {
+            self.rescale(vector, vector);
+            return;
+        }
+        let mut lo = min.clone();
+        let mut hi: Vec<f32> = min.iter().zip(step).map(|(&m, &s)| m + s * LEVELS).collect();
+        let mut grew = false;
+        for (d, &x) in vector.iter().enumerate() {
+            if x < lo[d] || x > hi[d] {
+                grew = true;
+                let span = hi[d].max(x) - lo[d].min(x);
+                if x < lo[d] {
+                    lo[d] = x - span * HEADROOM;
+                } else {
+                    hi[d] = x + span * HEADROOM;
+                }
+            }
+        }
+        if grew {
+            self.rescale(&lo, &hi);
+        }
+    }
+
+    /// Switch int8 ranges to `[lo, hi]` per dimension and re-encode stored
+    /// rows for every dimension that changed.
+    fn rescale(&mut self, lo: &[f32], hi: &[f32]) {
+        let Codes::Int8 { codes, min, step, fitted } = &mut self.codes else {
+            return;
+        };
+        let new_step: Vec<f32> = lo.iter().zip(hi).map(|(&l, &h)| (h - l) / LEVELS).collect();
+        if *fitted {
+            let changed: Vec<usize> = (0..lo.len())
+                .filter(|&d| lo[d] != min[d] || new_step[d] != step[d])
+                .collect();
+            codes.for_each_row_mut(|row| {
+                for &d in &changed {
+                    row[d] = encode(min[d] + step[d] * row[d] as f32, lo[d], new_step[d]);
+                }
+            });
+        }
+        min.copy_from_slice(lo);
+        *step = new_step;
+        *fitted = true;
+    }
+}
+
+#[inline]
+fn decode_int8(codes: &[u8], min: &[f32], step: &[f32], out: &mut [f32]) {
+    for (i, (o, &c)) in out.iter_mut().zip(codes).enumerate() {
+        *o = min[i] + step[i] * c as f32;
+    }
+}
+
+#[inline]
+fn encode(x: f32, min: f32, step: f32) -> u8 {
+    if step > 0.0 {
+        ((x - min) / step).round().clamp(0.0, LEVELS) as u8
+    } else {
+        0
+    }
+}
+
+// ── Chunked Rows ──────────────────────────────────────────────────────────────
+
+/// Fixed-stride rows in ~1 MiB chunks, like the in-memory vector store, so
+/// growth never copies old rows.
+struct Rows<T> {
+    dim: usize,
+    rows: usize,
+    chunks: Vec<Vec<T>>,
+}
+
+impl<T: Copy + Default> Rows<T> {
+    fn new(dim: usize) -> Self {
+        Self { dim, rows: 0, chunks: Vec::new() }
+    }
+
+    fn per_chunk(&self) -> usize {
+        (CHUNK_BYTES / (self.dim * std::mem::size_of::<T>()).max(1)).max(1)
+    }
+
+    fn row(&self, row: usize) -> &[T] {
+        let per = self.per_chunk();
+        let start = (row % per) * self.dim;
+        &self.chunks[row / per][start..start + self.dim]
+    }
+
+    fn row_mut(&mut self, row: usize) -> &mut [T] {
+        let (per, dim) = (self.per_chunk(), self.dim);
+        let start = (row % per) * dim;
+        &mut self.chunks[row / per][start..start + dim]
+    }
+
+    /// Append zeroed rows until there are at least `rows`.
+    fn grow_to(&mut self, rows: usize) {
+        let per = self.per_chunk();
+        while self.rows < rows {
+            if self.rows.is_multiple_of(per) {
+                self.chunks.push(Vec::with_capacity(per * self.dim));
+            }
+            let chunk = self.chunks.last_mut().expect("chunk pushed above");
+            chunk.resize(chunk.len() + self.dim, T::default());
+            self.rows += 1;
+        }
+    }
+
+    fn for_each_row_mut(&mut self, mut f: impl FnMut(&mut [T])) {
+        for chunk in &mut self.chunks {
+            chunk.chunks_exact_mut(self.dim.max(1)).for_each(&mut f);
+        }
+    }
+
+    fn heap_bytes(&self) -> usize {
+        let items: usize = self.chunks.iter().map(Vec::capacity).sum();
+        items * std::mem::size_of::<T>() + self.chunks.capacity() * std::mem::size_of::<Vec<T>>()
+    }
+}
+
+// ── Kernels ───────────────────────────────────────────────────────────────────
+
+/// Int8 kernel: (query, codes, per-dimension min, per-dimension step).
+type Int8Kernel = fn(&[f32], &[u8], &[f32], &[f32]) -> f32;
+
+/// Distance kernels over quantized rows.
+#[derive(Clone, Copy)]
+struct Kernels {
+    name: &'static str,
+    f16_dot: fn(&[f32], &[f16]) -> f32,
+    f16_l2_sq: fn(&[f32], &[f16]) -> f32,
+    int8_dot: Int8Kernel,
+    int8_l2_sq: Int8Kernel,
+}
+
+const BLOCKED: Kernels = Kernels {
+    name: "blocked",
+    f16_dot: |q, c| blocked(q, |from, out| c[from..from + out.len()].convert_to_f32_slice(out), dot),
+    f16_l2_sq: |q, c| {
+        blocked(q, |from, out| c[from..from + out.len()].convert_to_f32_slice(out), euclidean_distance_sq)
+    },
+    int8_dot: |q, c, min, step| {
+        blocked(q, |from, out| decode_int8(&c[from..], &min[from..], &step[from..], out), dot)
+    },
+    int8_l2_sq: |q, c, min, step| {
+        blocked(q, |from, out| decode_int8(&c[from..], &min[from..], &step[from..], out), euclidean_distance_sq)
+    },
+};
+
+static ACTIVE: OnceLock<Kernels> = OnceLock::new();
+
+/// Fused x86 kernels when the CPU has them, else the blocked fallback.
+#[inline]
+fn kernels() -> &'static Kernels {
+    ACTIVE.get_or_init(|| {
+        let kernels = *available().first().unwrap_or(&BLOCKED);
+        log::info!("quantized distance kernels: {}", kernels.name);
+        kernels
+    })
+}
+
+/// Every kernel set this CPU supports, fastest first.
+fn available() -> Vec<Kernels> {
+    let mut sets = Vec::new();
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") && is_x86_feature_detected!("f16c") {
+            sets.push(x86::AVX2);
+        }
+    }
+    sets.push(BLOCKED);
+    sets
+}
+
+/// Decode `query.len()` components a block at a time and sum `kernel` over
+/// the blocks.
+#[inline]
+fn blocked(query: &[f32], decode: impl Fn(usize, &mut [f32]), kernel: fn(&[f32], &[f32]) -> f32) -> f32 {
+    let mut buf = [0f32; BLOCK];
+    let mut acc = 0.0;
+    for (i, q) in query.chunks(BLOCK).enumerate() {
+        let block = &mut buf[..q.len()];
+        decode(i * BLOCK, block);
+        acc += kernel(q, block);
+    }
+    acc
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use half::f16;
+
+    use super::Kernels;
+
+    pub const AVX2: Kernels = Kernels {
+        name: "avx2",
+        // SAFETY (all four): AVX2, FMA and F16C were detected before this
+        // set was selected.
+        f16_dot: |q, c| unsafe { f16_dot(q, c) },
+        f16_l2_sq: |q, c| unsafe { f16_l2_sq(q, c) },
+        int8_dot: |q, c, min, step| unsafe { int8_dot(q, c, min, step) },
+        int8_l2_sq: |q, c, min, step| unsafe { int8_l2_sq(q, c, min, step) },
+    };
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn hsum256(v: __m256) -> f32 {
+        let sum = _mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps(v, 1));
+        let sum = _mm_add_ps(sum, _mm_movehl_ps(sum, sum));
+        _mm_cvtss_f32(_mm_add_ss(sum, _mm_shuffle_ps(sum, sum, 0b01)))
+    }
+
+    /// Eight f16 components at `c`, widened to f32.
+    #[target_feature(enable = "avx2,f16c")]
+    unsafe fn load_f16(c: *const f16) -> __m256 {
+        _mm256_cvtph_ps(_mm_loadu_si128(c as *const __m128i))
+    }
+
+    /// Eight int8 components at `c`, decoded as `min + step · code`.
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn load_int8(c: *const u8, min: *const f32, step: *const f32) -> __m256 {
+        let codes = _mm256_cvtepi32_ps(_mm256_cvtepu8_epi32(_mm_loadl_epi64(c as *const __m128i)));
+        _mm256_fmadd_ps(codes, _mm256_loadu_ps(step), _mm256_loadu_ps(min))
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    unsafe fn f16_dot(q: &[f32], c: &[f16]) -> f32 {
+        let n = q.len().min(c.len());
+        let mut acc = [_mm256_setzero_ps(); 2];
+        let mut i = 0;
+        while i + 16 <= n {
+            for (k, acc) in acc.iter_mut().enumerate() {
+                let j = i + 8 * k;
+                *acc = _mm256_fmadd_ps(_mm256_loadu_ps(q.as_ptr().add(j)), load_f16(c.as_ptr().add(j)), *acc);
+            }
+            i += 16;
+        }
+        if i + 8 <= n {
+            acc[0] = _mm256_fmadd_ps(_mm256_loadu_ps(q.as_ptr().add(i)), load_f16(c.as_ptr().add(i)), acc[0]);
+            i += 8;
+        }
+        let tail: f32 = q[i..n].iter().zip(&c[i..n]).map(|(a, b)| a * b.to_f32()).sum();
+        hsum256(_mm256_add_ps(acc[0], acc[1])) + tail
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    unsafe fn f16_l2_sq(q: &[f32], c: &[f16]) -> f32 {
+        let n = q.len().min(c.len());
+        let mut acc = [_mm256_setzero_ps(); 2];
+        let mut i = 0;
+        while i + 16 <= n {
+            for (k, acc) in acc.iter_mut().enumerate() {
+                let j = i + 8 * k;
+                let d = _mm256_sub_ps(_mm256_loadu_ps(q.as_ptr().add(j)), load_f16(c.as_ptr().add(j)));
+                *acc = _mm256_fmadd_ps(d, d, *acc);
+            }
+            i += 16;
+        }
+        if i + 8 <= n {
+            let d = _mm256_sub_ps(_mm256_loadu_ps(q.as_ptr().add(i)), load_f16(c.as_ptr().add(i)));
+            acc[0] = _mm256_fmadd_ps(d, d, acc[0]);
+            i += 8;
+        }
+        let tail: f32 = q[i..n].iter().zip(&c[i..n]).map(|(a, b)| (a - b.to_f32()).powi(2)).sum();
+        hsum256(_mm256_add_ps(acc[0], acc[1])) + tail
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn int8_dot(q: &[f32], c: &[u8], min: &[f32], step: &[f32]) -> f32 {
+        let n = q.len().min(c.len()).min(min.len()).min(step.len());
+        let mut acc = [_mm256_setzero_ps(); 2];
+        let mut i = 0;
+        while i + 16 <= n {
+            for (k, acc) in acc.iter_mut().enumerate() {
+                let j = i + 8 * k;
+                let v = load_int8(c.as_ptr().add(j), min.as_ptr().add(j), step.as_ptr().add(j));
+                *acc = _mm256_fmadd_ps(_mm256_loadu_ps(q.as_ptr().add(j)), v, *acc);
+            }
+            i += 16;
+        }
+        if i + 8 <= n {
+            let v = load_int8(c.as_ptr().add(i), min.as_ptr().add(i), step.as_ptr().add(i));
+            acc[0] = _mm256_fmadd_ps(_mm256_loadu_ps(q.as_ptr().add(i)), v, acc[0]);
+            i += 8;
+        }
+        let tail: f32 = (i..n).map(|j| q[j] * (min[j] + step[j] * c[j] as f32)).sum();
+        hsum256(_mm256_add_ps(acc[0], acc[1])) + tail
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn int8_l2_sq(q: &[f32], c: &[u8], min: &[f32], step: &[f32]) -> f32 {
+        let n = q.len().min(c.len()).min(min.len()).min(step.len());
+        let mut acc = [_mm256_setzero_ps(); 2];
+        let mut i = 0;
+        while i + 16 <= n {
+            for (k, acc) in acc.iter_mut().enumerate() {
+                let j = i + 8 * k;
+                let v = load_int8(c.as_ptr().add(j), min.as_ptr().add(j), step.as_ptr().add(j));
+                let d = _mm256_sub_ps(_mm256_loadu_ps(q.as_ptr().add(j)), v);
+                *acc = _mm256_fmadd_ps(d, d, *acc);
+            }
+            i += 16;
+        }
+        if i + 8 <= n {
+            let v = load_int8(c.as_ptr().add(i), min.as_ptr().add(i), step.as_ptr().add(i));
+            let d = _mm256_sub_ps(_mm256_loadu_ps(q.as_ptr().add(i)), v);
+            acc[0] = _mm256_fmadd_ps(d, d, acc[0]);
+            i += 8;
+        }
+        let tail: f32 = (i..n).map(|j| (q[j] - (min[j] + step[j] * c[j] as f32)).powi(2)).sum();
+        hsum256(_mm256_add_ps(acc[0], acc[1])) + tail
+    }
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::{normalize, rank_score, score};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_vectors(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|_| normalize(&(0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect::<Vec<f32>>()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Quantization::parse("none"), Some(Quantization::None));
+        assert_eq!(Quantization::parse("f16"), Some(Quantization::F16));
+        assert_eq!(Quantization::parse("int8"), Some(Quantization::Int8));
+        assert_eq!(Quantization::parse("int4"), None);
+        assert!(QuantizedVectors::new(Quantization::None, 8).is_none());
+    }
+
+    #[test]
+    fn test_kernels_match_full_precision() {
+        // 100 dims: one full block plus a partial one
+        let data = random_vectors(50, 100, 1);
+        let query = random_vectors(1, 100, 2).remove(0);
+        for (kind, tol) in [(Quantization::F16, 1e-3), (Quantization::Int8, 2e-2)] {
+            let store = QuantizedVectors::from_vectors(kind, 100, data.iter().map(|v| v.as_slice())).unwrap();
+            for metric in [Metric::Cosine, Metric::DotProduct, Metric::Euclidean] {
+                for (row, v) in data.iter().enumerate() {
+                    let row = row as u32;
+                    let exact = score(&metric, &query, v);
+                    let approx = store.score(&metric, &query, row);
+                    assert!((exact - approx).abs() < tol, "{:?} {:?}: {} vs {}", kind, metric, exact, approx);
+                    let rank = rank_score(&metric, &query, &store.get(row));
+                    assert!((rank - store.rank(&metric, &query, row)).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernel_sets_agree() {
+        let data = random_vectors(20, 100, 4);
+        let query = random_vectors(1, 100, 5).remove(0);
+        let f16s: Vec<Vec<f16>> = data.iter().map(|v| v.iter().map(|&x| f16::from_f32(x)).collect()).collect();
+        let int8 = QuantizedVectors::from_vectors(Quantization::Int8, 100, data.iter().map(|v| v.as_slice())).unwrap();
+        let Codes::Int8 { codes, min, step, .. } = &int8.codes else { unreachable!() };
+        for k in available() {
+            for (row, c) in f16s.iter().enumerate() {
+                let ints = codes.row(row);
+                let close = |a: f32, b: f32| assert!((a - b).abs() < 1e-4, "{}: {} vs {}", k.name, a, b);
+                close((k.f16_dot)(&query, c), (BLOCKED.f16_dot)(&query, c));
+                close((k.f16_l2_sq)(&query, c), (BLOCKED.f16_l2_sq)(&query, c));
+                close((k.int8_dot)(&query, ints, min, step), (BLOCKED.int8_dot)(&query, ints, min, step));
+                close((k.int8_l2_sq)(&query, ints, min, step), (BLOCKED.int8_l2_sq)(&query, ints, min, step));
+            }
+        }
+    }
+
+    #[test]
+    fn test_int8_ranges_widen_and_reencode() {
+        let mut store = QuantizedVectors::new(Quantization::Int8, 2).unwrap();
+        store.set(0, &[0.0, 1.0]);
+        store.set(1, &[1.0, -1.0]);
+        store.set(2, &[-4.0, 3.0]);
+        for (row, v) in [[0.0, 1.0], [1.0, -1.0], [-4.0, 3.0]].iter().enumerate() {
+            let got = store.get(row as u32);
+            for (a, b) in got.iter().zip(v) {
+                assert!((a - b).abs() < 0.05, "row {}: {:?} vs {:?}", row, got, v);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantized_sizes() {
+        let (n, dim) = (20_000, 64);
+        let data = random_vectors(n, dim, 3);
+        let full = n * dim * 4;
+        let f16 = QuantizedVectors::from_vectors(Quantization::F16, dim, data.iter().map(|v| v.as_slice())).unwrap();
+        let int8 = QuantizedVectors::from_vectors(Quantization::Int8, dim, data.iter().map(|v| v.as_slice())).unwrap();
+        assert_eq!(f16.len(), n);
+        // At most one partly filled chunk on top of the codes.
+        assert!(f16.heap_bytes() <= full / 2 + CHUNK_BYTES + 1024, "{}", f16.heap_bytes());
+        assert!(int8.heap_bytes() <= full / 4 + CHUNK_BYTES + 1024, "{}", int8.heap_bytes());
+    }
+}
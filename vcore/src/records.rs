@@ -0,0 +1,486 @@
+// VecBase — records.rs
+// Pluggable record storage behind the `RecordStore` trait.
+// Author: d65v <https://github.com/d65v>
+//
+// `VecBase` talks to its records only through `RecordStore`, so backends can
+// be swapped without touching core. Two ship with the crate:
+//
+//   - MemoryRecordStore: a HashMap, contents saved inside snapshots
+//   - FileRecordStore:   an append-only log of records on disk with an
+//                        in-memory id → offset map; persists itself
+//
+// FileRecordStore reuses the WAL frame layout (length u32 | crc32 u32 |
+// bincode payload). A put appends the new record, a delete appends a
+// tombstone; the latest frame for an id wins. A torn tail is truncated on
+// open. Once superseded frames outweigh live ones, `flush` rewrites the file.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::{Filter, Metadata};
+use crate::storage::{storage_err, tmp_path};
+use crate::wal::{decode_frames, encode_frame, FRAME_HEADER_LEN};
+use crate::{Result, VecBaseError, VecRecord};
+
+/// Default record file name inside `storage_path`.
+pub const RECORDS_FILE: &str = "records.vbr";
+
+/// Files smaller than this are never compacted.
+const COMPACT_MIN_BYTES: u64 = 64 * 1024;
+
+// ── Trait ─────────────────────────────────────────────────────────────────────
+
+/// Storage for `VecRecord`s keyed by id.
+///
+/// Implementations must be `Send + Sync` so a `VecBase` can be shared across
+/// threads. Reads return owned records because a backend may not keep them
+/// in memory.
+pub trait RecordStore: Send + Sync {
+    /// Fetch a record by id.
+    fn get(&self, id: &str) -> Result<Option<VecRecord>>;
+
+    /// Insert or replace a record.
+    fn put(&mut self, record: VecRecord) -> Result<()>;
+
+    /// Remove a record. Returns whether it existed.
+    fn delete(&mut self, id: &str) -> Result<bool>;
+
+    fn contains(&self, id: &str) -> bool;
+
+    /// Whether the record `id` exists and its metadata passes `filter`.
+    /// Called for every node visited by a filtered search; stores that can
+    /// check metadata without materializing a `VecRecord` should override it.
+    fn matches(&self, id: &str, filter: &Filter) -> Result<bool> {
+        Ok(self.get(id)?.is_some_and(|r| filter.matches(r.metadata.as_ref())))
+    }
+
+    /// Every stored record, in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<VecRecord>> + '_>;
+
+    /// Every stored id, in no particular order.
+    fn ids(&self) -> Box<dyn Iterator<Item = &str> + '_>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Make all previous writes durable.
+    fn flush(&mut self) -> Result<()>;
+
+    /// `true` if the store keeps its own records on disk, in which case
+    /// snapshots do not copy them.
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
+
+// ── Memory Store ──────────────────────────────────────────────────────────────
+
+/// In-memory `RecordStore`; the default.
+/// Each id is held once, as the map key.
+#[derive(Default)]
+pub struct MemoryRecordStore {
+    records: HashMap<String, (u32, Option<Metadata>)>,
+}
+
+impl MemoryRecordStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RecordStore for MemoryRecordStore {
+    fn get(&self, id: &str) -> Result<Option<VecRecord>> {
+        Ok(self.records.get_key_value(id).map(to_record))
+    }
+
+    fn put(&mut self, record: VecRecord) -> Result<()> {
+        self.records.insert(record.id, (record.row, record.metadata));
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<bool> {
+        Ok(self.records.remove(id).is_some())
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.records.contains_key(id)
+    }
+
+    fn matches(&self, id: &str, filter: &Filter) -> Result<bool> {
+        Ok(self.records.get(id).is_some_and(|(_, meta)| filter.matches(meta.as_ref())))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<VecRecord>> + '_> {
+        Box::new(self.records.iter().map(to_record).map(Ok))
+    }
+
+    fn ids(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.records.keys().map(String::as_str))
+    }
+
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn to_record((id, (row, metadata)): (&String, &(u32, Option<Metadata>))) -> VecRecord {
+    VecRecord {
+        id: id.clone(),
+        row: *row,
+        metadata: metadata.clone(),
+    }
+}
+
+// ── File Store ────────────────────────────────────────────────────────────────
+
+/// One frame in a record file.
+#[derive(Serialize, Deserialize)]
+enum RecordOp {
+    Put(VecRecord),
+    Delete(String),
+}
+
+/// Where the live frame for an id sits in the file.
+#[derive(Clone, Copy)]
+struct Slot {
+    offset: u64,
+    len: u64,
+}
+
+/// File-backed `RecordStore`: records live on disk, only ids and offsets
+/// are kept in memory.
+///
+/// Writes are not fsync'd individually — `VecBase` logs every mutation to
+/// its WAL first — so call [`flush`](RecordStore::flush) (done by
+/// `VecBase::checkpoint`) to make them durable.
+pub struct FileRecordStore {
+    path: PathBuf,
+    file: Mutex<File>,
+    slots: HashMap<String, Slot>,
+    /// Append position, i.e. the file length.
+    end: u64,
+    /// Bytes taken up by live frames; the rest is superseded or tombstones.
+    live_bytes: u64,
+}
+
+impl FileRecordStore {
+    /// Open (or create) the record file at `path`.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on I/O failure.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| storage_err("create record dir", e))?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| storage_err(&format!("open record file {}", path.display()), e))?;
+
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)
+            .map_err(|e| storage_err("read record file", e))?;
+
+        let (frames, valid_len) = decode_frames::<RecordOp>(&buf);
+        if valid_len < buf.len() {
+            log::warn!(
+                "record file {}: discarding {} bytes of torn/corrupt tail",
+                path.display(),
+                buf.len() - valid_len
+            );
+            file.set_len(valid_len as u64)
+                .and_then(|_| file.sync_all())
+                .map_err(|e| storage_err("truncate record file", e))?;
+        }
+
+        let mut store = Self {
+            path,
+            file: Mutex::new(file),
+            slots: HashMap::new(),
+            end: valid_len as u64,
+            live_bytes: 0,
+        };
+
+        let starts: Vec<usize> = frames.iter().map(|(start, _)| *start).collect();
+        for (i, (start, op)) in frames.into_iter().enumerate() {
+            let next = starts.get(i + 1).copied().unwrap_or(valid_len);
+            let slot = Slot {
+                offset: start as u64,
+                len: (next - start) as u64,
+            };
+            match op {
+                RecordOp::Put(record) => store.set_slot(record.id, slot),
+                RecordOp::Delete(id) => {
+                    store.clear_slot(&id);
+                }
+            }
+        }
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rewrite the file with only live records.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on I/O failure; the old file is
+    /// left in place until the rewrite is complete.
+    pub fn compact(&mut self) -> Result<()> {
+        let tmp = tmp_path(&self.path);
+        let mut slots = HashMap::with_capacity(self.slots.len());
+        let mut end = 0u64;
+        {
+            let out = File::create(&tmp).map_err(|e| storage_err("create record file", e))?;
+            let mut out = BufWriter::new(out);
+            for (id, slot) in &self.slots {
+                let frame = encode_frame(&RecordOp::Put(self.read_slot(*slot)?))?;
+                out.write_all(&frame)
+                    .map_err(|e| storage_err("write record file", e))?;
+                let len = frame.len() as u64;
+                slots.insert(id.clone(), Slot { offset: end, len });
+                end += len;
+            }
+            out.into_inner()
+                .map_err(|e| storage_err("write record file", e))?
+                .sync_all()
+                .map_err(|e| storage_err("sync record file", e))?;
+        }
+        fs::rename(&tmp, &self.path).map_err(|e| storage_err("rename record file", e))?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.path)
+            .map_err(|e| storage_err("reopen record file", e))?;
+        self.file = Mutex::new(file);
+        self.slots = slots;
+        self.end = end;
+        self.live_bytes = end;
+        Ok(())
+    }
+
+    fn append(&mut self, op: &RecordOp) -> Result<Slot> {
+        let frame = encode_frame(op)?;
+        let file = self.file.get_mut().unwrap_or_else(|e| e.into_inner());
+        file.seek(SeekFrom::Start(self.end))
+            .and_then(|_| file.write_all(&frame))
+            .map_err(|e| storage_err("append record", e))?;
+        let slot = Slot {
+            offset: self.end,
+            len: frame.len() as u64,
+        };
+        self.end += slot.len;
+        Ok(slot)
+    }
+
+    fn set_slot(&mut self, id: String, slot: Slot) {
+        self.live_bytes += slot.len;
+        if let Some(old) = self.slots.insert(id, slot) {
+            self.live_bytes -= old.len;
+        }
+    }
+
+    fn clear_slot(&mut self, id: &str) -> bool {
+        match self.slots.remove(id) {
+            Some(old) => {
+                self.live_bytes -= old.len;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn read_slot(&self, slot: Slot) -> Result<VecRecord> {
+        let mut buf = vec![0u8; slot.len as usize];
+        {
+            let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            file.seek(SeekFrom::Start(slot.offset))
+                .and_then(|_| file.read_exact(&mut buf))
+                .map_err(|e| storage_err("read record", e))?;
+        }
+
+        let crc = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+        let payload = &buf[FRAME_HEADER_LEN..];
+        if crc32fast::hash(payload) != crc {
+            return Err(VecBaseError::StorageError(format!(
+                "record at offset {} in {} failed its checksum",
+                slot.offset,
+                self.path.display()
+            )));
+        }
+        match bincode::deserialize(payload).map_err(|e| storage_err("decode record", e))? {
+            RecordOp::Put(record) => Ok(record),
+            RecordOp::Delete(_) => Err(VecBaseError::StorageError(
+                "record slot points at a tombstone".to_string(),
+            )),
+        }
+    }
+}
+
+impl RecordStore for FileRecordStore {
+    fn get(&self, id: &str) -> Result<Option<VecRecord>> {
+        match self.slots.get(id) {
+            Some(&slot) => self.read_slot(slot).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&mut self, record: VecRecord) -> Result<()> {
+        let id = record.id.clone();
+        let slot = self.append(&RecordOp::Put(record))?;
+        self.set_slot(id, slot);
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<bool> {
+        if !self.slots.contains_key(id) {
+            return Ok(false);
+        }
+        self.append(&RecordOp::Delete(id.to_string()))?;
+        Ok(self.clear_slot(id))
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.slots.contains_key(id)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<VecRecord>> + '_> {
+        Box::new(self.slots.values().map(|&slot| self.read_slot(slot)))
+    }
+
+    fn ids(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(self.slots.keys().map(String::as_str))
+    }
+
+    fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let garbage = self.end - self.live_bytes;
+        if self.end >= COMPACT_MIN_BYTES && garbage > self.live_bytes {
+            return self.compact();
+        }
+        self.file
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .sync_data()
+            .map_err(|e| storage_err("sync record file", e))
+    }
+
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::scratch_dir;
+
+    fn record(id: &str, row: u32) -> VecRecord {
+        VecRecord {
+            id: id.to_string(),
+            row,
+            metadata: Some(crate::metadata! { "name" => id }),
+        }
+    }
+
+    /// Behaviour every `RecordStore` must share.
+    fn exercise(store: &mut dyn RecordStore) {
+        store.put(record("a", 1)).unwrap();
+        store.put(record("b", 2)).unwrap();
+        store.put(record("a", 3)).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get("a").unwrap().unwrap().row, 3);
+        assert!(store.get("zzz").unwrap().is_none());
+
+        assert!(store.delete("b").unwrap());
+        assert!(!store.delete("b").unwrap());
+        assert!(!store.contains("b"));
+
+        let all: Vec<VecRecord> = store.iter().collect::<Result<_>>().unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].metadata, Some(crate::metadata! { "name" => "a" }));
+        assert_eq!(store.ids().collect::<Vec<_>>(), vec!["a"]);
+        store.flush().unwrap();
+    }
+
+    #[test]
+    fn test_memory_store() {
+        exercise(&mut MemoryRecordStore::new());
+    }
+
+    #[test]
+    fn test_file_store_reopens() {
+        let path = scratch_dir("records-reopen").join(RECORDS_FILE);
+        exercise(&mut FileRecordStore::open(&path).unwrap());
+
+        let store = FileRecordStore::open(&path).unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get("a").unwrap().unwrap().row, 3);
+        assert!(store.get("b").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_file_store_torn_tail() {
+        let path = scratch_dir("records-torn").join(RECORDS_FILE);
+        {
+            let mut store = FileRecordStore::open(&path).unwrap();
+            store.put(record("a", 1)).unwrap();
+            store.put(record("b", 2)).unwrap();
+        }
+        let len = fs::metadata(&path).unwrap().len();
+        let f = OpenOptions::new().write(true).open(&path).unwrap();
+        f.set_len(len - 4).unwrap();
+        drop(f);
+
+        let mut store = FileRecordStore::open(&path).unwrap();
+        assert_eq!(store.ids().collect::<Vec<_>>(), vec!["a"]);
+        store.put(record("c", 3)).unwrap();
+        drop(store);
+        assert_eq!(FileRecordStore::open(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_file_store_compacts() {
+        let path = scratch_dir("records-compact").join(RECORDS_FILE);
+        let mut store = FileRecordStore::open(&path).unwrap();
+        for round in 0..400 {
+            for i in 0..10 {
+                store.put(record(&format!("r{}", i), round)).unwrap();
+            }
+        }
+        let before = fs::metadata(&path).unwrap().len();
+        store.flush().unwrap();
+        let after = fs::metadata(&path).unwrap().len();
+        assert!(after * 50 < before, "{} -> {}", before, after);
+        assert_eq!(store.get("r3").unwrap().unwrap().row, 399);
+
+        store.put(record("new", 0)).unwrap();
+        drop(store);
+        let store = FileRecordStore::open(&path).unwrap();
+        assert_eq!(store.len(), 11);
+        assert_eq!(store.get("r9").unwrap().unwrap().row, 399);
+    }
+}
@@ -0,0 +1,624 @@
+// VecBase — server.rs
+// HTTP JSON API served by `vecbase run`.
+// Author: d65v <https://github.com/d65v>
+//
+// Endpoints (all bodies JSON):
+//
+//   GET    /health          {"status": "ok"}
+//   GET    /count           {"count": n}
+//   POST   /vectors         {"id", "vector", "metadata"?}           → 201
+//   POST   /vectors/batch   {"items": [{"id", "vector", "metadata"?}]}
+//   GET    /vectors/{id}    {"id", "vector", "metadata"}
+//   DELETE /vectors/{id}    {"deleted": id}
+//   POST   /search          {"vector", "top_k"?, "ef" | "nprobe"?} → {"results": [...]}
+//
+// Metadata is a plain JSON object (see `metadata::metadata_from_json`).
+// Errors come back as {"error": message} with a status from `status_for`.
+//
+// Handlers share one `VecBase`, which locks internally: searches run in
+// parallel, and only wait for the short commit step of an insert or
+// delete. Each runs on the blocking pool, since inserts and deletes may
+// wait on WAL writes.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{DefaultBodyLimit, Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::net::TcpListener;
+
+use crate::metadata::{metadata_from_json, metadata_to_json, Metadata};
+use crate::processing::{batch_insert, BatchInsert};
+use crate::{VecBase, VecBaseError};
+
+/// Largest accepted request body; batch inserts can be big.
+pub const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// A `VecBase` shared between request handlers.
+pub type SharedDb = Arc<VecBase>;
+
+// ── Config ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    /// Interface to bind
+    pub host: String,
+    /// TCP port to listen on
+    pub port: u16,
+    /// TCP port for the gRPC API (`vecbase grpc`)
+    pub grpc_port: u16,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 7777,
+            grpc_port: 7778,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Load from `VECBASE_HOST` / `VECBASE_PORT` / `VECBASE_GRPC_PORT`,
+    /// falling back to defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let env_port = |key: &str, default: u16| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            host: std::env::var("VECBASE_HOST").unwrap_or(defaults.host),
+            port: env_port("VECBASE_PORT", defaults.port),
+            grpc_port: env_port("VECBASE_GRPC_PORT", defaults.grpc_port),
+        }
+    }
+
+    /// `host:port`, ready for `TcpListener::bind`.
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// `host:grpc_port`, ready for `TcpListener::bind`.
+    pub fn grpc_addr(&self) -> String {
+        format!("{}:{}", self.host, self.grpc_port)
+    }
+}
+
+// ── Errors ────────────────────────────────────────────────────────────────────
+
+/// HTTP status for a `VecBaseError`.
+pub fn status_for(err: &VecBaseError) -> StatusCode {
+    match err {
+        VecBaseError::DimensionMismatch { .. } | VecBaseError::ConfigError(_) => {
+            StatusCode::BAD_REQUEST
+        }
+        VecBaseError::NotFound { .. } | VecBaseError::CollectionNotFound { .. } => {
+            StatusCode::NOT_FOUND
+        }
+        VecBaseError::CollectionExists { .. } => StatusCode::CONFLICT,
+        VecBaseError::CapacityExceeded { .. } => StatusCode::INSUFFICIENT_STORAGE,
+        VecBaseError::StorageError(_) | VecBaseError::PluginLoadError(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// An error response: `{"error": message}` with `status`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<VecBaseError> for ApiError {
+    fn from(err: VecBaseError) -> Self {
+        Self {
+            status: status_for(&err),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            log::error!("{}", self.message);
+        }
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+// ── Request / Response Bodies ─────────────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct InsertRequest {
+    pub id: String,
+    pub vector: Vec<f32>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    pub items: Vec<InsertRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchFailure {
+    pub id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    pub inserted: usize,
+    pub failed: Vec<BatchFailure>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchRequest {
+    pub vector: Vec<f32>,
+    /// Results to return, clamped to the collection's `max_elements`
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    /// Per-query search breadth, see [`VecBase::search_with_breadth`]: HNSW
+    /// beam width, IVF lists probed (also accepted as `nprobe`), LSH extra
+    /// probes or PQ re-rank factor. The collection's configured value when
+    /// unset.
+    #[serde(default, alias = "nprobe")]
+    pub ef: Option<usize>,
+}
+
+fn default_top_k() -> usize {
+    10
+}
+
+#[derive(Debug, Serialize)]
+pub struct Hit {
+    pub id: String,
+    pub score: f32,
+    pub metadata: serde_json::Value,
+}
+
+/// Metadata from a request body; `null` or absent means none.
+fn parse_metadata(json: Option<serde_json::Value>) -> ApiResult<Option<Metadata>> {
+    match json {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(json) => metadata_from_json(&json).map(Some).ok_or_else(|| {
+            ApiError::bad_request(
+                "metadata must be an object of strings, numbers, bools or lists of those",
+            )
+        }),
+    }
+}
+
+fn metadata_json(meta: Option<&Metadata>) -> serde_json::Value {
+    meta.map(metadata_to_json)
+        .unwrap_or(serde_json::Value::Null)
+}
+
+// ── Router ────────────────────────────────────────────────────────────────────
+
+/// All routes, bound to `db`.
+pub fn router(db: SharedDb) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/count", get(count))
+        .route("/vectors", post(insert))
+        .route("/vectors/batch", post(insert_batch))
+        .route("/vectors/{id}", get(get_vector).delete(delete_vector))
+        .route("/search", post(search))
+        .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+        .with_state(db)
+}
+
+/// Serve the API on `listener` until `shutdown` resolves.
+///
+/// # Errors
+/// Returns the underlying I/O error if the server fails.
+pub async fn serve(
+    listener: TcpListener,
+    db: SharedDb,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    log::info!("HTTP API listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(db))
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+/// Run `f` against the database on the blocking pool.
+async fn with_db<T, F>(db: SharedDb, f: F) -> ApiResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&VecBase) -> ApiResult<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&db))
+        .await
+        .map_err(|e| ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: format!("request handler failed: {}", e),
+        })?
+}
+
+// ── Handlers ──────────────────────────────────────────────────────────────────
+
+async fn health() -> Json<serde_json::Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn count(State(db): State<SharedDb>) -> ApiResult<Json<serde_json::Value>> {
+    let n = db.len();
+    Ok(Json(json!({ "count": n })))
+}
+
+async fn insert(
+    State(db): State<SharedDb>,
+    body: Result<Json<InsertRequest>, JsonRejection>,
+) -> ApiResult<(StatusCode, Json<serde_json::Value>)> {
+    let Json(req) = body?;
+    let metadata = parse_metadata(req.metadata)?;
+    let id = req.id;
+    with_db(db, move |db| {
+        db.insert(id.clone(), req.vector, metadata)?;
+        Ok((StatusCode::CREATED, Json(json!({ "id": id }))))
+    })
+    .await
+}
+
+async fn insert_batch(
+    State(db): State<SharedDb>,
+    body: Result<Json<BatchRequest>, JsonRejection>,
+) -> ApiResult<Json<BatchResponse>> {
+    let Json(req) = body?;
+    let mut items = Vec::with_capacity(req.items.len());
+    let mut failed = Vec::new();
+    for item in req.items {
+        match parse_metadata(item.metadata) {
+            Ok(metadata) => items.push(BatchInsert {
+                id: item.id,
+                vector: item.vector,
+                metadata,
+            }),
+            Err(e) => failed.push(BatchFailure {
+                id: item.id,
+                error: e.message,
+            }),
+        }
+    }
+    with_db(db, move |db| {
+        let result = batch_insert(db, items);
+        failed.extend(
+            result
+                .failed
+                .into_iter()
+                .map(|(id, error)| BatchFailure { id, error }),
+        );
+        Ok(Json(BatchResponse {
+            inserted: result.inserted,
+            failed,
+        }))
+    })
+    .await
+}
+
+async fn get_vector(
+    State(db): State<SharedDb>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<serde_json::Value>> {
+    with_db(db, move |db| {
+        let (record, vector) = db
+            .get_with_vector(&id)?
+            .ok_or(VecBaseError::NotFound { id: id.clone() })?;
+        Ok(Json(json!({
+            "id": record.id,
+            "vector": vector,
+            "metadata": metadata_json(record.metadata.as_ref()),
+        })))
+    })
+    .await
+}
+
+async fn delete_vector(
+    State(db): State<SharedDb>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<serde_json::Value>> {
+    with_db(db, move |db| {
+        db.delete(&id)?;
+        Ok(Json(json!({ "deleted": id })))
+    })
+    .await
+}
+
+async fn search(
+    State(db): State<SharedDb>,
+    body: Result<Json<SearchRequest>, JsonRejection>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let Json(req) = body?;
+    with_db(db, move |db| {
+        if req.vector.len() != db.config.dim {
+            return Err(VecBaseError::DimensionMismatch {
+                expected: db.config.dim,
+                got: req.vector.len(),
+            }
+            .into());
+        }
+        // No collection holds more, and indexes size buffers by top_k.
+        let top_k = req.top_k.min(db.config.max_elements);
+        // Without `ef` the index uses its own configured breadth.
+        let results = match req.ef {
+            Some(breadth) => db.search_with_breadth(&req.vector, top_k, breadth),
+            None => db.search(&req.vector, top_k),
+        };
+        let results: Vec<Hit> = results
+            .into_iter()
+            .map(|r| Hit {
+                metadata: metadata_json(r.metadata.as_ref()),
+                id: r.id,
+                score: r.score,
+            })
+            .collect();
+        Ok(Json(json!({ "results": results })))
+    })
+    .await
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VecBaseConfig;
+    use serde_json::Value as Json;
+
+    /// Start a server on an ephemeral local port; returns its base URL.
+    fn spawn_server(config: VecBaseConfig) -> String {
+        let db: SharedDb = Arc::new(VecBase::new(config));
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                tx.send(listener.local_addr().unwrap()).unwrap();
+                serve(listener, db, std::future::pending()).await.unwrap();
+            });
+        });
+        format!("http://{}", rx.recv().unwrap())
+    }
+
+    /// Status and JSON body of a request, whatever the status.
+    fn call(req: ureq::Request, body: Option<Json>) -> (u16, Json) {
+        let res = match body {
+            Some(body) => req.send_json(body),
+            None => req.call(),
+        };
+        let res = match res {
+            Ok(res) => res,
+            Err(ureq::Error::Status(_, res)) => res,
+            Err(e) => panic!("request failed: {}", e),
+        };
+        (res.status(), res.into_json().unwrap())
+    }
+
+    #[test]
+    fn test_http_api() {
+        let base = spawn_server(VecBaseConfig {
+            dim: 3,
+            metric: "euclidean".into(),
+            max_elements: 4,
+            ..VecBaseConfig::default()
+        });
+        let url = |path: &str| format!("{}{}", base, path);
+
+        assert_eq!(
+            call(ureq::get(&url("/health")), None),
+            (200, serde_json::json!({ "status": "ok" }))
+        );
+
+        let (status, body) = call(
+            ureq::post(&url("/vectors")),
+            Some(
+                serde_json::json!({ "id": "a", "vector": [1.0, 0.0, 0.0], "metadata": { "lang": "en" } }),
+            ),
+        );
+        assert_eq!((status, body["id"].as_str()), (201, Some("a")));
+
+        let (status, body) = call(
+            ureq::post(&url("/vectors/batch")),
+            Some(serde_json::json!({ "items": [
+                { "id": "b", "vector": [0.0, 1.0, 0.0] },
+                { "id": "c", "vector": [0.0, 0.0, 1.0], "metadata": null },
+                { "id": "short", "vector": [1.0] },
+                { "id": "bad-meta", "vector": [1.0, 1.0, 1.0], "metadata": [1, 2] },
+            ] })),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["inserted"], 2);
+        let failed: Vec<&str> = body["failed"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(failed, vec!["bad-meta", "short"]);
+        assert_eq!(call(ureq::get(&url("/count")), None).1["count"], 3);
+
+        let (status, body) = call(
+            ureq::post(&url("/search")),
+            Some(serde_json::json!({ "vector": [0.9, 0.1, 0.0], "top_k": 2 })),
+        );
+        assert_eq!(status, 200);
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["id"], "a");
+        assert_eq!(results[0]["metadata"], serde_json::json!({ "lang": "en" }));
+
+        let (status, body) = call(ureq::get(&url("/vectors/a")), None);
+        assert_eq!(status, 200);
+        assert_eq!(body["vector"], serde_json::json!([1.0, 0.0, 0.0]));
+        assert_eq!(call(ureq::delete(&url("/vectors/a")), None).0, 200);
+        assert_eq!(call(ureq::get(&url("/vectors/a")), None).0, 404);
+        let (status, body) = call(ureq::delete(&url("/vectors/a")), None);
+        assert_eq!(status, 404);
+        assert!(body["error"].as_str().unwrap().contains("not found"));
+    }
+
+    #[test]
+    fn test_http_search_uses_configured_nprobe() {
+        let base = spawn_server(VecBaseConfig {
+            dim: 2,
+            metric: "euclidean".into(),
+            index_type: "ivf".into(),
+            index_params: [("nlist".into(), 2.into()), ("nprobe".into(), 1.into())].into(),
+            ..VecBaseConfig::default()
+        });
+        let url = |path: &str| format!("{}{}", base, path);
+        // Two lists: `a` split above and below the x axis (centroid at the
+        // origin) and a tight `b` cluster far off at (200, 0).
+        let items: Vec<Json> = (0..50)
+            .flat_map(|i| {
+                let y = if i % 2 == 0 { 20.0 } else { -20.0 };
+                [
+                    serde_json::json!({ "id": format!("a{}", i), "vector": [i as f32 * 0.01, y] }),
+                    serde_json::json!({ "id": format!("b{}", i), "vector": [200.0, i as f32 * 0.01] }),
+                ]
+            })
+            .collect();
+        let (status, _) = call(ureq::post(&url("/vectors/batch")), Some(serde_json::json!({ "items": items })));
+        assert_eq!(status, 200);
+
+        let top = |body: Json| {
+            let (status, body) = call(ureq::post(&url("/search")), Some(body));
+            assert_eq!(status, 200);
+            body["results"][0]["id"].as_str().unwrap()[..1].to_string()
+        };
+        // The query is nearer b's centroid but nearer an `a` vector, which
+        // only a search probing both lists finds.
+        let query = serde_json::json!({ "vector": [101.0, 40.0], "top_k": 1 });
+        assert_eq!(top(query.clone()), "b");
+        let mut wide = query.clone();
+        wide["ef"] = 2.into();
+        assert_eq!(top(wide), "a");
+        let mut wide = query;
+        wide["nprobe"] = 2.into();
+        assert_eq!(top(wide), "a");
+    }
+
+    #[test]
+    fn test_http_error_statuses() {
+        let base = spawn_server(VecBaseConfig {
+            dim: 2,
+            max_elements: 1,
+            ..VecBaseConfig::default()
+        });
+        let url = |path: &str| format!("{}{}", base, path);
+        let insert = |id: &str, vector: Json| {
+            call(
+                ureq::post(&url("/vectors")),
+                Some(serde_json::json!({ "id": id, "vector": vector })),
+            )
+        };
+
+        assert_eq!(insert("x", serde_json::json!([1.0])).0, 400);
+        assert_eq!(insert("x", serde_json::json!([1.0, 0.0])).0, 201);
+        let (status, body) = insert("y", serde_json::json!([0.0, 1.0]));
+        assert_eq!(status, 507);
+        assert!(body["error"].as_str().unwrap().contains("Capacity"));
+
+        let (status, body) = call(
+            ureq::post(&url("/search")),
+            Some(serde_json::json!({ "vector": [1.0, 2.0, 3.0] })),
+        );
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().contains("Dimension"));
+
+        let malformed = ureq::post(&url("/vectors"))
+            .set("Content-Type", "application/json")
+            .send_string("{ not json");
+        match malformed {
+            Err(ureq::Error::Status(code, res)) => {
+                assert_eq!(code, 400);
+                assert!(res.into_json::<Json>().unwrap()["error"].is_string());
+            }
+            other => panic!("expected 400, got {:?}", other.map(|r| r.status())),
+        }
+        let missing_field = call(
+            ureq::post(&url("/vectors")),
+            Some(serde_json::json!({ "id": "z" })),
+        );
+        assert_eq!(missing_field.0, 422);
+    }
+
+    #[test]
+    fn test_http_clamps_oversized_top_k() {
+        let base = spawn_server(VecBaseConfig {
+            dim: 2,
+            max_elements: 100,
+            index_type: "flat".into(),
+            ..VecBaseConfig::default()
+        });
+        let url = |path: &str| format!("{}{}", base, path);
+        for i in 0..10 {
+            let body = serde_json::json!({ "id": format!("v{}", i), "vector": [1.0, i as f32] });
+            assert_eq!(call(ureq::post(&url("/vectors")), Some(body)).0, 201);
+        }
+
+        let search = |top_k: u64| {
+            call(
+                ureq::post(&url("/search")),
+                Some(serde_json::json!({ "vector": [1.0, 0.0], "top_k": top_k })),
+            )
+        };
+        for top_k in [4_000_000_000, u64::MAX, 100] {
+            let (status, body) = search(top_k);
+            assert_eq!(status, 200);
+            assert_eq!(body["results"].as_array().unwrap().len(), 10);
+        }
+    }
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(
+            status_for(&VecBaseError::NotFound { id: "x".into() }),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status_for(&VecBaseError::CollectionExists { name: "x".into() }),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status_for(&VecBaseError::StorageError("disk".into())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -0,0 +1,219 @@
+// VecBase — storage.rs
+// On-disk snapshot format and flat-file bincode helpers.
+// Author: d65v <https://github.com/d65v>
+//
+// Snapshot layout (all integers little-endian):
+//
+//   offset  size  field
+//   0       8     magic    b"VECBASE\0"
+//   8       4     version  u32
+//   12      8     length   u64, payload bytes
+//   20      4     crc32    u32, CRC-32 of the payload
+//   24      N     payload  bincode-encoded body
+//
+// Snapshots are written to a temporary sibling file and renamed into place,
+// so a crash mid-save never leaves a half-written snapshot behind.
+//
+// Data too large to inline (memory-mapped vectors) lives in files beside
+// the snapshot and is referenced by name relative to its directory. While a
+// snapshot is encoded or decoded, `snapshot_path` tells such stores which
+// snapshot that is.
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Result, VecBaseError};
+
+/// Magic bytes at the start of every snapshot file.
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"VECBASE\0";
+
+/// Current snapshot format version. Bump on any incompatible payload change;
+/// `test_snapshot_format_fingerprint` in lib.rs fails until you do.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Default snapshot file name inside `storage_path`.
+pub const SNAPSHOT_FILE: &str = "vecbase.snap";
+
+const HEADER_LEN: usize = 8 + 4 + 8 + 4;
+
+thread_local! {
+    /// Snapshot being encoded or decoded on this thread, see [`in_snapshot`].
+    static SNAPSHOT_PATH: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `path` as the snapshot being encoded or decoded on this
+/// thread, so [`snapshot_path`] can locate files stored beside it.
+pub(crate) fn in_snapshot<T>(path: &Path, f: impl FnOnce() -> T) -> T {
+    let outer = SNAPSHOT_PATH.with(|p| p.replace(Some(path.to_path_buf())));
+    let out = f();
+    SNAPSHOT_PATH.with(|p| *p.borrow_mut() = outer);
+    out
+}
+
+/// Snapshot being encoded or decoded on this thread, if any.
+pub(crate) fn snapshot_path() -> Option<PathBuf> {
+    SNAPSHOT_PATH.with(|p| p.borrow().clone())
+}
+
+/// Directory holding `path`; `.` for a bare file name.
+pub(crate) fn dir_of(path: &Path) -> &Path {
+    path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."))
+}
+
+/// Map any displayable error into `VecBaseError::StorageError` with context.
+pub(crate) fn storage_err(context: &str, e: impl std::fmt::Display) -> VecBaseError {
+    VecBaseError::StorageError(format!("{}: {}", context, e))
+}
+
+/// Serialize `payload` into a versioned, checksummed snapshot at `path`.
+///
+/// # Errors
+/// Returns `VecBaseError::StorageError` on any I/O or encoding failure.
+pub fn write_snapshot<T: Serialize>(path: &Path, payload: &T) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| storage_err("create snapshot dir", e))?;
+    }
+
+    let body = in_snapshot(path, || bincode::serialize(payload)).map_err(|e| storage_err("encode snapshot", e))?;
+
+    let mut buf = Vec::with_capacity(HEADER_LEN + body.len());
+    buf.extend_from_slice(&SNAPSHOT_MAGIC);
+    buf.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    buf.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    buf.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    buf.extend_from_slice(&body);
+
+    let tmp = tmp_path(path);
+    {
+        let mut f = File::create(&tmp).map_err(|e| storage_err("create snapshot", e))?;
+        f.write_all(&buf).map_err(|e| storage_err("write snapshot", e))?;
+        f.sync_all().map_err(|e| storage_err("sync snapshot", e))?;
+    }
+    fs::rename(&tmp, path).map_err(|e| storage_err("rename snapshot", e))?;
+    Ok(())
+}
+
+/// Read and verify a snapshot written by [`write_snapshot`].
+///
+/// # Errors
+/// Returns `VecBaseError::StorageError` if the file is missing, truncated,
+/// has the wrong magic or version, fails its checksum, or does not decode.
+pub fn read_snapshot<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut buf))
+        .map_err(|e| storage_err(&format!("read snapshot {}", path.display()), e))?;
+
+    if buf.len() < HEADER_LEN {
+        return Err(VecBaseError::StorageError(format!(
+            "snapshot {} truncated: {} bytes",
+            path.display(),
+            buf.len()
+        )));
+    }
+    if buf[0..8] != SNAPSHOT_MAGIC {
+        return Err(VecBaseError::StorageError(format!(
+            "{} is not a VecBase snapshot (bad magic)",
+            path.display()
+        )));
+    }
+
+    let version = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+    if version != SNAPSHOT_VERSION {
+        return Err(VecBaseError::StorageError(format!(
+            "unsupported snapshot version {} (expected {})",
+            version, SNAPSHOT_VERSION
+        )));
+    }
+
+    let len = u64::from_le_bytes(buf[12..20].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(buf[20..24].try_into().unwrap());
+    let body = &buf[HEADER_LEN..];
+    if body.len() != len {
+        return Err(VecBaseError::StorageError(format!(
+            "snapshot length mismatch: header says {} bytes, found {}",
+            len,
+            body.len()
+        )));
+    }
+    if crc32fast::hash(body) != crc {
+        return Err(VecBaseError::StorageError(
+            "snapshot checksum mismatch".to_string(),
+        ));
+    }
+
+    in_snapshot(path, || bincode::deserialize(body)).map_err(|e| storage_err("decode snapshot", e))
+}
+
+pub(crate) fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Fresh, empty scratch directory under the system temp dir.
+    pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vecbase-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let path = scratch_dir("snap-roundtrip").join("x.snap");
+        let payload = vec![(String::from("a"), vec![1.0f32, 2.0])];
+        write_snapshot(&path, &payload).unwrap();
+        let back: Vec<(String, Vec<f32>)> = read_snapshot(&path).unwrap();
+        assert_eq!(back, payload);
+        assert!(!tmp_path(&path).exists());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_bad_magic() {
+        let path = scratch_dir("snap-magic").join("x.snap");
+        write_snapshot(&path, &42u32).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[0] = b'X';
+        fs::write(&path, bytes).unwrap();
+        let err = read_snapshot::<u32>(&path).unwrap_err();
+        assert!(matches!(err, VecBaseError::StorageError(_)));
+    }
+
+    #[test]
+    fn test_snapshot_rejects_future_version() {
+        let path = scratch_dir("snap-version").join("x.snap");
+        write_snapshot(&path, &42u32).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[8..12].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        let err = read_snapshot::<u32>(&path).unwrap_err();
+        assert!(err.to_string().contains("version"));
+    }
+
+    #[test]
+    fn test_snapshot_detects_corruption() {
+        let path = scratch_dir("snap-crc").join("x.snap");
+        write_snapshot(&path, &vec![7u64; 16]).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        let err = read_snapshot::<Vec<u64>>(&path).unwrap_err();
+        assert!(err.to_string().contains("checksum"));
+
+        fs::write(&path, &bytes[..HEADER_LEN + 3]).unwrap();
+        assert!(read_snapshot::<Vec<u64>>(&path).is_err());
+    }
+}
@@ -0,0 +1,644 @@
+// VecBase — vectors.rs
+// Fixed-stride vector storage addressed by dense row number.
+// Author: d65v <https://github.com/d65v>
+//
+// Two backends share one API:
+//   - Memory: fixed-size chunks of ~1 MiB, each a contiguous run of rows, so
+//             growth never copies old rows or leaves more than one chunk of
+//             spare capacity (a single doubling Vec can waste up to half)
+//   - Mmap:   one contiguous [r·dim, (r+1)·dim) layout in a memory-mapped
+//             file, so collections larger than RAM are paged in on demand
+//
+// Mmap file layout (native-endian, i.e. little-endian on supported targets):
+//
+//   offset  size  field
+//   0       4     magic   b"VBV\0"
+//   4       4     dim     u32
+//   8       8     rows    u64, rows in use
+//   16      …     rows × dim × f32
+//
+// The file grows by doubling; bytes past `rows` are spare capacity.
+//
+// A snapshot refers to an mmap store by file name, relative to the
+// snapshot's directory. The collection's own checkpoint (`vecbase.snap` next
+// to `vectors.vbv`) refers to the live file: rows appended since are dropped
+// on restore, and rows freed since are not overwritten until the next
+// checkpoint (see `FreeRows`), so the file still holds the checkpoint's
+// vectors. Any other snapshot gets a point-in-time copy, `<snapshot>.vbv`.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use memmap2::MmapMut;
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::storage::{self, storage_err};
+use crate::{Result, VecBaseError};
+
+/// Magic bytes at the start of every mmap vector file.
+pub const VECTORS_MAGIC: [u8; 4] = *b"VBV\0";
+
+/// Default vector file name inside `storage_path`.
+pub const VECTORS_FILE: &str = "vectors.vbv";
+
+const HEADER_LEN: usize = 16;
+const MIN_CAPACITY: usize = 1024;
+
+/// Target size of one in-memory chunk.
+pub(crate) const CHUNK_BYTES: usize = 1 << 20;
+
+// ── Vector Store ──────────────────────────────────────────────────────────────
+
+/// Row-addressed vector storage used by the ANN index.
+#[derive(Serialize, Deserialize)]
+pub enum VectorStore {
+    Memory(MemoryVectors),
+    Mmap(MmapVectors),
+}
+
+impl VectorStore {
+    /// Empty in-memory store.
+    pub fn memory(dim: usize) -> Self {
+        VectorStore::Memory(MemoryVectors {
+            dim,
+            rows: 0,
+            chunks: Vec::new(),
+        })
+    }
+
+    /// Open (or create) a memory-mapped store at `path`.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on I/O failure or if an existing
+    /// file has a different dimension or is not a vector file.
+    pub fn mmap(path: impl AsRef<Path>, dim: usize) -> Result<Self> {
+        MmapVectors::open(path.as_ref(), dim).map(VectorStore::Mmap)
+    }
+
+    pub fn dim(&self) -> usize {
+        match self {
+            VectorStore::Memory(m) => m.dim,
+            VectorStore::Mmap(m) => m.dim,
+        }
+    }
+
+    /// Number of rows allocated (live or free).
+    pub fn len(&self) -> usize {
+        match self {
+            VectorStore::Memory(m) => m.rows,
+            VectorStore::Mmap(m) => m.rows,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether rows live in a memory-mapped file.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, VectorStore::Mmap(_))
+    }
+
+    /// Borrow row `row`.
+    ///
+    /// # Panics
+    /// Panics if `row` is out of range.
+    #[inline]
+    pub fn get(&self, row: u32) -> &[f32] {
+        match self {
+            VectorStore::Memory(m) => m.row(row as usize),
+            VectorStore::Mmap(m) => m.row(row as usize),
+        }
+    }
+
+    /// Append a vector and return its row number.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the backing file cannot grow.
+    pub fn push(&mut self, v: &[f32]) -> Result<u32> {
+        debug_assert_eq!(v.len(), self.dim());
+        let row = self.len() as u32;
+        match self {
+            VectorStore::Memory(m) => m.push(v),
+            VectorStore::Mmap(m) => m.push(v)?,
+        }
+        Ok(row)
+    }
+
+    /// Make room for `additional` more rows, so that many pushes cannot fail.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the backing file cannot grow.
+    pub fn reserve(&mut self, additional: usize) -> Result<()> {
+        match self {
+            VectorStore::Memory(_) => Ok(()),
+            VectorStore::Mmap(m) => m.reserve(additional),
+        }
+    }
+
+    /// Overwrite an existing row in place.
+    ///
+    /// # Panics
+    /// Panics if `row` is out of range.
+    pub fn set(&mut self, row: u32, v: &[f32]) {
+        debug_assert_eq!(v.len(), self.dim());
+        match self {
+            VectorStore::Memory(m) => m.row_mut(row as usize).copy_from_slice(v),
+            VectorStore::Mmap(m) => m.row_mut(row as usize).copy_from_slice(v),
+        }
+    }
+
+    /// Flush pending writes to disk (no-op for the memory backend).
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` if the flush fails.
+    pub fn flush(&self) -> Result<()> {
+        match self {
+            VectorStore::Memory(_) => Ok(()),
+            VectorStore::Mmap(m) => m.map.flush().map_err(|e| storage_err("flush vector file", e)),
+        }
+    }
+
+    /// Approximate bytes of heap memory held by this store. Memory-mapped
+    /// rows are not counted — they are paged in and out by the OS.
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            VectorStore::Memory(m) => {
+                let floats: usize = m.chunks.iter().map(Vec::capacity).sum();
+                floats * std::mem::size_of::<f32>()
+                    + m.chunks.capacity() * std::mem::size_of::<Vec<f32>>()
+            }
+            VectorStore::Mmap(_) => 0,
+        }
+    }
+}
+
+// ── Free Rows ─────────────────────────────────────────────────────────────────
+
+/// Rows an index has freed, handed back out by later inserts.
+///
+/// Rows freed from a memory-mapped store may still hold vectors the last
+/// checkpoint refers to, so they are held back until
+/// [`release`](Self::release) is called after the next checkpoint; inserts
+/// append new rows meanwhile. Snapshots store every free row as one list.
+#[derive(Default)]
+pub(crate) struct FreeRows {
+    ready: Vec<u32>,
+    held: Vec<u32>,
+}
+
+impl FreeRows {
+    /// Every row already in `vectors`, for an index created over it.
+    pub(crate) fn all(vectors: &VectorStore) -> Self {
+        Self {
+            ready: (0..vectors.len() as u32).rev().collect(),
+            held: Vec::new(),
+        }
+    }
+
+    /// Free `row` of `vectors`.
+    pub(crate) fn push(&mut self, row: u32, vectors: &VectorStore) {
+        if vectors.is_mapped() {
+            self.held.push(row);
+        } else {
+            self.ready.push(row);
+        }
+    }
+
+    /// A row that may be overwritten, if any.
+    pub(crate) fn pop(&mut self) -> Option<u32> {
+        self.ready.pop()
+    }
+
+    /// Make held rows reusable, once a checkpoint no longer refers to them.
+    pub(crate) fn release(&mut self) {
+        self.ready.append(&mut self.held);
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        (self.ready.capacity() + self.held.capacity()) * 4
+    }
+}
+
+impl Serialize for FreeRows {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.collect_seq(self.ready.iter().chain(&self.held))
+    }
+}
+
+impl<'de> Deserialize<'de> for FreeRows {
+    /// Nothing in a restored snapshot refers to a free row, so all are ready.
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        Ok(Self {
+            ready: Vec::deserialize(d)?,
+            held: Vec::new(),
+        })
+    }
+}
+
+// ── Memory Backend ────────────────────────────────────────────────────────────
+
+/// In-memory arena of fixed-stride rows, split into equally sized chunks.
+#[derive(Serialize, Deserialize)]
+pub struct MemoryVectors {
+    dim: usize,
+    rows: usize,
+    chunks: Vec<Vec<f32>>,
+}
+
+impl MemoryVectors {
+    fn rows_per_chunk(&self) -> usize {
+        (CHUNK_BYTES / (self.dim * 4).max(1)).max(1)
+    }
+
+    fn locate(&self, row: usize) -> (usize, usize) {
+        assert!(row < self.rows, "vector row {} out of range ({})", row, self.rows);
+        let per = self.rows_per_chunk();
+        (row / per, (row % per) * self.dim)
+    }
+
+    fn row(&self, row: usize) -> &[f32] {
+        let (chunk, start) = self.locate(row);
+        &self.chunks[chunk][start..start + self.dim]
+    }
+
+    fn row_mut(&mut self, row: usize) -> &mut [f32] {
+        let (chunk, start) = self.locate(row);
+        let dim = self.dim;
+        &mut self.chunks[chunk][start..start + dim]
+    }
+
+    fn push(&mut self, v: &[f32]) {
+        let per = self.rows_per_chunk();
+        if self.rows.is_multiple_of(per) {
+            self.chunks.push(Vec::with_capacity(per * self.dim));
+        }
+        self.chunks.last_mut().unwrap().extend_from_slice(v);
+        self.rows += 1;
+    }
+}
+
+// ── Mmap Backend ──────────────────────────────────────────────────────────────
+
+/// Fixed-stride rows in a memory-mapped file.
+pub struct MmapVectors {
+    path: PathBuf,
+    file: File,
+    map: MmapMut,
+    dim: usize,
+    rows: usize,
+    capacity: usize,
+}
+
+impl MmapVectors {
+    fn open(path: &Path, dim: usize) -> Result<Self> {
+        if dim == 0 {
+            return Err(VecBaseError::ConfigError("vector dim must be > 0".to_string()));
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| storage_err("create vector dir", e))?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| storage_err(&format!("open vector file {}", path.display()), e))?;
+
+        let existing = file
+            .metadata()
+            .map_err(|e| storage_err("stat vector file", e))?
+            .len() as usize;
+        let stride = dim * 4;
+
+        let fresh = existing == 0;
+        let capacity = if fresh {
+            file.set_len((HEADER_LEN + MIN_CAPACITY * stride) as u64)
+                .map_err(|e| storage_err("size vector file", e))?;
+            MIN_CAPACITY
+        } else {
+            if existing < HEADER_LEN {
+                return Err(VecBaseError::StorageError(format!(
+                    "vector file {} truncated",
+                    path.display()
+                )));
+            }
+            (existing - HEADER_LEN) / stride
+        };
+
+        // SAFETY: the file is opened read-write by this process only; VecBase
+        // never hands out slices that outlive a remap (see `grow`).
+        let map = unsafe { MmapMut::map_mut(&file) }.map_err(|e| storage_err("mmap vector file", e))?;
+
+        let mut store = Self {
+            path: path.to_path_buf(),
+            file,
+            map,
+            dim,
+            rows: 0,
+            capacity,
+        };
+
+        if fresh {
+            store.map[0..4].copy_from_slice(&VECTORS_MAGIC);
+            store.map[4..8].copy_from_slice(&(dim as u32).to_ne_bytes());
+            store.write_rows();
+        } else {
+            if store.map[0..4] != VECTORS_MAGIC {
+                return Err(VecBaseError::StorageError(format!(
+                    "{} is not a VecBase vector file (bad magic)",
+                    path.display()
+                )));
+            }
+            let file_dim = u32::from_ne_bytes(store.map[4..8].try_into().unwrap()) as usize;
+            if file_dim != dim {
+                return Err(VecBaseError::StorageError(format!(
+                    "vector file {} has dim {}, expected {}",
+                    path.display(),
+                    file_dim,
+                    dim
+                )));
+            }
+            let file_rows = u64::from_ne_bytes(store.map[8..16].try_into().unwrap()) as usize;
+            if file_rows > store.capacity {
+                return Err(VecBaseError::StorageError(format!(
+                    "vector file {} header claims {} rows but holds {}",
+                    path.display(),
+                    file_rows,
+                    store.capacity
+                )));
+            }
+            store.rows = file_rows;
+        }
+        Ok(store)
+    }
+
+    fn row(&self, row: usize) -> &[f32] {
+        assert!(row < self.rows, "vector row {} out of range ({})", row, self.rows);
+        let start = HEADER_LEN + row * self.dim * 4;
+        let bytes = &self.map[start..start + self.dim * 4];
+        // SAFETY: the map is page-aligned and HEADER_LEN is a multiple of 4,
+        // so every row starts on an f32 boundary; the length is exact.
+        unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, self.dim) }
+    }
+
+    fn row_mut(&mut self, row: usize) -> &mut [f32] {
+        assert!(row < self.rows, "vector row {} out of range ({})", row, self.rows);
+        let start = HEADER_LEN + row * self.dim * 4;
+        let bytes = &mut self.map[start..start + self.dim * 4];
+        // SAFETY: as in `row`, plus `&mut self` guarantees exclusivity.
+        unsafe { std::slice::from_raw_parts_mut(bytes.as_mut_ptr() as *mut f32, self.dim) }
+    }
+
+    fn push(&mut self, v: &[f32]) -> Result<()> {
+        self.reserve(1)?;
+        self.rows += 1;
+        self.row_mut(self.rows - 1).copy_from_slice(v);
+        self.write_rows();
+        Ok(())
+    }
+
+    fn reserve(&mut self, additional: usize) -> Result<()> {
+        let needed = self.rows + additional;
+        if needed > self.capacity {
+            self.grow(needed)?;
+        }
+        Ok(())
+    }
+
+    /// Double the file (or more, to hold `min` rows) and remap it.
+    fn grow(&mut self, min: usize) -> Result<()> {
+        let capacity = (self.capacity * 2).max(MIN_CAPACITY).max(min);
+        self.map.flush().map_err(|e| storage_err("flush vector file", e))?;
+        self.file
+            .set_len((HEADER_LEN + capacity * self.dim * 4) as u64)
+            .map_err(|e| storage_err("grow vector file", e))?;
+        // SAFETY: see `open`; `&mut self` means no row slices are alive.
+        self.map = unsafe { MmapMut::map_mut(&self.file) }
+            .map_err(|e| storage_err("remap vector file", e))?;
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Logically drop every row at or past `rows`.
+    fn truncate(&mut self, rows: usize) {
+        self.rows = self.rows.min(rows);
+        self.write_rows();
+    }
+
+    fn write_rows(&mut self) {
+        let rows = self.rows as u64;
+        self.map[8..16].copy_from_slice(&rows.to_ne_bytes());
+    }
+
+    /// Name, relative to `snapshot`'s directory, of a file holding this
+    /// store's rows as they are now: the live file for the collection's own
+    /// checkpoint, otherwise a fresh copy named after the snapshot.
+    fn snapshot_file(&self, snapshot: &Path) -> Result<String> {
+        let live = self.path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let dir = storage::dir_of(snapshot);
+        let same_dir = match (dir.canonicalize(), storage::dir_of(&self.path).canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        };
+        if same_dir && snapshot.file_name() == Some(storage::SNAPSHOT_FILE.as_ref()) {
+            return Ok(live);
+        }
+
+        let mut name = snapshot.file_name().unwrap_or_default().to_os_string();
+        name.push(".vbv");
+        let copy = dir.join(&name);
+        let tmp = storage::tmp_path(&copy);
+        let bytes = &self.map[..HEADER_LEN + self.rows * self.dim * 4];
+        File::create(&tmp)
+            .and_then(|mut f| {
+                f.write_all(bytes)?;
+                f.sync_all()
+            })
+            .map_err(|e| storage_err(&format!("copy vector file to {}", copy.display()), e))?;
+        std::fs::rename(&tmp, &copy).map_err(|e| storage_err("rename vector file copy", e))?;
+        Ok(name.to_string_lossy().into_owned())
+    }
+}
+
+/// What a snapshot records for an mmap store: the vector file's name,
+/// relative to the snapshot's directory.
+#[derive(Serialize, Deserialize)]
+struct MmapDescriptor {
+    file: String,
+    dim: usize,
+    rows: usize,
+}
+
+impl Serialize for MmapVectors {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        let snapshot = storage::snapshot_path()
+            .ok_or_else(|| S::Error::custom("memory-mapped vectors are only written inside a snapshot"))?;
+        MmapDescriptor {
+            file: self.snapshot_file(&snapshot).map_err(S::Error::custom)?,
+            dim: self.dim,
+            rows: self.rows,
+        }
+        .serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for MmapVectors {
+    /// Re-open the referenced file. Rows appended after the snapshot was
+    /// taken are dropped; WAL replay re-creates them.
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let desc = MmapDescriptor::deserialize(d)?;
+        let snapshot = storage::snapshot_path()
+            .ok_or_else(|| D::Error::custom("memory-mapped vectors are only read inside a snapshot"))?;
+        let path = storage::dir_of(&snapshot).join(&desc.file);
+        let mut store = MmapVectors::open(&path, desc.dim).map_err(D::Error::custom)?;
+        if store.rows < desc.rows {
+            return Err(D::Error::custom(format!(
+                "vector file {} holds {} rows, snapshot expects {}",
+                path.display(),
+                store.rows,
+                desc.rows
+            )));
+        }
+        store.truncate(desc.rows);
+        Ok(store)
+    }
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::scratch_dir;
+
+    fn row(i: usize, dim: usize) -> Vec<f32> {
+        (0..dim).map(|j| (i * dim + j) as f32).collect()
+    }
+
+    #[test]
+    fn test_memory_store_spans_chunks() {
+        let dim = 1000; // 262 rows per chunk
+        let mut s = VectorStore::memory(dim);
+        for i in 0..600 {
+            s.push(&row(i, dim)).unwrap();
+        }
+        s.set(262, &[0.25; 1000]);
+        assert_eq!(s.get(261), row(261, dim).as_slice());
+        assert_eq!(s.get(262), &[0.25; 1000][..]);
+        assert_eq!(s.get(599), row(599, dim).as_slice());
+        assert!(s.heap_bytes() <= 3 * CHUNK_BYTES + 1024);
+    }
+
+    #[test]
+    fn test_memory_store_push_get_set() {
+        let mut s = VectorStore::memory(3);
+        assert_eq!(s.push(&[1.0, 2.0, 3.0]).unwrap(), 0);
+        assert_eq!(s.push(&[4.0, 5.0, 6.0]).unwrap(), 1);
+        s.set(0, &[7.0, 8.0, 9.0]);
+        assert_eq!(s.get(0), &[7.0, 8.0, 9.0]);
+        assert_eq!(s.get(1), &[4.0, 5.0, 6.0]);
+        assert_eq!(s.len(), 2);
+    }
+
+    #[test]
+    fn test_mmap_store_grows_and_reopens() {
+        let path = scratch_dir("mmap-grow").join(VECTORS_FILE);
+        let dim = 5;
+        {
+            let mut s = VectorStore::mmap(&path, dim).unwrap();
+            for i in 0..MIN_CAPACITY * 2 + 3 {
+                assert_eq!(s.push(&row(i, dim)).unwrap() as usize, i);
+            }
+            s.set(7, &[0.5; 5]);
+            s.flush().unwrap();
+        }
+
+        let s = VectorStore::mmap(&path, dim).unwrap();
+        assert_eq!(s.len(), MIN_CAPACITY * 2 + 3);
+        assert_eq!(s.get(7), &[0.5; 5]);
+        assert_eq!(s.get(2000), row(2000, dim).as_slice());
+    }
+
+    #[test]
+    fn test_mmap_store_reserve_grows_once() {
+        let path = scratch_dir("mmap-reserve").join(VECTORS_FILE);
+        let mut s = VectorStore::mmap(&path, 4).unwrap();
+        s.reserve(MIN_CAPACITY * 5).unwrap();
+        let len = std::fs::metadata(&path).unwrap().len();
+        assert_eq!(len as usize, HEADER_LEN + MIN_CAPACITY * 5 * 16);
+        for i in 0..MIN_CAPACITY * 5 {
+            s.push(&row(i, 4)).unwrap();
+        }
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[test]
+    fn test_mmap_store_rejects_wrong_dim() {
+        let path = scratch_dir("mmap-dim").join(VECTORS_FILE);
+        VectorStore::mmap(&path, 4).unwrap().push(&[1.0; 4]).unwrap();
+        assert!(matches!(
+            VectorStore::mmap(&path, 8),
+            Err(VecBaseError::StorageError(_))
+        ));
+    }
+
+    #[test]
+    fn test_mmap_store_serde_reattaches_file() {
+        let dir = scratch_dir("mmap-serde");
+        let checkpoint = dir.join(storage::SNAPSHOT_FILE);
+        let mut s = VectorStore::mmap(dir.join(VECTORS_FILE), 2).unwrap();
+        s.push(&[1.0, 2.0]).unwrap();
+        let bytes = storage::in_snapshot(&checkpoint, || bincode::serialize(&s)).unwrap();
+        assert!(std::fs::read_dir(&dir).unwrap().count() == 1, "checkpoint copied the live file");
+        // Rows written after the snapshot are dropped on reattach.
+        s.push(&[3.0, 4.0]).unwrap();
+        s.flush().unwrap();
+        drop(s);
+
+        let back: VectorStore = storage::in_snapshot(&checkpoint, || bincode::deserialize(&bytes)).unwrap();
+        assert_eq!(back.len(), 1);
+        assert_eq!(back.get(0), &[1.0, 2.0]);
+        assert!(bincode::serialize(&back).is_err());
+    }
+
+    #[test]
+    fn test_mmap_store_serde_copies_for_other_snapshots() {
+        let dir = scratch_dir("mmap-serde-copy");
+        let mut s = VectorStore::mmap(dir.join(VECTORS_FILE), 2).unwrap();
+        s.push(&[1.0, 2.0]).unwrap();
+        let backup = dir.join("backups").join("b.snap");
+        std::fs::create_dir_all(backup.parent().unwrap()).unwrap();
+        let bytes = storage::in_snapshot(&backup, || bincode::serialize(&s)).unwrap();
+        s.set(0, &[9.0, 9.0]);
+        s.flush().unwrap();
+
+        // The backup keeps its own rows and is found relative to its snapshot.
+        let moved = scratch_dir("mmap-serde-moved");
+        std::fs::rename(dir.join("backups").join("b.snap.vbv"), moved.join("b.snap.vbv")).unwrap();
+        let back: VectorStore =
+            storage::in_snapshot(&moved.join("b.snap"), || bincode::deserialize(&bytes)).unwrap();
+        assert_eq!(back.get(0), &[1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_free_rows_hold_mapped_rows_until_released() {
+        let mut memory = FreeRows::default();
+        memory.push(3, &VectorStore::memory(2));
+        assert_eq!(memory.pop(), Some(3));
+
+        let mapped = VectorStore::mmap(scratch_dir("free-rows").join(VECTORS_FILE), 2).unwrap();
+        let mut free = FreeRows::default();
+        free.push(5, &mapped);
+        assert_eq!(free.pop(), None);
+        let bytes = bincode::serialize(&free).unwrap();
+        free.release();
+        assert_eq!(free.pop(), Some(5));
+        let mut back: FreeRows = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(back.pop(), Some(5));
+    }
+}
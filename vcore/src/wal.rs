@@ -0,0 +1,283 @@
+// VecBase — wal.rs
+// Write-ahead log for crash-safe inserts and deletes.
+// Author: d65v <https://github.com/d65v>
+//
+// Every mutating operation is appended (and fsync'd) here before it is
+// applied in memory. On open the log is replayed on top of the last snapshot;
+// `VecBase::checkpoint` writes a fresh snapshot and truncates the log.
+//
+// Frame layout (all integers little-endian):
+//
+//   offset  size  field
+//   0       4     length   u32, payload bytes
+//   4       4     crc32    u32, CRC-32 of the payload
+//   8       N     payload  bincode-encoded `WalOp`
+//
+// A crash can leave a partially written last frame. Replay stops at the first
+// frame that is short or fails its checksum and truncates the file there, so
+// later appends never land behind garbage.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::Metadata;
+use crate::storage::storage_err;
+use crate::Result;
+
+/// Default WAL file name inside `storage_path`.
+pub const WAL_FILE: &str = "vecbase.wal";
+
+pub(crate) const FRAME_HEADER_LEN: usize = 4 + 4;
+
+/// A single logged mutation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WalOp {
+    Insert {
+        id: String,
+        vector: Vec<f32>,
+        metadata: Option<Metadata>,
+    },
+    Delete {
+        id: String,
+    },
+}
+
+/// Append-only handle to a WAL file.
+pub struct Wal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Wal {
+    /// Open (or create) the log at `path` and return every intact operation
+    /// in it, in append order. A torn or corrupt tail is truncated away.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on I/O failure.
+    pub fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<WalOp>)> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| storage_err(&format!("open wal {}", path.display()), e))?;
+
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)
+            .map_err(|e| storage_err("read wal", e))?;
+
+        let (frames, valid_len) = decode_frames::<WalOp>(&buf);
+        let ops: Vec<WalOp> = frames.into_iter().map(|(_, op)| op).collect();
+        if valid_len < buf.len() {
+            log::warn!(
+                "wal {}: discarding {} bytes of torn/corrupt tail after {} ops",
+                path.display(),
+                buf.len() - valid_len,
+                ops.len()
+            );
+            file.set_len(valid_len as u64)
+                .and_then(|_| file.sync_all())
+                .map_err(|e| storage_err("truncate wal", e))?;
+        }
+        file.seek(SeekFrom::End(0))
+            .map_err(|e| storage_err("seek wal", e))?;
+
+        Ok((Self { path, file }, ops))
+    }
+
+    /// Durably append one operation. Returns only after the frame is fsync'd.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on encoding or I/O failure.
+    pub fn append(&mut self, op: &WalOp) -> Result<()> {
+        let frame = encode_frame(op)?;
+        self.file
+            .write_all(&frame)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| storage_err("append wal", e))
+    }
+
+    /// Durably append `ops` in order with a single fsync.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on encoding or I/O failure.
+    pub fn append_all(&mut self, ops: &[WalOp]) -> Result<()> {
+        let mut frames = Vec::new();
+        for op in ops {
+            frames.extend_from_slice(&encode_frame(op)?);
+        }
+        self.file
+            .write_all(&frames)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| storage_err("append wal", e))
+    }
+
+    /// Drop every logged operation, e.g. after a successful checkpoint.
+    ///
+    /// # Errors
+    /// Returns `VecBaseError::StorageError` on I/O failure.
+    pub fn truncate(&mut self) -> Result<()> {
+        self.file
+            .set_len(0)
+            .and_then(|_| self.file.seek(SeekFrom::Start(0)))
+            .and_then(|_| self.file.sync_all())
+            .map_err(|e| storage_err("truncate wal", e))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Encode `value` as one length-prefixed, checksummed frame.
+///
+/// # Errors
+/// Returns `VecBaseError::StorageError` if `value` cannot be encoded.
+pub(crate) fn encode_frame<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let payload = bincode::serialize(value).map_err(|e| storage_err("encode frame", e))?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Decode consecutive frames from `buf`. Returns each intact value with the
+/// offset its frame starts at, and the byte length they occupy; anything
+/// past that is a torn or corrupt tail.
+pub(crate) fn decode_frames<T: DeserializeOwned>(buf: &[u8]) -> (Vec<(usize, T)>, usize) {
+    let mut values = Vec::new();
+    let mut pos = 0usize;
+
+    while buf.len() - pos >= FRAME_HEADER_LEN {
+        let len = u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(buf[pos + 4..pos + 8].try_into().unwrap());
+        let start = pos + FRAME_HEADER_LEN;
+        let end = match start.checked_add(len) {
+            Some(end) if end <= buf.len() => end,
+            _ => break,
+        };
+        let payload = &buf[start..end];
+        if crc32fast::hash(payload) != crc {
+            break;
+        }
+        match bincode::deserialize::<T>(payload) {
+            Ok(value) => values.push((pos, value)),
+            Err(_) => break,
+        }
+        pos = end;
+    }
+
+    (values, pos)
+}
+
+// ── Tests ─────────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::scratch_dir;
+
+    fn sample_ops() -> Vec<WalOp> {
+        vec![
+            WalOp::Insert { id: "a".into(), vector: vec![1.0, 2.0], metadata: None },
+            WalOp::Insert { id: "b".into(), vector: vec![3.0, 4.0], metadata: Some(crate::metadata! { "k" => "m" }) },
+            WalOp::Delete { id: "a".into() },
+        ]
+    }
+
+    fn write_ops(path: &Path) -> u64 {
+        let (mut wal, replayed) = Wal::open(path).unwrap();
+        assert!(replayed.is_empty());
+        for op in sample_ops() {
+            wal.append(&op).unwrap();
+        }
+        std::fs::metadata(path).unwrap().len()
+    }
+
+    #[test]
+    fn test_wal_replay_in_order() {
+        let path = scratch_dir("wal-replay").join(WAL_FILE);
+        write_ops(&path);
+        let (_, ops) = Wal::open(&path).unwrap();
+        assert_eq!(ops, sample_ops());
+    }
+
+    #[test]
+    fn test_wal_append_all() {
+        let path = scratch_dir("wal-append-all").join(WAL_FILE);
+        let (mut wal, _) = Wal::open(&path).unwrap();
+        wal.append_all(&sample_ops()).unwrap();
+        drop(wal);
+        let (_, ops) = Wal::open(&path).unwrap();
+        assert_eq!(ops, sample_ops());
+    }
+
+    #[test]
+    fn test_wal_truncated_tail() {
+        let path = scratch_dir("wal-torn").join(WAL_FILE);
+        let full = write_ops(&path);
+
+        // Chop the last frame in half, as if the process died mid-write.
+        let f = OpenOptions::new().write(true).open(&path).unwrap();
+        f.set_len(full - 3).unwrap();
+        drop(f);
+
+        let (mut wal, ops) = Wal::open(&path).unwrap();
+        assert_eq!(ops, sample_ops()[..2].to_vec());
+        let valid = std::fs::metadata(&path).unwrap().len();
+        assert!(valid < full - 3, "torn tail should be truncated away");
+
+        // Appends after recovery are readable again.
+        wal.append(&WalOp::Delete { id: "b".into() }).unwrap();
+        drop(wal);
+        let (_, ops) = Wal::open(&path).unwrap();
+        assert_eq!(ops.len(), 3);
+        assert_eq!(ops[2], WalOp::Delete { id: "b".into() });
+    }
+
+    #[test]
+    fn test_wal_corrupted_tail() {
+        let path = scratch_dir("wal-corrupt").join(WAL_FILE);
+        let full = write_ops(&path);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let (_, ops) = Wal::open(&path).unwrap();
+        assert_eq!(ops, sample_ops()[..2].to_vec());
+        assert!(std::fs::metadata(&path).unwrap().len() < full);
+    }
+
+    #[test]
+    fn test_wal_garbage_length_header() {
+        let path = scratch_dir("wal-garbage").join(WAL_FILE);
+        write_ops(&path);
+        let mut f = OpenOptions::new().append(true).open(&path).unwrap();
+        f.write_all(&u32::MAX.to_le_bytes()).unwrap();
+        f.write_all(&[0u8; 6]).unwrap();
+        drop(f);
+
+        let (_, ops) = Wal::open(&path).unwrap();
+        assert_eq!(ops, sample_ops());
+    }
+
+    #[test]
+    fn test_wal_truncate() {
+        let path = scratch_dir("wal-reset").join(WAL_FILE);
+        write_ops(&path);
+        let (mut wal, _) = Wal::open(&path).unwrap();
+        wal.truncate().unwrap();
+        drop(wal);
+        let (_, ops) = Wal::open(&path).unwrap();
+        assert!(ops.is_empty());
+    }
+}